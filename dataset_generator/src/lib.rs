@@ -0,0 +1,245 @@
+//! What dataset_generator does with a replay once it is out of its archive:
+//! writing it as slpz, indexing where it came from, and streaming its rows into a database.
+
+use slp_action_db::parse_old_game;
+
+/// Where slpz files are written, and where `build` in slp_action_db reads them from.
+pub const OUTPUT_DIR: &str = "output/";
+/// Kept beside `OUTPUT_DIR` rather than in it, so everything in the output directory is a replay.
+pub const INDEX_PATH: &str = "index.tsv";
+
+/// Maps output slpz filenames back to the archive and entry they were extracted from.
+/// Stored as `output file \t archive \t entry` lines, in `INDEX_PATH` when run.
+pub struct OutputIndex {
+    pub entries: std::collections::BTreeMap<String, (String, String)>,
+}
+
+impl OutputIndex {
+    pub fn load(path: &std::path::Path) -> OutputIndex {
+        let mut entries = std::collections::BTreeMap::new();
+
+        // a missing index just means nothing has been written yet
+        if let Ok(text) = std::fs::read_to_string(path) {
+            for line in text.lines() {
+                let mut fields = line.split('\t');
+                let (Some(output), Some(archive), Some(entry), None)
+                    = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                    eprintln!("ERROR: invalid index line '{}'", line);
+                    continue;
+                };
+                entries.insert(output.to_string(), (archive.to_string(), entry.to_string()));
+            }
+        }
+
+        OutputIndex { entries }
+    }
+
+    pub fn insert(&mut self, output: String, archive: &str, entry: &str) {
+        self.entries.insert(output, (archive.to_string(), entry.to_string()));
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut text = String::with_capacity(self.entries.len() * 64);
+        for (output, (archive, entry)) in self.entries.iter() {
+            text.push_str(output);
+            text.push('\t');
+            text.push_str(archive);
+            text.push('\t');
+            text.push_str(entry);
+            text.push('\n');
+        }
+        std::fs::write(path, text)
+    }
+}
+
+/// Deterministic output name for an archive entry.
+/// Hashing the archive name along with the full entry path keeps entries with the same filename
+/// from different archives or subdirectories from overwriting each other.
+pub fn output_filename(archive: &str, entry: &str) -> String {
+    let mut hash = slp_action_db::FNV_OFFSET;
+    hash = slp_action_db::fnv1a(hash, archive.as_bytes());
+    hash = slp_action_db::fnv1a(hash, &[0]);
+    hash = slp_action_db::fnv1a(hash, entry.as_bytes());
+
+    format!("{:016x}.slpz", hash)
+}
+
+fn check_game_start(buf: &[u8]) -> bool {
+    let Ok(header) = parse_old_game::parse_raw_header(&buf) else { return false };
+    let Ok(sizes) = parse_old_game::event_sizes(&buf, header.event_sizes_offset) else { return false; };
+    let game_start_size = sizes.event_sizes[0x36 as usize] as usize + 1;
+    if buf.len() < sizes.game_start_offset + game_start_size { return false; }
+
+    let mut count = 0;
+    for i in 0..4 {
+        let typ = buf[sizes.game_start_offset + 0x66 + 0x24*i];
+        if typ == 3 { continue; }
+        if typ != 0 { return false; }
+        count += 1;
+
+        let char_ext = buf[sizes.game_start_offset + 0x65 + 0x24*i];
+        if char_ext != 2 { return false; }
+    }
+    if count != 2 { return false; }
+
+    true
+}
+
+/// Streams rows straight into a `.actions` file, skipping the intermediate slpz files.
+/// The source manifest and the header's counts and checksum are written by `finish`.
+pub struct Pipeline {
+    out: std::io::BufWriter<std::fs::File>,
+    options: slp_action_db::build::BuildOptions,
+    rows: Vec<slp_action_db::Row>,
+    row_buf: Vec<u8>,
+    row_count: usize,
+    sources: Vec<String>,
+    /// `fnv1a` of everything written after the header so far.
+    checksum: u64,
+}
+
+impl Pipeline {
+    pub fn create(path: &std::path::Path, options: slp_action_db::build::BuildOptions) -> std::io::Result<Pipeline> {
+        use std::io::Write;
+
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        // counts and checksum are filled in by finish
+        out.write_all(&Pipeline::header(0, 0, 0))?;
+
+        Ok(Pipeline {
+            out,
+            options,
+            rows: Vec::with_capacity(1024),
+            row_buf: Vec::with_capacity(1024 * slp_action_db::Row::WRITTEN_SIZE),
+            row_count: 0,
+            sources: Vec::new(),
+            checksum: slp_action_db::FNV_OFFSET,
+        })
+    }
+
+    fn header(row_count: usize, source_count: usize, checksum: u64) -> Vec<u8> {
+        let mut header_buf = Vec::with_capacity(slp_action_db::Header::WRITTEN_SIZE);
+        slp_action_db::write_header(&mut header_buf, &slp_action_db::Header {
+            version: slp_action_db::VERSION,
+            player_character: slp_parser::Character::Fox,
+            opponent_character: slp_parser::Character::Fox,
+            row_count: row_count as u32,
+            source_count: source_count as u32,
+            layout: slp_action_db::Layout::Rows,
+            // rows are streamed out as they are built, so they can't be sorted
+            state_index: Vec::new(),
+            checksum,
+        });
+        header_buf
+    }
+
+    /// `source` is recorded in the manifest as where the rows came from.
+    pub fn push_game(&mut self, slp: &[u8], source: String) {
+        use std::io::Write;
+
+        let game = match parse_old_game::parse_old_file_full(slp) {
+            Ok(g) => g,
+            Err(e) => {
+                eprintln!("ERROR: could not parse: {}", e);
+                return;
+            }
+        };
+
+        self.rows.clear();
+        let source_idx = self.sources.len() as u32;
+        if let Err(reason) = slp_action_db::build::push_game_rows(&mut self.rows, &game, source_idx, &self.options) {
+            eprintln!("  skipped: {:?}", reason);
+            return;
+        }
+
+        self.row_buf.clear();
+        for row in self.rows.iter() {
+            slp_action_db::write_row(&mut self.row_buf, row);
+        }
+
+        match self.out.write_all(&self.row_buf) {
+            Ok(_) => {
+                self.row_count += self.rows.len();
+                self.checksum = slp_action_db::fnv1a(self.checksum, &self.row_buf);
+                self.sources.push(source);
+                println!("  wrote {} rows", self.rows.len());
+            }
+            Err(e) => eprintln!("ERROR: could not write rows: {}", e),
+        }
+    }
+
+    /// Writes the source manifest after the rows, then goes back to fill in the header.
+    pub fn finish(mut self) -> std::io::Result<usize> {
+        use std::io::{Seek, Write};
+
+        let mut source_buf = Vec::new();
+        for source in self.sources.iter() {
+            slp_action_db::write_source(&mut source_buf, source);
+        }
+        self.out.write_all(&source_buf)?;
+        self.checksum = slp_action_db::fnv1a(self.checksum, &source_buf);
+
+        let mut file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.seek(std::io::SeekFrom::Start(0))?;
+        file.write_all(&Pipeline::header(self.row_count, self.sources.len(), self.checksum))?;
+
+        Ok(self.row_count)
+    }
+}
+
+fn write_slpz(compressor: &mut slpz::Compressor, buf: &[u8], output_dir: &std::path::Path, output_filename: &str) -> bool {
+    match slpz::compress(compressor, buf) {
+        Err(e) => {
+            eprintln!("ERROR: could not compress: {}", e);
+            false
+        },
+        Ok(slpz_bytes) => {
+            let output_path = output_dir.join(output_filename);
+
+            match std::fs::write(output_path, slpz_bytes) {
+                Ok(_) => {
+                    println!("  wrote slpz to {}", output_filename);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("ERROR: could not write file: {}", e);
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Writes a replay taken from `archive` as slpz into `output_dir` if there is a compressor,
+/// and its rows to the database if there is a pipeline. Replays other than Fox dittos are left out.
+pub fn handle_game(
+    compressor: Option<&mut slpz::Compressor>,
+    pipeline: Option<&mut Pipeline>,
+    index: &mut OutputIndex,
+    output_dir: &std::path::Path,
+    buf: &[u8],
+    archive: &str,
+    filename: &str,
+) {
+    // parse game info 
+
+    if !check_game_start(buf) { return; }
+
+    // no skip - fox ditto
+
+    // rows point at the slpz file if there is one, otherwise at the archive entry
+    let mut source = format!("{}/{}", archive, filename);
+
+    if let Some(compressor) = compressor {
+        let output = output_filename(archive, filename);
+        if write_slpz(compressor, buf, output_dir, &output) {
+            source = output_dir.join(&output).to_string_lossy().into_owned();
+            index.insert(output, archive, filename);
+        }
+    }
+
+    if let Some(pipeline) = pipeline {
+        pipeline.push_game(buf, source);
+    }
+}
@@ -0,0 +1,65 @@
+use dataset_generator::*;
+
+#[test]
+fn output_filenames_are_deterministic() {
+    let name = output_filename("2020-07.zip", "Game_20200712T183145.slp");
+    assert_eq!(name, output_filename("2020-07.zip", "Game_20200712T183145.slp"));
+    assert!(name.ends_with(".slpz"));
+    assert_eq!(name.len(), 16 + ".slpz".len());
+    assert!(name[..16].chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[test]
+fn output_filenames_dont_collide() {
+    let entries = [
+        ("2020-07.zip", "Game_1.slp"),
+        ("2020-08.zip", "Game_1.slp"),
+        ("2020-07.zip", "netplay/Game_1.slp"),
+        ("2020-07.zip", "local/Game_1.slp"),
+        ("2020-07.zip", "local/netplay/Game_1.slp"),
+        ("2020-07.zip", "Game_2.slp"),
+        // the separator keeps the split between archive and entry from being moved
+        ("2020-07.zip/a", "Game_1.slp"),
+        ("2020-07.zip", "a/Game_1.slp"),
+        ("ab", "c"),
+        ("a", "bc"),
+        ("", "abc"),
+    ];
+    let mut names = entries.iter().map(|(archive, entry)| output_filename(archive, entry)).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), entries.len());
+}
+
+#[test]
+fn index_round_trips() {
+    let dir = std::env::temp_dir().join(format!("dataset_generator_index_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.tsv");
+
+    // nothing written yet
+    assert!(OutputIndex::load(&path).entries.is_empty());
+
+    let mut index = OutputIndex::load(&path);
+    for (archive, entry) in [("2020-07.zip", "Game_1.slp"), ("2020-08.zip", "Game_1.slp"), ("2020-07.zip", "nested dir/Game_1.slp")] {
+        index.insert(output_filename(archive, entry), archive, entry);
+    }
+    index.save(&path).unwrap();
+
+    let loaded = OutputIndex::load(&path);
+    assert_eq!(loaded.entries, index.entries);
+    let (archive, entry) = &loaded.entries[&output_filename("2020-07.zip", "nested dir/Game_1.slp")];
+    assert_eq!((archive.as_str(), entry.as_str()), ("2020-07.zip", "nested dir/Game_1.slp"));
+
+    // later runs add to what is there, and bad lines are skipped
+    let mut text = std::fs::read_to_string(&path).unwrap();
+    text.push_str("not an index line\n");
+    std::fs::write(&path, text).unwrap();
+    let mut index = OutputIndex::load(&path);
+    assert_eq!(index.entries, loaded.entries);
+    index.insert(output_filename("2020-09.zip", "Game_1.slp"), "2020-09.zip", "Game_1.slp");
+    index.save(&path).unwrap();
+    assert_eq!(OutputIndex::load(&path).entries.len(), 4);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
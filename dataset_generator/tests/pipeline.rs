@@ -0,0 +1,125 @@
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use dataset_generator::*;
+use slp_action_db::{build, parse_old_game, Database, Header, Layout};
+use common::finished_replay;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_generator_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The database built the usual way, from parsed games and `write_database`.
+fn expected(games: &[&[u8]], sources: &[String], options: &build::BuildOptions) -> Vec<u8> {
+    let mut rows = Vec::new();
+    for (i, slp) in games.iter().enumerate() {
+        let game = parse_old_game::parse_old_file_full(slp).unwrap();
+        build::push_game_rows(&mut rows, &game, i as u32, options).unwrap();
+    }
+    let db = Database {
+        header: Header {
+            version: slp_action_db::VERSION,
+            player_character: slp_parser::Character::Fox,
+            opponent_character: slp_parser::Character::Fox,
+            row_count: 0,
+            source_count: 0,
+            layout: Layout::Rows,
+            state_index: Vec::new(),
+            checksum: 0,
+        },
+        rows,
+        sources: sources.to_vec(),
+    };
+    let mut buf = Vec::new();
+    slp_action_db::write_database(&mut buf, &db);
+    buf
+}
+
+#[test]
+fn pipeline_matches_write_database() {
+    let dir = temp_dir("pipeline");
+    let output_dir = dir.join("output");
+    let actions = dir.join("out.actions");
+    let (short, _) = finished_replay(600);
+    let (long, _) = finished_replay(1200);
+
+    // a game that isn't a Fox ditto is left out
+    let mut not_fox = short.clone();
+    not_fox[32 + 5 + 0x60] = 9;
+
+    let mut index = OutputIndex::load(&dir.join("index.tsv"));
+    let mut pipeline = Pipeline::create(&actions, build::BuildOptions::default()).unwrap();
+    handle_game(None, Some(&mut pipeline), &mut index, &output_dir, &short, "2020-07.zip", "Game_1.slp");
+    handle_game(None, Some(&mut pipeline), &mut index, &output_dir, &not_fox, "2020-07.zip", "Game_2.slp");
+    handle_game(None, Some(&mut pipeline), &mut index, &output_dir, &long, "2020-07.zip", "nested/Game_3.slp");
+    let row_count = pipeline.finish().unwrap();
+
+    let written = std::fs::read(&actions).unwrap();
+    let sources = ["2020-07.zip/Game_1.slp".to_string(), "2020-07.zip/nested/Game_3.slp".to_string()];
+    assert_eq!(written, expected(&[&short, &long], &sources, &build::BuildOptions::default()));
+
+    let db = slp_action_db::read_database(&written).unwrap();
+    assert_eq!(db.rows.len(), row_count);
+    assert!(row_count > 0);
+    assert_eq!(db.sources, sources);
+
+    // without --slpz, nothing else is written
+    assert!(!output_dir.exists());
+    assert!(index.entries.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pipeline_passes_on_build_options() {
+    let dir = temp_dir("pipeline_options");
+    let actions = dir.join("out.actions");
+    let (slp, _) = finished_replay(600);
+    let options = build::BuildOptions { winner_weight: 2.0, loser_weight: 0.5, ..Default::default() };
+
+    let mut index = OutputIndex::load(&dir.join("index.tsv"));
+    let mut pipeline = Pipeline::create(&actions, options.clone()).unwrap();
+    handle_game(None, Some(&mut pipeline), &mut index, &dir, &slp, "a.zip", "Game_1.slp");
+    pipeline.finish().unwrap();
+
+    let sources = ["a.zip/Game_1.slp".to_string()];
+    assert_eq!(std::fs::read(&actions).unwrap(), expected(&[&slp], &sources, &options));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn slpz_with_the_pipeline() {
+    let dir = temp_dir("pipeline_slpz");
+    let output_dir = dir.join("output");
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let actions = dir.join("out.actions");
+    let (slp, _) = finished_replay(600);
+
+    let mut compressor = slpz::Compressor::new(3).unwrap();
+    let mut index = OutputIndex::load(&dir.join("index.tsv"));
+    let mut pipeline = Pipeline::create(&actions, build::BuildOptions::default()).unwrap();
+    handle_game(Some(&mut compressor), Some(&mut pipeline), &mut index, &output_dir, &slp, "a.zip", "Game_1.slp");
+    pipeline.finish().unwrap();
+
+    // rows point at the slpz file, which the index maps back to the archive entry
+    let output = output_filename("a.zip", "Game_1.slp");
+    let source = output_dir.join(&output).to_string_lossy().into_owned();
+    assert_eq!(std::fs::read(&actions).unwrap(), expected(&[&slp], &[source], &build::BuildOptions::default()));
+    assert_eq!(index.entries[&output], ("a.zip".to_string(), "Game_1.slp".to_string()));
+
+    let slpz_bytes = std::fs::read(output_dir.join(&output)).unwrap();
+    let decompressed = slpz::decompress(&mut slpz::Decompressor::new().unwrap(), &slpz_bytes).unwrap();
+    assert_eq!(decompressed, slp);
+
+    // slpz files alone, without a database
+    let mut index = OutputIndex::load(&dir.join("index.tsv"));
+    handle_game(Some(&mut compressor), None, &mut index, &output_dir, &slp, "b.zip", "Game_1.slp");
+    assert!(output_dir.join(output_filename("b.zip", "Game_1.slp")).exists());
+    assert_eq!(index.entries.len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+//! Nearest neighbours and per-action summaries, for when a fixed search distance finds too few or too many rows.
+
+use crate::*;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Neighbour {
+    /// Euclidean distance over both situations' positions.
+    pub distance: f32,
+    pub row: Row,
+}
+
+/// The `k` rows with the query's start state pair closest to its positions, nearest first.
+/// Rows at the same distance keep their order.
+pub fn nearest(rows: &[Row], query: &SearchQuery, k: usize) -> Vec<Neighbour> {
+    let mut neighbours = rows.iter()
+        .filter(|row| row.player_response.start_state == query.player_response.start_state)
+        .filter(|row| row.opponent_initiation.start_state == query.opponent_initiation.start_state)
+        .map(|row| Neighbour { distance: distance(query, row), row: row.clone() })
+        .filter(|n| !n.distance.is_nan())
+        .collect::<Vec<_>>();
+
+    neighbours.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    neighbours.truncate(k);
+    neighbours
+}
+
+fn distance(query: &SearchQuery, row: &Row) -> f32 {
+    let pl_x_dist = query.player_response.pos_x - row.player_response.pos_x;
+    let pl_y_dist = query.player_response.pos_y - row.player_response.pos_y;
+    let op_x_dist = query.opponent_initiation.pos_x - row.opponent_initiation.pos_x;
+    let op_y_dist = query.opponent_initiation.pos_y - row.opponent_initiation.pos_y;
+    (pl_x_dist*pl_x_dist + pl_y_dist*pl_y_dist + op_x_dist*op_x_dist + op_y_dist*op_y_dist).sqrt()
+}
+
+impl Database {
+    /// Same results as `nearest` over every row, only looking at the query's start state pair if the rows are sorted.
+    pub fn nearest(&self, query: &SearchQuery, k: usize) -> Vec<Neighbour> {
+        let range = self.header.state_range(query.opponent_initiation.start_state, query.player_response.start_state)
+            .unwrap_or(0..self.rows.len());
+        nearest(self.rows.get(range).unwrap_or(&[]), query, k)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActionSummary {
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::action"))]
+    pub action: slp_parser::HighLevelAction,
+    pub count: usize,
+    /// Sum of `Row::weight`.
+    pub total_weight: f32,
+    /// Mean score weighted by `Row::weight`, or the plain mean if every weight is zero.
+    pub mean_score: f32,
+}
+
+/// Groups rows by the player's response, most weight first.
+pub fn summarize_actions(rows: &[Row]) -> Vec<ActionSummary> {
+    struct Sums {
+        action: slp_parser::HighLevelAction,
+        count: usize,
+        weight: f64,
+        weighted_score: f64,
+        score: f64,
+    }
+
+    let mut sums: Vec<Sums> = Vec::new();
+    for row in rows {
+        let action = row.player_response.action_taken;
+        let i = match sums.iter().position(|s| s.action == action) {
+            Some(i) => i,
+            None => {
+                sums.push(Sums { action, count: 0, weight: 0.0, weighted_score: 0.0, score: 0.0 });
+                sums.len() - 1
+            }
+        };
+
+        let s = &mut sums[i];
+        s.count += 1;
+        s.weight += row.weight as f64;
+        s.weighted_score += row.weight as f64 * row.score as f64;
+        s.score += row.score as f64;
+    }
+
+    let mut summaries = sums.into_iter()
+        .map(|s| ActionSummary {
+            action: s.action,
+            count: s.count,
+            total_weight: s.weight as f32,
+            mean_score: if s.weight != 0.0 { s.weighted_score / s.weight } else { s.score / s.count as f64 } as f32,
+        })
+        .collect::<Vec<_>>();
+
+    summaries.sort_by(|a, b| b.total_weight.total_cmp(&a.total_weight).then(a.action.as_u16().cmp(&b.action.as_u16())));
+    summaries
+}
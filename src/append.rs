@@ -0,0 +1,122 @@
+//! Adding rows from new replays to an existing database, without rebuilding it from every replay.
+
+use crate::*;
+
+#[derive(Debug)]
+pub enum AppendError {
+    Io(std::io::Error),
+    Parse(slp_parser::SlpError),
+    Skipped(build::SkipReason),
+}
+
+impl std::fmt::Display for AppendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppendError::Io(e) => write!(f, "could not read replay: {}", e),
+            AppendError::Parse(e) => write!(f, "could not parse replay: {}", e),
+            AppendError::Skipped(reason) => write!(f, "skipped: {:?}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AppendReport {
+    pub added_replays: usize,
+    pub added_rows: usize,
+    /// Replays already in the source manifest, which were left out.
+    pub already_present: Vec<String>,
+    pub failed: Vec<(String, AppendError)>,
+}
+
+/// Adds a game's rows, recording `source` in the manifest.
+/// Nothing is added if the game is skipped. Returns the number of rows added.
+///
+/// Doesn't re-sort a sorted database, see `append_replays`.
+pub fn append_game(
+    db: &mut Database,
+    game: &parse_old_game::FullGame,
+    source: String,
+    options: &build::BuildOptions,
+) -> Result<usize, build::SkipReason> {
+    let before = db.rows.len();
+    let source_idx = db.sources.len() as u32;
+    build::push_game_rows(&mut db.rows, game, source_idx, options)?;
+
+    db.sources.push(source);
+    db.header.row_count = db.rows.len() as u32;
+    db.header.source_count = db.sources.len() as u32;
+    Ok(db.rows.len() - before)
+}
+
+/// Reads, parses and adds each `.slp` or `.slpz` replay whose path isn't already in the source manifest.
+///
+/// A sorted database is re-sorted afterwards so its state index stays valid.
+/// The checksum is filled in when the database is written.
+pub fn append_replays(db: &mut Database, paths: &[String], options: &build::BuildOptions) -> AppendReport {
+    let mut report = AppendReport::default();
+    let mut known = db.sources.iter().cloned().collect::<std::collections::HashSet<String>>();
+
+    for path in paths {
+        if known.contains(path) {
+            report.already_present.push(path.clone());
+            continue;
+        }
+
+        let bytes = match std::fs::read(path) {
+            Ok(b) => b,
+            Err(e) => {
+                report.failed.push((path.clone(), AppendError::Io(e)));
+                continue;
+            }
+        };
+
+        let game = if path.ends_with(".slpz") {
+            parse_old_game::parse_old_file_full_slpz(&bytes)
+        } else {
+            parse_old_game::parse_old_file_full(&bytes)
+        };
+        let game = match game {
+            Ok(g) => g,
+            Err(e) => {
+                report.failed.push((path.clone(), AppendError::Parse(e)));
+                continue;
+            }
+        };
+
+        match append_game(db, &game, path.clone(), options) {
+            Ok(rows) => {
+                report.added_replays += 1;
+                report.added_rows += rows;
+                known.insert(path.clone());
+            }
+            Err(reason) => report.failed.push((path.clone(), AppendError::Skipped(reason))),
+        }
+    }
+
+    if !db.header.state_index.is_empty() && report.added_rows != 0 { sort_database(db); }
+    report
+}
+
+/// Appends replays to the database at `path` in place, keeping its layout.
+/// Files in past versions are upgraded to the current version.
+pub fn append_file(
+    path: &std::path::Path,
+    replays: &[String],
+    options: &build::BuildOptions,
+) -> std::io::Result<AppendReport> {
+    let file = std::fs::read(path)?;
+    let mut db = read_database(&file).map_err(std::io::Error::other)?;
+    db.header.version = VERSION;
+
+    let report = append_replays(&mut db, replays, options);
+    if report.added_replays == 0 && read_u32(&file, 0).ok() == Some(VERSION) { return Ok(report); }
+
+    let mut buf = Vec::with_capacity(file.len() + report.added_rows * Row::WRITTEN_SIZE + 1024);
+    write_database(&mut buf, &db);
+
+    // write alongside then rename, so a failed write doesn't lose the original
+    let tmp = path.with_extension("actions.tmp");
+    std::fs::write(&tmp, buf)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(report)
+}
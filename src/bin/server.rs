@@ -0,0 +1,38 @@
+use slp_action_db::*;
+
+const USAGE: &str = "usage: slp_action_server [--addr <host:port>] <db.actions>...
+  serve a JSON HTTP API over the databases, one per matchup, see the `server` module.
+  listens on 127.0.0.1:7878 by default";
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut addr = String::from("127.0.0.1:7878");
+    if args.first().map(|a| a.as_str()) == Some("--addr") && args.len() >= 2 {
+        addr = args.remove(1);
+        args.remove(0);
+    }
+    if args.is_empty() || args.iter().any(|a| a.starts_with("--")) {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    let dbs = args.iter()
+        .map(|path| {
+            let file = std::fs::read(path).unwrap_or_else(|e| fail(format_args!("could not read {}: {}", path, e)));
+            read_database(&file).unwrap_or_else(|e| fail(format_args!("could not read {}: {}", path, e)))
+        })
+        .collect::<Vec<_>>();
+
+    let api = server::Api::new(dbs).unwrap_or_else(|e| fail(e));
+    let http = server::bind(&addr).unwrap_or_else(|e| fail(e));
+    for m in api.matchups.iter() {
+        println!("{}: {} rows from {} sources", m.name, m.db.rows.len(), m.db.sources.len());
+    }
+    println!("listening on http://{}", http.server_addr());
+    server::serve(&http, &api);
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("ERROR: {}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,154 @@
+use crate::*;
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Only append rows where the player with this connect code took `player_response`.
+    pub player: Option<ConnectCode>,
+    /// Skip games that ended with a quit out or no contest.
+    pub skip_quit_outs: bool,
+    /// `Row::weight` for rows where the responding player went on to win the game.
+    pub winner_weight: f32,
+    /// `Row::weight` for rows where the responding player went on to lose the game.
+    pub loser_weight: f32,
+    /// Skip games whose gecko code list is registered as a mod, see `ModRegistry::is_modded`.
+    /// Code lists the registry doesn't know, and replays that predate code lists being recorded, are kept.
+    pub skip_modded: Option<crate::gecko::ModRegistry>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            player: None,
+            skip_quit_outs: false,
+            winner_weight: 1.0,
+            loser_weight: 1.0,
+            skip_modded: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    NotTwoPlayer,
+    QuitOut,
+    Modded,
+}
+
+/// Appends a row for every scored interaction in a two player game, from both players' perspectives.
+/// Games with neither player matching `options.player` append nothing.
+/// `source` is the game's index in `Database::sources`.
+pub fn push_game_rows(
+    rows: &mut Vec<Row>,
+    game: &parse_old_game::FullGame,
+    source: u32,
+    options: &BuildOptions,
+) -> Result<(), SkipReason> {
+    if options.skip_quit_outs && game.game_end.is_some_and(|e| e.is_quit_out()) {
+        return Err(SkipReason::QuitOut);
+    }
+
+    if let (Some(registry), Some(codes)) = (&options.skip_modded, &game.gecko_codes) {
+        if registry.is_modded(codes) { return Err(SkipReason::Modded); }
+    }
+
+    let winner = game.winner();
+    let game = &game.game;
+    let (low, high) = game.info.low_high_ports().ok_or(SkipReason::NotTwoPlayer)?;
+
+    let low_code = game.info.connect_codes[low];
+    let high_code = game.info.connect_codes[high];
+    let want_low = options.player.is_none_or(|c| c == low_code);
+    let want_high = options.player.is_none_or(|c| c == high_code);
+    if !want_low && !want_high { return Ok(()); }
+
+    let weight = |port: usize| match winner {
+        Some(w) if w == port => options.winner_weight,
+        Some(_) => options.loser_weight,
+        None => 1.0,
+    };
+
+    let low_frames = game.frames[low].as_ref().ok_or(SkipReason::NotTwoPlayer)?;
+    let high_frames = game.frames[high].as_ref().ok_or(SkipReason::NotTwoPlayer)?;
+
+    let low_actions = slp_parser::parse_actions(low_frames);
+    let high_actions = slp_parser::parse_actions(high_frames);
+
+    let a = slp_parser::generate_interactions(game.info.stage, &low_actions, &high_actions, low_frames, high_frames);
+    let b = slp_parser::generate_interactions(game.info.stage, &high_actions, &low_actions, high_frames, low_frames);
+
+    rows.reserve(a.len() + b.len());
+
+    // the row's player_response comes from the second player passed to generate_interactions
+    if want_high {
+        for interaction in a {
+            rows.extend(interaction_row(interaction, low_frames, high_frames, high_code, weight(high), source));
+        }
+    }
+
+    if want_low {
+        for interaction in b {
+            rows.extend(interaction_row(interaction, high_frames, low_frames, low_code, weight(low), source));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `None` for interactions without a score.
+pub(crate) fn interaction_row(
+    interaction: slp_parser::InteractionRef<'_>,
+    pl_frames: &[slp_parser::Frame],
+    op_frames: &[slp_parser::Frame],
+    player_code: ConnectCode,
+    weight: f32,
+    source: u32,
+) -> Option<Row> {
+    let (s1, s2) = interaction.score?;
+
+    let pl_pos = pl_frames[interaction.player_response.frame_start].position;
+    let op_pos = op_frames[interaction.opponent_initiation.frame_start].position;
+
+    Some(Row {
+        opponent_initiation: Situation {
+            start_state: interaction.player_response.start_state,
+            action_taken: interaction.player_response.action_taken,
+            pos_x: pl_pos.x,
+            pos_y: pl_pos.y,
+        },
+        player_response: Situation {
+            start_state: interaction.opponent_initiation.start_state,
+            action_taken: interaction.opponent_initiation.action_taken,
+            pos_x: op_pos.x,
+            pos_y: op_pos.y,
+        },
+        score: (s1.percent + s1.kill + s1.pos_x + s1.pos_y)
+            - (s2.percent + s2.kill + s2.pos_x + s2.pos_y),
+        weight,
+        player_code,
+        source,
+        frame: interaction.player_response.frame_start as i32 + parse_old_game::FIRST_FRAME,
+    })
+}
+
+/// The query that finds rows like the one `interaction_row` makes, whether or not the interaction has a score.
+pub(crate) fn interaction_query(
+    interaction: slp_parser::InteractionRef<'_>,
+    pl_frames: &[slp_parser::Frame],
+    op_frames: &[slp_parser::Frame],
+) -> SearchQuery {
+    let pl_pos = pl_frames[interaction.player_response.frame_start].position;
+    let op_pos = op_frames[interaction.opponent_initiation.frame_start].position;
+
+    SearchQuery {
+        opponent_initiation: SearchSituation {
+            start_state: interaction.player_response.start_state,
+            pos_x: pl_pos.x,
+            pos_y: pl_pos.y,
+        },
+        player_response: SearchSituation {
+            start_state: interaction.opponent_initiation.start_state,
+            pos_x: op_pos.x,
+            pos_y: op_pos.y,
+        },
+    }
+}
@@ -0,0 +1,219 @@
+//! Block compressed columnar layout for `.actions` files, chosen with `Layout::Columnar`.
+//!
+//! After the header comes the block count, then an index entry for each block:
+//! its row count, compressed size, and the distinct (opponent start state, player start state) pairs in it.
+//! Then the zstd compressed blocks, then the source manifest.
+//!
+//! Inside a block each field is stored as its own column, in the order
+//! opponent state, opponent action, player state, player action,
+//! opponent x, opponent y, player x, player y, score, weight, player code, source, frame.
+//! Similar values next to each other compress far better than whole rows.
+
+use crate::*;
+
+pub const BLOCK_ROWS: usize = 4096;
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct BlockIndex {
+    pub row_count: u32,
+    /// Byte offset of the compressed block in the file.
+    pub offset: usize,
+    pub compressed_size: u32,
+    /// `state_pair` of every start state pair in the block, sorted.
+    pub state_pairs: Vec<u32>,
+}
+
+impl BlockIndex {
+    pub fn contains(&self, opponent_state: slp_parser::BroadState, player_state: slp_parser::BroadState) -> bool {
+        self.state_pairs.binary_search(&state_pair(opponent_state, player_state)).is_ok()
+    }
+}
+
+/// Writes the database in the columnar layout, whatever its header's layout.
+pub fn write_columnar(buf: &mut Vec<u8>, db: &Database) {
+    let header_start = buf.len();
+    write_header(buf, &Header {
+        row_count: db.rows.len() as u32,
+        source_count: db.sources.len() as u32,
+        layout: Layout::Columnar,
+        state_index: Vec::new(),
+        ..db.header.clone()
+    });
+
+    let blocks = db.rows.chunks(BLOCK_ROWS)
+        .map(|rows| {
+            let mut state_pairs = rows.iter()
+                .map(|r| state_pair(r.opponent_initiation.start_state, r.player_response.start_state))
+                .collect::<Vec<u32>>();
+            state_pairs.sort_unstable();
+            state_pairs.dedup();
+
+            let columns = write_columns(rows);
+            // compressing an in-memory buffer can't fail
+            let compressed = zstd::bulk::compress(&columns, COMPRESSION_LEVEL).unwrap();
+            (rows.len(), state_pairs, compressed)
+        })
+        .collect::<Vec<_>>();
+
+    let body_start = buf.len();
+    buf.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    for (row_count, state_pairs, compressed) in blocks.iter() {
+        buf.extend_from_slice(&(*row_count as u32).to_le_bytes());
+        buf.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(state_pairs.len() as u32).to_le_bytes());
+        for pair in state_pairs { buf.extend_from_slice(&pair.to_le_bytes()); }
+    }
+
+    for (_, _, compressed) in blocks.iter() { buf.extend_from_slice(compressed); }
+    for source in db.sources.iter() { write_source(buf, source); }
+    write_checksum(buf, header_start, body_start);
+}
+
+fn write_columns(rows: &[Row]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(rows.len() * Row::WRITTEN_SIZE);
+    for r in rows { buf.extend_from_slice(&r.opponent_initiation.start_state.as_u16().to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.opponent_initiation.action_taken.as_u16().to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.player_response.start_state.as_u16().to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.player_response.action_taken.as_u16().to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.opponent_initiation.pos_x.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.opponent_initiation.pos_y.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.player_response.pos_x.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.player_response.pos_y.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.score.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.weight.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.player_code); }
+    for r in rows { buf.extend_from_slice(&r.source.to_le_bytes()); }
+    for r in rows { buf.extend_from_slice(&r.frame.to_le_bytes()); }
+    buf
+}
+
+/// A columnar file with its block index read, and blocks decompressed on demand.
+/// The checksum is only verified by `read_columnar`.
+pub struct ColumnarFile<'a> {
+    pub header: Header,
+    pub blocks: Vec<BlockIndex>,
+    file: &'a [u8],
+    sources_offset: usize,
+}
+
+impl<'a> ColumnarFile<'a> {
+    pub fn parse(file: &'a [u8]) -> Result<ColumnarFile<'a>, DBError> {
+        let header = read_header(file)?;
+        if header.version > VERSION { return Err(DBError::new(DBErrorKind::VersionTooNew(header.version), 0)); }
+        if header.version < VERSION { return Err(DBError::new(DBErrorKind::VersionTooOld(header.version), 0)); }
+        if header.layout != Layout::Columnar { return Err(DBError::new(DBErrorKind::BadLayout(0), 6)); }
+
+        let mut cursor = header.written_size();
+        let block_count = read_u32(file, cursor)?;
+        cursor += 4;
+
+        let mut blocks = Vec::with_capacity((block_count as usize).min(file.len() / 12));
+        for _ in 0..block_count {
+            let row_count = read_u32(file, cursor)?;
+            let compressed_size = read_u32(file, cursor + 4)?;
+            let pair_count = read_u32(file, cursor + 8)? as usize;
+            cursor += 12;
+
+            let mut state_pairs = Vec::with_capacity(pair_count.min(file.len() / 4));
+            for _ in 0..pair_count {
+                state_pairs.push(read_u32(file, cursor)?);
+                cursor += 4;
+            }
+
+            blocks.push(BlockIndex { row_count, offset: 0, compressed_size, state_pairs });
+        }
+
+        for block in blocks.iter_mut() {
+            block.offset = cursor;
+            cursor += block.compressed_size as usize;
+        }
+        if cursor > file.len() { return Err(DBError::new(DBErrorKind::Truncated, file.len())); }
+
+        Ok(ColumnarFile { header, blocks, file, sources_offset: cursor })
+    }
+
+    /// Index of the first row of `block` across the whole file.
+    fn first_row(&self, block: usize) -> usize {
+        self.blocks[..block].iter().map(|b| b.row_count as usize).sum()
+    }
+
+    pub fn read_block(&self, block: usize) -> Result<Vec<Row>, DBError> {
+        let index = &self.blocks[block];
+        let n = index.row_count as usize;
+        let first_row = self.first_row(block);
+        let compressed = &self.file[index.offset..][..index.compressed_size as usize];
+        let bad_block = DBError { kind: DBErrorKind::BadBlock, offset: index.offset, row: Some(first_row) };
+
+        let columns = zstd::bulk::decompress(compressed, n * Row::WRITTEN_SIZE).map_err(|_| bad_block.clone())?;
+        if columns.len() != n * Row::WRITTEN_SIZE { return Err(bad_block); }
+
+        let mut rows = Vec::with_capacity(n);
+        for i in 0..n {
+            let row = read_column_row(&columns, n, i, &self.header)
+                .map_err(|e| DBError { offset: index.offset, row: Some(first_row + i), ..e })?;
+            rows.push(row);
+        }
+
+        Ok(rows)
+    }
+
+    pub fn read_sources(&self) -> Result<Vec<String>, DBError> {
+        read_sources(self.file, self.sources_offset, self.header.source_count)
+    }
+
+    /// Same results as `crate::search` over every row,
+    /// without decompressing blocks that have none of the queries' start state pairs.
+    pub fn search(&self, queries: &[SearchQuery]) -> Result<Vec<Vec<Row>>, DBError> {
+        let mut results = vec![Vec::new(); queries.len()];
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let matches = queries.iter().any(|q| {
+                block.contains(q.opponent_initiation.start_state, q.player_response.start_state)
+            });
+            if !matches { continue; }
+
+            let rows = self.read_block(i)?;
+            for (results, block_results) in results.iter_mut().zip(search(&rows, queries)) {
+                results.extend(block_results);
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+fn read_column_row(columns: &[u8], n: usize, i: usize, header: &Header) -> Result<Row, DBError> {
+    Ok(Row {
+        opponent_initiation: Situation {
+            start_state: read_state(columns, 2*i, header.opponent_character)?,
+            action_taken: read_action(columns, 2*n + 2*i, header.opponent_character)?,
+            pos_x: read_f32(columns, 8*n + 4*i)?,
+            pos_y: read_f32(columns, 12*n + 4*i)?,
+        },
+        player_response: Situation {
+            start_state: read_state(columns, 4*n + 2*i, header.player_character)?,
+            action_taken: read_action(columns, 6*n + 2*i, header.player_character)?,
+            pos_x: read_f32(columns, 16*n + 4*i)?,
+            pos_y: read_f32(columns, 20*n + 4*i)?,
+        },
+        score: read_f32(columns, 24*n + 4*i)?,
+        weight: read_f32(columns, 28*n + 4*i)?,
+        player_code: columns[32*n + 10*i..][..10].try_into().unwrap(),
+        source: read_u32(columns, 42*n + 4*i)?,
+        frame: read_u32(columns, 46*n + 4*i)? as i32,
+    })
+}
+
+pub fn read_columnar(file: &[u8]) -> Result<Database, DBError> {
+    let columnar = ColumnarFile::parse(file)?;
+    verify_checksum(file, &columnar.header)?;
+
+    let mut rows = Vec::with_capacity(columnar.header.row_count as usize);
+    for i in 0..columnar.blocks.len() {
+        rows.extend(columnar.read_block(i)?);
+    }
+    let sources = columnar.read_sources()?;
+
+    Ok(Database { header: columnar.header, rows, sources })
+}
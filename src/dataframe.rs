@@ -0,0 +1,299 @@
+//! Arrow IPC and Parquet export and import, for reading databases from pandas, polars and the like.
+//! Enabled with the `arrow` feature.
+//!
+//! Each row becomes a table row. States and actions are stored as their numeric codes,
+//! which are what is read back, and as names for readability.
+//! `source` is null for `Row::NO_SOURCE`, and `source_path` is its path in the manifest.
+//!
+//! The rest of the header and the full source manifest are kept in the schema metadata,
+//! so importing an export gives back the same database.
+
+use crate::*;
+use arrow::array::{Array, ArrayRef, FixedSizeBinaryArray, Float32Array, Int32Array, RecordBatch, StringArray, UInt16Array, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use parquet::errors::ParquetError;
+use std::sync::Arc;
+
+/// Rows per record batch when writing.
+pub const BATCH_ROWS: usize = 64 * 1024;
+
+// schema metadata keys
+const PLAYER_CHARACTER_KEY: &str = "slp_action_db.player_character";
+const OPPONENT_CHARACTER_KEY: &str = "slp_action_db.opponent_character";
+const LAYOUT_KEY: &str = "slp_action_db.layout";
+const SORTED_KEY: &str = "slp_action_db.sorted";
+/// Each source as its length in bytes, a colon, then the path, so that any path, even an empty one, comes back as it was.
+const SOURCES_KEY: &str = "slp_action_db.sources";
+
+#[derive(Debug)]
+pub enum DataFrameError {
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    /// A column is missing or has the wrong type.
+    BadColumn(&'static str),
+    /// A metadata key is missing or can't be parsed.
+    BadMetadata(&'static str),
+    /// A value is out of range, such as a state code the character doesn't have.
+    BadValue { column: &'static str, row: usize },
+}
+
+impl std::fmt::Display for DataFrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataFrameError::Arrow(e) => write!(f, "arrow error: {}", e),
+            DataFrameError::Parquet(e) => write!(f, "parquet error: {}", e),
+            DataFrameError::BadColumn(c) => write!(f, "column '{}' is missing or has the wrong type", c),
+            DataFrameError::BadMetadata(k) => write!(f, "metadata '{}' is missing or invalid", k),
+            DataFrameError::BadValue { column, row } => write!(f, "invalid value in column '{}' in row {}", column, row),
+        }
+    }
+}
+
+impl std::error::Error for DataFrameError {}
+
+impl From<ArrowError> for DataFrameError {
+    fn from(e: ArrowError) -> Self { DataFrameError::Arrow(e) }
+}
+
+impl From<ParquetError> for DataFrameError {
+    fn from(e: ParquetError) -> Self { DataFrameError::Parquet(e) }
+}
+
+pub fn schema(db: &Database) -> Schema {
+    let metadata = [
+        (PLAYER_CHARACTER_KEY, db.header.player_character.to_u8_internal().to_string()),
+        (OPPONENT_CHARACTER_KEY, db.header.opponent_character.to_u8_internal().to_string()),
+        (LAYOUT_KEY, match db.header.layout { Layout::Rows => "rows", Layout::Columnar => "columnar" }.to_string()),
+        (SORTED_KEY, (!db.header.state_index.is_empty()).to_string()),
+        (SOURCES_KEY, join_sources(&db.sources)),
+    ].into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+
+    let situation_fields = |prefix: &str| [
+        Field::new(format!("{}_state", prefix), DataType::UInt16, false),
+        Field::new(format!("{}_state_name", prefix), DataType::Utf8, false),
+        Field::new(format!("{}_action", prefix), DataType::UInt16, false),
+        Field::new(format!("{}_action_name", prefix), DataType::Utf8, false),
+        Field::new(format!("{}_x", prefix), DataType::Float32, false),
+        Field::new(format!("{}_y", prefix), DataType::Float32, false),
+    ];
+
+    let mut fields = Vec::with_capacity(19);
+    fields.extend(situation_fields("opponent"));
+    fields.extend(situation_fields("player"));
+    fields.extend([
+        Field::new("score", DataType::Float32, false),
+        Field::new("weight", DataType::Float32, false),
+        Field::new("player_code", DataType::FixedSizeBinary(10), false),
+        Field::new("source", DataType::UInt32, true),
+        Field::new("source_path", DataType::Utf8, true),
+        Field::new("frame", DataType::Int32, false),
+    ]);
+
+    Schema::new_with_metadata(fields, metadata)
+}
+
+/// One record batch for `rows`, which must be from `db`.
+pub fn record_batch(schema: SchemaRef, db: &Database, rows: &[Row]) -> Result<RecordBatch, ArrowError> {
+    let situation_columns = |situation: fn(&Row) -> &Situation| -> [ArrayRef; 6] {
+        [
+            Arc::new(rows.iter().map(|r| situation(r).start_state.as_u16()).collect::<UInt16Array>()),
+            Arc::new(rows.iter().map(|r| Some(names::state_name(situation(r).start_state))).collect::<StringArray>()),
+            Arc::new(rows.iter().map(|r| situation(r).action_taken.as_u16()).collect::<UInt16Array>()),
+            Arc::new(rows.iter().map(|r| Some(names::action_name(situation(r).action_taken))).collect::<StringArray>()),
+            Arc::new(rows.iter().map(|r| situation(r).pos_x).collect::<Float32Array>()),
+            Arc::new(rows.iter().map(|r| situation(r).pos_y).collect::<Float32Array>()),
+        ]
+    };
+
+    let source = |r: &Row| (r.source != Row::NO_SOURCE).then_some(r.source);
+    let codes = rows.iter().flat_map(|r| r.player_code).collect::<Vec<u8>>();
+
+    let mut columns = Vec::with_capacity(19);
+    columns.extend(situation_columns(|r| &r.opponent_initiation));
+    columns.extend(situation_columns(|r| &r.player_response));
+    columns.extend::<[ArrayRef; 6]>([
+        Arc::new(rows.iter().map(|r| r.score).collect::<Float32Array>()),
+        Arc::new(rows.iter().map(|r| r.weight).collect::<Float32Array>()),
+        Arc::new(FixedSizeBinaryArray::try_new(10, codes.into(), None)?),
+        Arc::new(rows.iter().map(source).collect::<UInt32Array>()),
+        Arc::new(rows.iter().map(|r| db.sources.get(r.source as usize).map(|s| s.as_str())).collect::<StringArray>()),
+        Arc::new(rows.iter().map(|r| r.frame).collect::<Int32Array>()),
+    ]);
+
+    RecordBatch::try_new(schema, columns)
+}
+
+fn record_batches(db: &Database) -> Result<(SchemaRef, Vec<RecordBatch>), ArrowError> {
+    let schema = Arc::new(schema(db));
+    let batches = db.rows.chunks(BATCH_ROWS)
+        .map(|rows| record_batch(schema.clone(), db, rows))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((schema, batches))
+}
+
+/// Writes an Arrow IPC file, usually named `.arrow` or `.feather`.
+pub fn write_ipc(out: impl std::io::Write, db: &Database) -> Result<(), DataFrameError> {
+    let (schema, batches) = record_batches(db)?;
+    let mut writer = arrow::ipc::writer::FileWriter::try_new(out, &schema)?;
+    for batch in batches.iter() { writer.write(batch)?; }
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn read_ipc(input: impl std::io::Read + std::io::Seek) -> Result<Database, DataFrameError> {
+    let reader = arrow::ipc::reader::FileReader::try_new(input, None)?;
+    let schema = reader.schema();
+    from_record_batches(&schema, reader.map(|b| b.map_err(DataFrameError::from)))
+}
+
+/// Writes a zstd compressed Parquet file.
+pub fn write_parquet(out: impl std::io::Write + Send, db: &Database) -> Result<(), DataFrameError> {
+    use parquet::basic::{Compression, ZstdLevel};
+
+    let (schema, batches) = record_batches(db)?;
+    let props = parquet::file::properties::WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(out, schema, Some(props))?;
+    for batch in batches.iter() { writer.write(batch)?; }
+    writer.close()?;
+    Ok(())
+}
+
+/// `input` is usually a `std::fs::File`, or `bytes::Bytes` for a file in memory.
+pub fn read_parquet(input: impl parquet::file::reader::ChunkReader + 'static) -> Result<Database, DataFrameError> {
+    let builder = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(input)?;
+    let schema = builder.schema().clone();
+    let reader = builder.build()?;
+    from_record_batches(&schema, reader.map(|b| b.map_err(DataFrameError::from)))
+}
+
+/// Rebuilds a database from batches with the schema written by `schema`.
+/// Name columns are ignored, and may be left out.
+pub fn from_record_batches(
+    schema: &Schema,
+    batches: impl Iterator<Item = Result<RecordBatch, DataFrameError>>,
+) -> Result<Database, DataFrameError> {
+    let metadata = |key: &'static str| schema.metadata.get(key).ok_or(DataFrameError::BadMetadata(key));
+    let parse = |key: &'static str| -> Result<u32, DataFrameError> {
+        metadata(key)?.parse().map_err(|_| DataFrameError::BadMetadata(key))
+    };
+    let character = |key: &'static str| -> Result<slp_parser::Character, DataFrameError> {
+        u8::try_from(parse(key)?).ok()
+            .and_then(slp_parser::Character::from_u8_internal)
+            .ok_or(DataFrameError::BadMetadata(key))
+    };
+
+    let sources = split_sources(metadata(SOURCES_KEY)?).ok_or(DataFrameError::BadMetadata(SOURCES_KEY))?;
+
+    // exports hold the rows, not the format they were read from
+    let mut header = Header {
+        version: VERSION,
+        player_character: character(PLAYER_CHARACTER_KEY)?,
+        opponent_character: character(OPPONENT_CHARACTER_KEY)?,
+        row_count: 0,
+        source_count: sources.len() as u32,
+        layout: match metadata(LAYOUT_KEY)?.as_str() {
+            "rows" => Layout::Rows,
+            "columnar" => Layout::Columnar,
+            _ => return Err(DataFrameError::BadMetadata(LAYOUT_KEY)),
+        },
+        state_index: Vec::new(),
+        checksum: 0,
+    };
+    let sorted = match metadata(SORTED_KEY)?.as_str() {
+        "true" => true,
+        "false" => false,
+        _ => return Err(DataFrameError::BadMetadata(SORTED_KEY)),
+    };
+
+    let mut rows = Vec::new();
+    for batch in batches {
+        read_batch(&mut rows, &batch?, &header)?;
+    }
+
+    header.row_count = rows.len() as u32;
+    if sorted { header.state_index = state_index(&rows); }
+    Ok(Database { header, rows, sources })
+}
+
+fn join_sources(sources: &[String]) -> String {
+    let mut joined = String::with_capacity(sources.iter().map(|s| s.len() + 4).sum());
+    for source in sources {
+        joined.push_str(&source.len().to_string());
+        joined.push(':');
+        joined.push_str(source);
+    }
+    joined
+}
+
+fn split_sources(mut joined: &str) -> Option<Vec<String>> {
+    let mut sources = Vec::new();
+    while !joined.is_empty() {
+        let (len, rest) = joined.split_once(':')?;
+        let len = len.parse::<usize>().ok()?;
+        sources.push(rest.get(..len)?.to_string());
+        joined = &rest[len..];
+    }
+    Some(sources)
+}
+
+fn read_batch(rows: &mut Vec<Row>, batch: &RecordBatch, header: &Header) -> Result<(), DataFrameError> {
+    fn column<'a, T: Array + 'static>(batch: &'a RecordBatch, name: &'static str) -> Result<&'a T, DataFrameError> {
+        batch.column_by_name(name)
+            .and_then(|c| c.as_any().downcast_ref::<T>())
+            .ok_or(DataFrameError::BadColumn(name))
+    }
+    let u16s = |name| column::<UInt16Array>(batch, name);
+    let f32s = |name| column::<Float32Array>(batch, name);
+
+    let op_state = u16s("opponent_state")?;
+    let op_action = u16s("opponent_action")?;
+    let op_x = f32s("opponent_x")?;
+    let op_y = f32s("opponent_y")?;
+    let pl_state = u16s("player_state")?;
+    let pl_action = u16s("player_action")?;
+    let pl_x = f32s("player_x")?;
+    let pl_y = f32s("player_y")?;
+    let score = f32s("score")?;
+    let weight = f32s("weight")?;
+    let player_code = column::<FixedSizeBinaryArray>(batch, "player_code")?;
+    if player_code.value_length() != 10 { return Err(DataFrameError::BadColumn("player_code")); }
+    let source = column::<UInt32Array>(batch, "source")?;
+    let frame = column::<Int32Array>(batch, "frame")?;
+
+    let first_row = rows.len();
+    rows.reserve(batch.num_rows());
+    for i in 0..batch.num_rows() {
+        let row = first_row + i;
+        let state = |column: &'static str, codes: &UInt16Array, character| {
+            slp_parser::BroadState::from_u16(character, codes.value(i)).ok_or(DataFrameError::BadValue { column, row })
+        };
+        let action = |column: &'static str, codes: &UInt16Array, character| {
+            slp_parser::HighLevelAction::from_u16(character, codes.value(i)).ok_or(DataFrameError::BadValue { column, row })
+        };
+
+        rows.push(Row {
+            opponent_initiation: Situation {
+                start_state: state("opponent_state", op_state, header.opponent_character)?,
+                action_taken: action("opponent_action", op_action, header.opponent_character)?,
+                pos_x: op_x.value(i),
+                pos_y: op_y.value(i),
+            },
+            player_response: Situation {
+                start_state: state("player_state", pl_state, header.player_character)?,
+                action_taken: action("player_action", pl_action, header.player_character)?,
+                pos_x: pl_x.value(i),
+                pos_y: pl_y.value(i),
+            },
+            score: score.value(i),
+            weight: weight.value(i),
+            player_code: player_code.value(i).try_into().unwrap(),
+            source: if source.is_null(i) { Row::NO_SOURCE } else { source.value(i) },
+            frame: frame.value(i),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! Gecko code lists recorded in replays, used to tell replays from modded builds apart.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeckoCode {
+    /// Code type with the address high bit masked off, e.g. `0x04` for a 32 bit write or `0xC2` for an asm insert.
+    pub code_type: u8,
+    pub address: u32,
+    /// Everything after the first word, including the code type specific second word.
+    pub contents: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeckoCodeList {
+    pub codes: Vec<GeckoCode>,
+}
+
+impl GeckoCodeList {
+    /// Order independent hash of every code.
+    /// Replays from the same build have the same fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let mut code_hashes = self.codes.iter()
+            .map(|code| {
+                let mut hash = crate::FNV_OFFSET;
+                hash = crate::fnv1a(hash, &[code.code_type]);
+                hash = crate::fnv1a(hash, &code.address.to_be_bytes());
+                crate::fnv1a(hash, &code.contents)
+            })
+            .collect::<Vec<u64>>();
+        code_hashes.sort_unstable();
+
+        let mut hash = crate::FNV_OFFSET;
+        for code_hash in code_hashes {
+            hash = crate::fnv1a(hash, &code_hash.to_le_bytes());
+        }
+        hash
+    }
+}
+
+/// Splits the raw gecko list into codes.
+/// A trailing partial code is dropped.
+pub fn parse_gecko_list(bytes: &[u8]) -> GeckoCodeList {
+    let mut codes = Vec::new();
+    let mut cursor = 0;
+
+    while cursor + 8 <= bytes.len() {
+        let word1 = u32::from_be_bytes(bytes[cursor..][..4].try_into().unwrap());
+        let word2 = u32::from_be_bytes(bytes[cursor+4..][..4].try_into().unwrap()) as usize;
+        let code_type = ((word1 >> 24) & 0xFE) as u8;
+        let address = (word1 & 0x01FFFFFF) | 0x80000000;
+
+        // end of list
+        if word1 == 0xF0000000 { break; }
+
+        let size = match code_type {
+            // asm insert and execute, word2 lines of 8 bytes
+            0xC0 | 0xC2 => 8 + word2 * 8,
+            // string write, word2 bytes padded to 8
+            0x06 => 8 + ((word2 + 7) & !7),
+            // serial write
+            0x08 => 16,
+            _ => 8,
+        };
+
+        let Some(code) = bytes.get(cursor..).and_then(|b| b.get(..size)) else { break };
+        codes.push(GeckoCode {
+            code_type,
+            address,
+            contents: code[4..].to_vec(),
+        });
+        cursor += size;
+    }
+
+    GeckoCodeList { codes }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModFamily {
+    Vanilla,
+    UnclePunch,
+    TwentyXX,
+    PracticeMod,
+    Other,
+}
+
+impl ModFamily {
+    pub fn from_name(s: &str) -> Option<ModFamily> {
+        Some(match s {
+            "vanilla" => ModFamily::Vanilla,
+            "unclepunch" => ModFamily::UnclePunch,
+            "20xx" => ModFamily::TwentyXX,
+            "practice" => ModFamily::PracticeMod,
+            "other" => ModFamily::Other,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ModFamily::Vanilla => "vanilla",
+            ModFamily::UnclePunch => "unclepunch",
+            ModFamily::TwentyXX => "20xx",
+            ModFamily::PracticeMod => "practice",
+            ModFamily::Other => "other",
+        }
+    }
+}
+
+/// Known code list fingerprints and the mod family they belong to.
+///
+/// Every Slippi release ships a slightly different code list, so there is no single vanilla fingerprint,
+/// and no fingerprints are built in. They are collected from replays of known origin
+/// and stored as `<hex fingerprint> <family>` lines.
+#[derive(Clone, Debug, Default)]
+pub struct ModRegistry {
+    pub fingerprints: std::collections::HashMap<u64, ModFamily>,
+}
+
+impl ModRegistry {
+    /// Parses `<hex fingerprint> <family>` lines. Blank lines and lines starting with '#' are skipped.
+    /// Returns the 1-based number of the first invalid line on failure.
+    pub fn parse(text: &str) -> Result<ModRegistry, usize> {
+        let mut fingerprints = std::collections::HashMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let mut fields = line.split_whitespace();
+            let (Some(fingerprint), Some(family), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(i + 1);
+            };
+            let fingerprint = u64::from_str_radix(fingerprint, 16).map_err(|_| i + 1)?;
+            let family = ModFamily::from_name(family).ok_or(i + 1)?;
+            fingerprints.insert(fingerprint, family);
+        }
+
+        Ok(ModRegistry { fingerprints })
+    }
+
+    pub fn write(&self) -> String {
+        let mut entries = self.fingerprints.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(fingerprint, _)| **fingerprint);
+
+        let mut text = String::with_capacity(entries.len() * 32);
+        for (fingerprint, family) in entries {
+            text.push_str(&format!("{:016x} {}\n", fingerprint, family.name()));
+        }
+        text
+    }
+
+    /// Returns `None` for fingerprints not in the registry.
+    pub fn classify(&self, codes: &GeckoCodeList) -> Option<ModFamily> {
+        self.fingerprints.get(&codes.fingerprint()).copied()
+    }
+
+    /// True if the code list is registered as anything but `ModFamily::Vanilla`.
+    /// Unknown code lists are not treated as modded, so an empty registry keeps every replay.
+    pub fn is_modded(&self, codes: &GeckoCodeList) -> bool {
+        self.classify(codes).is_some_and(|family| family != ModFamily::Vanilla)
+    }
+}
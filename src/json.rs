@@ -0,0 +1,189 @@
+//! Just enough json to write the text formats and playback queues, and read back what `text::write_jsonl` writes
+//! and hand edits of it, without a dependency. The `server` feature uses serde_json instead.
+
+/// Writes `s` as a quoted json string.
+pub(crate) fn push_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// Kept as written, so floats parse exactly.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Strings and numbers as text.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Json::String(s) | Json::Number(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// `None` if `text` isn't a single json value.
+pub(crate) fn parse(text: &str) -> Option<Json> {
+    let mut parser = Parser { bytes: text.as_bytes(), cursor: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    (parser.cursor == parser.bytes.len()).then_some(value)
+}
+
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.cursor).is_some_and(|b| b.is_ascii_whitespace()) { self.cursor += 1; }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.cursor).copied()
+    }
+
+    fn expect(&mut self, literal: &str) -> Option<()> {
+        let end = self.cursor + literal.len();
+        (self.bytes.get(self.cursor..end)? == literal.as_bytes()).then_some(())?;
+        self.cursor = end;
+        Some(())
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        if depth > MAX_DEPTH { return None; }
+
+        match self.peek()? {
+            b'n' => self.expect("null").map(|_| Json::Null),
+            b't' => self.expect("true").map(|_| Json::Bool(true)),
+            b'f' => self.expect("false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.cursor += 1;
+                let mut values = Vec::new();
+                if self.peek()? == b']' {
+                    self.cursor += 1;
+                    return Some(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    match self.peek()? {
+                        b',' => self.cursor += 1,
+                        b']' => { self.cursor += 1; return Some(Json::Array(values)); }
+                        _ => return None,
+                    }
+                }
+            }
+            b'{' => {
+                self.cursor += 1;
+                let mut entries = Vec::new();
+                if self.peek()? == b'}' {
+                    self.cursor += 1;
+                    return Some(Json::Object(entries));
+                }
+                loop {
+                    if self.peek()? != b'"' { return None; }
+                    let key = self.string()?;
+                    if self.peek()? != b':' { return None; }
+                    self.cursor += 1;
+                    entries.push((key, self.value(depth + 1)?));
+                    match self.peek()? {
+                        b',' => self.cursor += 1,
+                        b'}' => { self.cursor += 1; return Some(Json::Object(entries)); }
+                        _ => return None,
+                    }
+                }
+            }
+            b'-' | b'0'..=b'9' => {
+                let start = self.cursor;
+                while self.bytes.get(self.cursor).is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.cursor += 1;
+                }
+                let number = std::str::from_utf8(&self.bytes[start..self.cursor]).ok()?;
+                number.parse::<f64>().ok()?;
+                Some(Json::Number(number.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.cursor += 1;
+        let mut s = String::new();
+        loop {
+            let start = self.cursor;
+            while self.bytes.get(self.cursor).is_some_and(|&b| b != b'"' && b != b'\\') { self.cursor += 1; }
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.cursor]).ok()?);
+
+            match self.bytes.get(self.cursor)? {
+                b'"' => { self.cursor += 1; return Some(s); }
+                _ => {
+                    let escape = *self.bytes.get(self.cursor + 1)?;
+                    self.cursor += 2;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let mut c = self.hex4()? as u32;
+                            if (0xD800..0xDC00).contains(&c) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()? as u32;
+                                if !(0xDC00..0xE000).contains(&low) { return None; }
+                                c = 0x10000 + ((c - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            s.push(char::from_u32(c)?);
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u16> {
+        let hex = std::str::from_utf8(self.bytes.get(self.cursor..self.cursor + 4)?).ok()?;
+        self.cursor += 4;
+        u16::from_str_radix(hex, 16).ok()
+    }
+}
@@ -0,0 +1,153 @@
+//! Readers for `.actions` files written by older versions, and upgrading them to the current format.
+//!
+//! Every past version can still be read. Versions before 3 have an 8 byte header with no counts, rows until the end of the file, and no sources.
+//! Versions 3 to 5 are laid out like the current version without the checksum, so their header is 8 bytes shorter.
+//! Header bytes 6 and 7 were reserved and zero until the version that gave them a meaning.
+//!
+//! | version | row size | added                                |
+//! |---------|----------|--------------------------------------|
+//! | 0       | 28       |                                      |
+//! | 1       | 38       | `player_code`                        |
+//! | 2       | 42       | `weight`                             |
+//! | 3       | 50       | `source`, `frame`, counts            |
+//! | 4       | 50       | layout in header byte 6, `columnar`  |
+//! | 5       | 50       | state index flag in header byte 7    |
+//! | 6       | 50       | checksum                             |
+
+use crate::*;
+
+const LEGACY_HEADER_SIZE: usize = 8;
+/// Header size for versions 3 to 5.
+const UNCHECKED_HEADER_SIZE: usize = 16;
+
+/// Row size for a past format version with the 8 byte header. `None` for later and unknown versions.
+pub fn legacy_row_size(version: u32) -> Option<usize> {
+    match version {
+        0 => Some(Situation::WRITTEN_SIZE * 2 + 4),
+        1 => Some(Situation::WRITTEN_SIZE * 2 + 4 + 10),
+        2 => Some(Situation::WRITTEN_SIZE * 2 + 4 + 4 + 10),
+        _ => None,
+    }
+}
+
+/// Reads a file written in a past format version.
+///
+/// Missing fields get the value the builder would have used: zeroed connect codes, a weight of 1,
+/// and `Row::NO_SOURCE`. The header is given the current version, so the database can be written back out
+/// in the current format, and its counts are filled in. The file's own version is its first four bytes.
+pub fn read_legacy(file: &[u8]) -> Result<Database, DBError> {
+    let version = read_u32(file, 0)?;
+    if (3..=5).contains(&version) { return read_unchecked(file, version); }
+    let row_size = legacy_row_size(version).ok_or(DBError::new(DBErrorKind::VersionTooNew(version), 0))?;
+
+    if file.len() < LEGACY_HEADER_SIZE { return Err(DBError::new(DBErrorKind::Truncated, 0)); }
+    let player_character = read_character(file, 4)?;
+    let opponent_character = read_character(file, 5)?;
+
+    let row_bytes = &file[LEGACY_HEADER_SIZE..];
+    let row_count = row_bytes.len() / row_size;
+    if !row_bytes.len().is_multiple_of(row_size) {
+        let offset = LEGACY_HEADER_SIZE + row_count * row_size;
+        return Err(DBError { kind: DBErrorKind::Truncated, offset, row: Some(row_count) });
+    }
+
+    let header = Header {
+        version: VERSION,
+        player_character,
+        opponent_character,
+        row_count: row_count as u32,
+        source_count: 0,
+        layout: Layout::Rows,
+        state_index: Vec::new(),
+        checksum: 0,
+    };
+
+    let mut rows = Vec::with_capacity(row_count);
+    for (i, row) in row_bytes.chunks_exact(row_size).enumerate() {
+        let row = read_legacy_row(row, version, &header)
+            .map_err(|e| e.in_row(LEGACY_HEADER_SIZE + i * row_size, i))?;
+        rows.push(row);
+    }
+
+    Ok(Database { header, rows, sources: Vec::new() })
+}
+
+/// Reads a version 3 to 5 file by inserting an empty checksum into the header,
+/// filling it in, and reading the result as the current version.
+fn read_unchecked(file: &[u8], version: u32) -> Result<Database, DBError> {
+    if file.len() < UNCHECKED_HEADER_SIZE { return Err(DBError::new(DBErrorKind::Truncated, file.len())); }
+
+    // a reserved byte that isn't zero is a file this version never wrote
+    let layout = read_u8(file, 6)?;
+    if version < 4 && layout != 0 { return Err(DBError::new(DBErrorKind::BadLayout(layout), 6)); }
+    let has_index = read_u8(file, 7)?;
+    if version < 5 && has_index != 0 { return Err(DBError::new(DBErrorKind::BadLayout(has_index), 7)); }
+
+    let mut current = Vec::with_capacity(file.len() + 8);
+    current.extend_from_slice(&VERSION.to_le_bytes());
+    current.extend_from_slice(&file[4..UNCHECKED_HEADER_SIZE]);
+    current.extend_from_slice(&[0; 8]);
+    current.extend_from_slice(&file[UNCHECKED_HEADER_SIZE..]);
+
+    let body_start = read_header(&current)?.written_size();
+    write_checksum(&mut current, 0, body_start);
+
+    // report offsets in the original file
+    read_database(&current).map_err(|e| match e.offset {
+        offset if offset >= Header::WRITTEN_SIZE => DBError { offset: offset - 8, ..e },
+        _ => e,
+    })
+}
+
+fn read_legacy_row(file: &[u8], version: u32, header: &Header) -> Result<Row, DBError> {
+    let (weight, player_code) = match version {
+        0 => (1.0, [0u8; 10]),
+        1 => (1.0, file[28..38].try_into().unwrap()),
+        _ => (read_f32(file, 28)?, file[32..42].try_into().unwrap()),
+    };
+
+    Ok(Row {
+        opponent_initiation: Situation {
+            start_state: read_state(file, 0, header.opponent_character)?,
+            action_taken: read_action(file, 2, header.opponent_character)?,
+            pos_x: read_f32(file, 4)?,
+            pos_y: read_f32(file, 8)?,
+        },
+        player_response: Situation {
+            start_state: read_state(file, 12, header.player_character)?,
+            action_taken: read_action(file, 14, header.player_character)?,
+            pos_x: read_f32(file, 16)?,
+            pos_y: read_f32(file, 20)?,
+        },
+        score: read_f32(file, 24)?,
+        weight,
+        player_code,
+        source: Row::NO_SOURCE,
+        frame: 0,
+    })
+}
+
+/// Rewrites a file of any readable version in the current format.
+/// Files already in the current format are rewritten unchanged.
+pub fn upgrade(file: &[u8]) -> Result<Vec<u8>, DBError> {
+    let db = read_database(file)?;
+
+    let mut buf = Vec::with_capacity(file.len() + db.rows.len() * 8 + Header::WRITTEN_SIZE);
+    write_database(&mut buf, &db);
+    Ok(buf)
+}
+
+/// Upgrades a file in place. Returns the version it was upgraded from.
+pub fn upgrade_file(path: &std::path::Path) -> std::io::Result<u32> {
+    let file = std::fs::read(path)?;
+    let version = read_u32(&file, 0).map_err(std::io::Error::other)?;
+    if version == VERSION { return Ok(version); }
+
+    let upgraded = upgrade(&file).map_err(std::io::Error::other)?;
+
+    // write alongside then rename, so a failed write doesn't lose the original
+    let tmp = path.with_extension("actions.tmp");
+    std::fs::write(&tmp, upgraded)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(version)
+}
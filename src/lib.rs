@@ -1,8 +1,51 @@
-pub const VERSION: u32 = 0;
+pub mod parse_old_game;
+pub mod build;
+pub mod player;
+pub mod ubjson;
+pub mod gecko;
+pub mod tail;
+pub mod spectator;
+pub mod writer;
+pub mod playback;
+pub mod legacy;
+pub mod columnar;
+pub mod merge;
+pub mod append;
+pub mod names;
+pub mod text;
+pub mod analysis;
+pub mod replay_query;
+pub(crate) mod json;
+#[cfg(feature = "arrow")]
+pub mod dataframe;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "server")]
+pub mod server;
+
+pub const VERSION: u32 = 6;
+
+/// Slippi connect code as stored in the game start event. Shift JIS, zero padded.
+pub type ConnectCode = [u8; 10];
+
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a, stable across runs and platforms unlike std's DefaultHasher.
+/// Start with `FNV_OFFSET` and pass the result back in to hash several slices.
+pub fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Situation {
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::state"))]
     pub start_state: slp_parser::BroadState,
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::action"))]
     pub action_taken: slp_parser::HighLevelAction,
     pub pos_x: f32,
     pub pos_y: f32,
@@ -13,38 +56,195 @@ impl Situation {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Row {
     pub player_response: Situation,
     pub opponent_initiation: Situation,
     pub score: f32,
+    /// How much this row should count for relative to others, see `build::BuildOptions`.
+    pub weight: f32,
+    /// Connect code of the player who took `player_response`. Zeroed for offline games.
+    pub player_code: ConnectCode,
+    /// Index of the replay this row came from in `Database::sources`, or `Row::NO_SOURCE`.
+    pub source: u32,
+    /// In-game frame number the opponent initiation started on.
+    pub frame: i32,
 }
 
 impl Row {
-    pub const WRITTEN_SIZE: usize = Situation::WRITTEN_SIZE * 2 + 4;
+    pub const WRITTEN_SIZE: usize = Situation::WRITTEN_SIZE * 2 + 4 + 4 + 10 + 4 + 4;
+    pub const NO_SOURCE: u32 = u32::MAX;
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     pub version: u32,
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::character"))]
     pub player_character: slp_parser::Character,
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::character"))]
     pub opponent_character: slp_parser::Character,
+    pub row_count: u32,
+    pub source_count: u32,
+    pub layout: Layout,
+    /// For rows sorted with `sort_database`, the first row of each start state pair,
+    /// as `(state_pair(opponent, player), row index)` in row order. Empty if the rows are unsorted.
+    /// Only written for `Layout::Rows`.
+    pub state_index: Vec<(u32, u32)>,
+    /// `fnv1a` of everything after the header and state index. Filled in by `write_database`.
+    pub checksum: u64,
+}
+
+/// How the rows are stored after the header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Layout {
+    /// Fixed size rows one after the other, see `write_row`.
+    Rows,
+    /// Compressed blocks of columns, see `columnar`.
+    Columnar,
 }
 
 impl Header {
-    pub const WRITTEN_SIZE: usize = 8;
+    /// Size without the state index.
+    pub const WRITTEN_SIZE: usize = 24;
+
+    /// Size including the state index, where the rows start.
+    pub fn written_size(&self) -> usize {
+        if self.state_index.is_empty() { return Header::WRITTEN_SIZE; }
+        Header::WRITTEN_SIZE + 4 + self.state_index.len() * 8
+    }
+
+    /// Rows with this start state pair, if the rows are sorted.
+    /// `None` if there is no state index, an empty range if the index has no such pair.
+    pub fn state_range(
+        &self,
+        opponent_state: slp_parser::BroadState,
+        player_state: slp_parser::BroadState,
+    ) -> Option<std::ops::Range<usize>> {
+        if self.state_index.is_empty() { return None; }
+
+        let pair = state_pair(opponent_state, player_state);
+        let Ok(i) = self.state_index.binary_search_by_key(&pair, |&(p, _)| p) else { return Some(0..0) };
+        let start = self.state_index[i].1 as usize;
+        let end = self.state_index.get(i + 1).map_or(self.row_count, |&(_, row)| row) as usize;
+        Some(start..end.max(start))
+    }
+}
+
+/// Packs a start state pair into one sortable key.
+pub fn state_pair(opponent_state: slp_parser::BroadState, player_state: slp_parser::BroadState) -> u32 {
+    (opponent_state.as_u16() as u32) << 16 | player_state.as_u16() as u32
 }
 
+/// Whether a source path points inside an archive file, like the `archive/entry` paths
+/// dataset_generator records for replays it doesn't extract.
+///
+/// This looks at the filesystem: the path is an archive entry if one of its parents exists as a file,
+/// so it is false for paths that no longer exist, and can change as files are added or removed.
+pub fn is_archive_entry(source: &str) -> bool {
+    std::path::Path::new(source).ancestors().skip(1).any(|p| p.is_file())
+}
+
+/// A whole `.actions` file.
+///
+/// Laid out as the header, the rows, then the source manifest:
+/// the path of each replay rows were taken from as a u16 length followed by utf-8.
 #[derive(Debug, Clone)]
-pub enum DBError {
-    InvalidFile(&'static str),
-    VersionTooNew,
+pub struct Database {
+    pub header: Header,
+    pub rows: Vec<Row>,
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DBErrorKind {
+    /// The file ended part way through the header, a row, or the source manifest.
+    Truncated,
+    BadCharacter(u8),
+    BadStateCode(u16),
+    BadActionCode(u16),
+    /// A source path that is not utf-8.
+    BadSource,
+    /// A layout, or a state index flag, that the file's version doesn't have.
+    BadLayout(u8),
+    /// A compressed block that failed to decompress or decompressed to the wrong size.
+    BadBlock,
+    BadChecksum,
+    /// Too old for this version of the library to read. Every past version is currently readable, see `legacy`.
+    VersionTooOld(u32),
+    /// Written by a newer version of the library.
+    VersionTooNew(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DBError {
+    pub kind: DBErrorKind,
+    /// Byte offset into the file of the value that could not be read.
+    pub offset: usize,
+    /// Index of the row the error is in, if it is in a row.
+    pub row: Option<usize>,
 }
 
+impl DBError {
+    pub fn new(kind: DBErrorKind, offset: usize) -> DBError {
+        DBError { kind, offset, row: None }
+    }
+
+    /// For errors from `read_row`, whose offsets are relative to the start of the row.
+    pub fn in_row(self, row_offset: usize, row: usize) -> DBError {
+        DBError { offset: row_offset + self.offset, row: Some(row), ..self }
+    }
+
+    fn offset_by(self, offset: usize) -> DBError {
+        DBError { offset: offset + self.offset, ..self }
+    }
+}
+
+impl std::fmt::Display for DBError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            DBErrorKind::Truncated => write!(f, "file is truncated")?,
+            DBErrorKind::BadCharacter(c) => write!(f, "invalid character {}", c)?,
+            DBErrorKind::BadStateCode(n) => write!(f, "invalid state code {}", n)?,
+            DBErrorKind::BadActionCode(n) => write!(f, "invalid action code {}", n)?,
+            DBErrorKind::BadSource => write!(f, "source path is not valid utf-8")?,
+            DBErrorKind::BadLayout(n) => write!(f, "invalid layout {}", n)?,
+            DBErrorKind::BadBlock => write!(f, "invalid compressed block")?,
+            DBErrorKind::BadChecksum => write!(f, "checksum does not match the contents")?,
+            DBErrorKind::VersionTooOld(v) => write!(f, "file version {} is older than supported", v)?,
+            DBErrorKind::VersionTooNew(v) => write!(f, "file version {} is newer than this library (version {})", v, VERSION)?,
+        }
+
+        write!(f, " at byte {}", self.offset)?;
+        if let Some(row) = self.row { write!(f, " in row {}", row)?; }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DBError {}
+
 pub fn write_header(buf: &mut Vec<u8>, header: &Header) {
     buf.extend_from_slice(&header.version.to_le_bytes());
     buf.push(header.player_character.to_u8_internal());
     buf.push(header.opponent_character.to_u8_internal());
-    buf.resize(Header::WRITTEN_SIZE, 0);
+    buf.push(match header.layout {
+        Layout::Rows => 0,
+        Layout::Columnar => 1,
+    });
+    let has_index = header.layout == Layout::Rows && !header.state_index.is_empty();
+    buf.push(has_index as u8);
+    buf.extend_from_slice(&header.row_count.to_le_bytes());
+    buf.extend_from_slice(&header.source_count.to_le_bytes());
+    buf.extend_from_slice(&header.checksum.to_le_bytes());
+
+    if has_index {
+        buf.extend_from_slice(&(header.state_index.len() as u32).to_le_bytes());
+        for (pair, row) in header.state_index.iter() {
+            buf.extend_from_slice(&pair.to_le_bytes());
+            buf.extend_from_slice(&row.to_le_bytes());
+        }
+    }
 }
 
 pub fn write_row(buf: &mut Vec<u8>, row: &Row) {
@@ -59,70 +259,179 @@ pub fn write_row(buf: &mut Vec<u8>, row: &Row) {
     buf.extend_from_slice(&row.player_response.pos_y.to_le_bytes());
 
     buf.extend_from_slice(&row.score.to_le_bytes());
+    buf.extend_from_slice(&row.weight.to_le_bytes());
+    buf.extend_from_slice(&row.player_code);
+    buf.extend_from_slice(&row.source.to_le_bytes());
+    buf.extend_from_slice(&row.frame.to_le_bytes());
 }
 
-macro_rules! invalid_db { () => { DBError::InvalidFile(concat!(file!(), ":", line!())) } }
+/// Paths longer than u16::MAX bytes are truncated.
+pub fn write_source(buf: &mut Vec<u8>, source: &str) {
+    let mut len = source.len().min(u16::MAX as usize);
+    while !source.is_char_boundary(len) { len -= 1; }
+    buf.extend_from_slice(&(len as u16).to_le_bytes());
+    buf.extend_from_slice(&source.as_bytes()[..len]);
+}
+
+/// Writes the header with the row and source counts and the checksum filled in, in the header's layout.
+pub fn write_database(buf: &mut Vec<u8>, db: &Database) {
+    if db.header.layout == Layout::Columnar { return columnar::write_columnar(buf, db); }
+
+    let header_start = buf.len();
+    write_header(buf, &Header {
+        row_count: db.rows.len() as u32,
+        source_count: db.sources.len() as u32,
+        ..db.header.clone()
+    });
+    let body_start = buf.len();
+    for row in db.rows.iter() { write_row(buf, row); }
+    for source in db.sources.iter() { write_source(buf, source); }
+    write_checksum(buf, header_start, body_start);
+}
+
+/// Fills in the checksum of a header written at `header_start`, over everything from `body_start` on.
+pub(crate) fn write_checksum(buf: &mut [u8], header_start: usize, body_start: usize) {
+    let checksum = fnv1a(FNV_OFFSET, &buf[body_start..]);
+    buf[header_start + 16..header_start + 24].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// Checks the header's checksum against the rest of the file.
+pub fn verify_checksum(file: &[u8], header: &Header) -> Result<(), DBError> {
+    let body = file.get(header.written_size()..).ok_or(DBError::new(DBErrorKind::Truncated, file.len()))?;
+    if fnv1a(FNV_OFFSET, body) != header.checksum { return Err(DBError::new(DBErrorKind::BadChecksum, 16)); }
+    Ok(())
+}
 
 pub fn read_header(file: &[u8]) -> Result<Header, DBError> {
-    if file.len() < Header::WRITTEN_SIZE { return Err(invalid_db!()); }
+    if file.len() < Header::WRITTEN_SIZE { return Err(DBError::new(DBErrorKind::Truncated, 0)); }
+
+    let mut state_index = Vec::new();
+    if read_u8(file, 7)? != 0 {
+        let count = read_u32(file, Header::WRITTEN_SIZE)? as usize;
+        let mut cursor = Header::WRITTEN_SIZE + 4;
+        state_index.reserve(count.min(file.len() / 8));
+        for _ in 0..count {
+            state_index.push((read_u32(file, cursor)?, read_u32(file, cursor + 4)?));
+            cursor += 8;
+        }
+    }
 
     Ok(Header {
-        version: read_u32(&file[0..])?,
-        player_character: slp_parser::Character::from_u8_internal(read_u8(&file[4..])?)
-            .ok_or(invalid_db!())?,
-        opponent_character: slp_parser::Character::from_u8_internal(read_u8(&file[5..])?)
-            .ok_or(invalid_db!())?,
+        version: read_u32(file, 0)?,
+        player_character: read_character(file, 4)?,
+        opponent_character: read_character(file, 5)?,
+        row_count: read_u32(file, 8)?,
+        source_count: read_u32(file, 12)?,
+        checksum: read_u64(file, 16)?,
+        layout: match read_u8(file, 6)? {
+            0 => Layout::Rows,
+            1 => Layout::Columnar,
+            n => return Err(DBError::new(DBErrorKind::BadLayout(n), 6)),
+        },
+        state_index,
     })
 }
 
+/// Offsets in errors are relative to the start of the row, see `DBError::in_row`.
 pub fn read_row(file: &[u8], header: &Header) -> Result<Row, DBError> {
-    if file.len() < Row::WRITTEN_SIZE { return Err(invalid_db!()); }
+    if file.len() < Row::WRITTEN_SIZE { return Err(DBError::new(DBErrorKind::Truncated, 0)); }
 
     Ok(Row {
         opponent_initiation: Situation {
-            start_state: slp_parser::BroadState::from_u16(header.opponent_character, read_u16(&file[0..])?)
-                .ok_or(invalid_db!())?,
-            action_taken: slp_parser::HighLevelAction::from_u16(header.opponent_character, read_u16(&file[2..])?)
-                .ok_or(invalid_db!())?,
-            pos_x: read_f32(&file[4..])?,
-            pos_y: read_f32(&file[8..])?,
+            start_state: read_state(file, 0, header.opponent_character)?,
+            action_taken: read_action(file, 2, header.opponent_character)?,
+            pos_x: read_f32(file, 4)?,
+            pos_y: read_f32(file, 8)?,
         },
         player_response: Situation {
-            start_state: slp_parser::BroadState::from_u16(header.player_character, read_u16(&file[12..])?)
-                .ok_or(invalid_db!())?,
-            action_taken: slp_parser::HighLevelAction::from_u16(header.player_character, read_u16(&file[14..])?)
-                .ok_or(invalid_db!())?,
-            pos_x: read_f32(&file[16..])?,
-            pos_y: read_f32(&file[20..])?,
+            start_state: read_state(file, 12, header.player_character)?,
+            action_taken: read_action(file, 14, header.player_character)?,
+            pos_x: read_f32(file, 16)?,
+            pos_y: read_f32(file, 20)?,
         },
-        score: read_f32(&file[24..])?,
+        score: read_f32(file, 24)?,
+        weight: read_f32(file, 28)?,
+        player_code: file[32..42].try_into().unwrap(),
+        source: read_u32(file, 42)?,
+        frame: read_u32(file, 46)? as i32,
     })
 }
 
+/// Returns the source and its written size.
+/// Offsets in errors are relative to the start of the source.
+pub fn read_source(file: &[u8]) -> Result<(String, usize), DBError> {
+    let len = read_u16(file, 0)? as usize;
+    let bytes = file.get(2..2 + len).ok_or(DBError::new(DBErrorKind::Truncated, 0))?;
+    let source = std::str::from_utf8(bytes).map_err(|_| DBError::new(DBErrorKind::BadSource, 2))?;
+    Ok((source.to_string(), 2 + len))
+}
+
+/// Files in past format versions are read with `legacy::read_legacy`.
+/// Fails with `DBErrorKind::BadChecksum` if the file was changed after it was written.
 pub fn read_file(file: &[u8]) -> Result<(Header, Vec<Row>), DBError> {
+    let version = read_u32(file, 0)?;
+    if version > VERSION { return Err(DBError::new(DBErrorKind::VersionTooNew(version), 0)); }
+    if version < VERSION {
+        let db = legacy::read_legacy(file)?;
+        return Ok((db.header, db.rows));
+    }
+
     let header = read_header(file)?;
-    if header.version != VERSION { return Err(DBError::VersionTooNew); }
+    if header.layout == Layout::Columnar {
+        let db = columnar::read_columnar(file)?;
+        return Ok((db.header, db.rows));
+    }
+    verify_checksum(file, &header)?;
 
-    let mut cursor = Header::WRITTEN_SIZE;
-    let row_count = file[cursor..].len() / Row::WRITTEN_SIZE;
-    let mut rows = Vec::with_capacity(row_count);
+    let row_count = header.row_count as usize;
+    let mut cursor = header.written_size();
+    let mut rows = Vec::with_capacity(row_count.min(file.len() / Row::WRITTEN_SIZE));
 
-    while cursor < file.len() {
-        rows.push(read_row(&file[cursor..], &header)?);
+    for i in 0..row_count {
+        let row = read_row(file.get(cursor..).unwrap_or(&[]), &header).map_err(|e| e.in_row(cursor, i))?;
+        rows.push(row);
         cursor += Row::WRITTEN_SIZE;
     }
 
     Ok((header, rows))
 }
 
+/// Files in past format versions are read with `legacy::read_legacy`.
+pub fn read_database(file: &[u8]) -> Result<Database, DBError> {
+    if read_u32(file, 0)? < VERSION { return legacy::read_legacy(file); }
+    if read_header(file)?.layout == Layout::Columnar { return columnar::read_columnar(file); }
+    let (header, rows) = read_file(file)?;
+
+    let cursor = header.written_size() + rows.len() * Row::WRITTEN_SIZE;
+    let sources = read_sources(file, cursor, header.source_count)?;
+
+    Ok(Database { header, rows, sources })
+}
+
+/// Reads `count` sources starting at `offset`.
+pub(crate) fn read_sources(file: &[u8], offset: usize, count: u32) -> Result<Vec<String>, DBError> {
+    let mut cursor = offset;
+    let mut sources = Vec::with_capacity((count as usize).min(file.len()));
+    for _ in 0..count {
+        let rest = file.get(cursor..).ok_or(DBError::new(DBErrorKind::Truncated, cursor))?;
+        let (source, size) = read_source(rest).map_err(|e| e.offset_by(cursor))?;
+        sources.push(source);
+        cursor += size;
+    }
+    Ok(sources)
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SearchSituation {
+    #[cfg_attr(feature = "serde", serde(with = "names::serde_names::state"))]
     pub start_state: slp_parser::BroadState,
     pub pos_x: f32,
     pub pos_y: f32,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SearchQuery {
     pub player_response: SearchSituation,
     pub opponent_initiation: SearchSituation,
@@ -152,8 +461,84 @@ impl SearchQuery {
     }
 }
 
+/// Sorts rows by start state pair, then the opponent's position, then the player's,
+/// and fills in the header's state index.
+pub fn sort_database(db: &mut Database) {
+    db.rows.sort_by(|a, b| {
+        let a_pair = state_pair(a.opponent_initiation.start_state, a.player_response.start_state);
+        let b_pair = state_pair(b.opponent_initiation.start_state, b.player_response.start_state);
+        a_pair.cmp(&b_pair)
+            .then(a.opponent_initiation.pos_x.total_cmp(&b.opponent_initiation.pos_x))
+            .then(a.opponent_initiation.pos_y.total_cmp(&b.opponent_initiation.pos_y))
+            .then(a.player_response.pos_x.total_cmp(&b.player_response.pos_x))
+            .then(a.player_response.pos_y.total_cmp(&b.player_response.pos_y))
+    });
+
+    db.header.row_count = db.rows.len() as u32;
+    db.header.state_index = state_index(&db.rows);
+}
+
+/// The state index for rows already in `sort_database` order.
+pub fn state_index(rows: &[Row]) -> Vec<(u32, u32)> {
+    let mut state_index: Vec<(u32, u32)> = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let pair = state_pair(row.opponent_initiation.start_state, row.player_response.start_state);
+        if state_index.last().is_none_or(|&(last, _)| last != pair) {
+            state_index.push((pair, i as u32));
+        }
+    }
+    state_index
+}
+
+impl Database {
+    /// Same results as `search` over every row, only looking at the rows for each query's
+    /// start state pair if the rows are sorted.
+    pub fn search(&self, queries: &[SearchQuery]) -> Vec<Vec<Row>> {
+        if self.header.state_index.is_empty() { return search(&self.rows, queries); }
+
+        queries.iter()
+            .map(|query| {
+                let range = self.header.state_range(query.opponent_initiation.start_state, query.player_response.start_state)
+                    .unwrap_or(0..self.rows.len());
+                let rows = self.rows.get(range).unwrap_or(&[]);
+                search(rows, std::slice::from_ref(query)).pop().unwrap()
+            })
+            .collect()
+    }
+}
+
+/// Searches a file without reading more of it than needed.
+/// Sorted files only read the rows for each query's start state pair,
+/// and columnar files skip blocks without them. The checksum is not verified.
+pub fn search_file(file: &[u8], queries: &[SearchQuery]) -> Result<Vec<Vec<Row>>, DBError> {
+    if read_u32(file, 0)? != VERSION { return Ok(read_database(file)?.search(queries)); }
+
+    let header = read_header(file)?;
+    if header.layout == Layout::Columnar { return columnar::ColumnarFile::parse(file)?.search(queries); }
+    if header.state_index.is_empty() { return Ok(read_database(file)?.search(queries)); }
+
+    let rows_start = header.written_size();
+    let mut results = Vec::with_capacity(queries.len());
+    for query in queries {
+        let range = header.state_range(query.opponent_initiation.start_state, query.player_response.start_state)
+            .unwrap_or(0..0);
+
+        let mut rows = Vec::with_capacity(range.len());
+        for i in range {
+            let offset = rows_start + i * Row::WRITTEN_SIZE;
+            let row = read_row(file.get(offset..).unwrap_or(&[]), &header).map_err(|e| e.in_row(offset, i))?;
+            rows.push(row);
+        }
+        results.push(search(&rows, std::slice::from_ref(query)).pop().unwrap());
+    }
+
+    Ok(results)
+}
+
+/// How far each situation's position can be from the query's for a row to match.
+pub const SEARCH_DISTANCE: f32 = 2.0;
+
 pub fn search(rows: &[Row], queries: &[SearchQuery]) -> Vec<Vec<Row>> {
-    const SEARCH_DISTANCE: f32 = 2.0;
     const SEARCH_DISTANCE_SQ: f32 = SEARCH_DISTANCE*SEARCH_DISTANCE;
 
     let mut results = vec![Vec::new(); queries.len()];
@@ -177,22 +562,41 @@ pub fn search(rows: &[Row], queries: &[SearchQuery]) -> Vec<Vec<Row>> {
     results
 }
 
-fn read_u32(file: &[u8]) -> Result<u32, DBError> {
-    if file.len() < 4 { return Err(invalid_db!()); }
-    Ok(u32::from_le_bytes(file[..4].try_into().unwrap()))
+fn read_u32(file: &[u8], offset: usize) -> Result<u32, DBError> {
+    let bytes = file.get(offset..offset + 4).ok_or(DBError::new(DBErrorKind::Truncated, offset))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(file: &[u8], offset: usize) -> Result<u64, DBError> {
+    let bytes = file.get(offset..offset + 8).ok_or(DBError::new(DBErrorKind::Truncated, offset))?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u16(file: &[u8], offset: usize) -> Result<u16, DBError> {
+    let bytes = file.get(offset..offset + 2).ok_or(DBError::new(DBErrorKind::Truncated, offset))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u8(file: &[u8], offset: usize) -> Result<u8, DBError> {
+    file.get(offset).copied().ok_or(DBError::new(DBErrorKind::Truncated, offset))
+}
+
+fn read_f32(file: &[u8], offset: usize) -> Result<f32, DBError> {
+    let bytes = file.get(offset..offset + 4).ok_or(DBError::new(DBErrorKind::Truncated, offset))?;
+    Ok(f32::from_le_bytes(bytes.try_into().unwrap()))
 }
 
-fn read_u16(file: &[u8]) -> Result<u16, DBError> {
-    if file.len() < 2 { return Err(invalid_db!()); }
-    Ok(u16::from_le_bytes(file[..2].try_into().unwrap()))
+fn read_character(file: &[u8], offset: usize) -> Result<slp_parser::Character, DBError> {
+    let n = read_u8(file, offset)?;
+    slp_parser::Character::from_u8_internal(n).ok_or(DBError::new(DBErrorKind::BadCharacter(n), offset))
 }
 
-fn read_u8(file: &[u8]) -> Result<u8, DBError> {
-    if file.is_empty() { return Err(invalid_db!()); }
-    Ok(file[0])
+fn read_state(file: &[u8], offset: usize, character: slp_parser::Character) -> Result<slp_parser::BroadState, DBError> {
+    let n = read_u16(file, offset)?;
+    slp_parser::BroadState::from_u16(character, n).ok_or(DBError::new(DBErrorKind::BadStateCode(n), offset))
 }
 
-fn read_f32(file: &[u8]) -> Result<f32, DBError> {
-    if file.len() < 4 { return Err(invalid_db!()); }
-    Ok(f32::from_le_bytes(file[..4].try_into().unwrap()))
+fn read_action(file: &[u8], offset: usize, character: slp_parser::Character) -> Result<slp_parser::HighLevelAction, DBError> {
+    let n = read_u16(file, offset)?;
+    slp_parser::HighLevelAction::from_u16(character, n).ok_or(DBError::new(DBErrorKind::BadActionCode(n), offset))
 }
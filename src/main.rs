@@ -1,10 +1,245 @@
-mod parse_old_game;
-
 use slp_action_db::*;
 
+const USAGE: &str = "usage: slp_action_db [<command>]
+  with no command, build output.actions from dataset_generator/output/
+  merge <out.actions> <in.actions>...
+                    combine databases with the same characters
+  split <in.actions> stage|character <out_prefix>
+                    write one <out_prefix>_<key>.actions per stage or character pair
+  dedup <in.actions> [<out.actions>]
+                    remove rows from replays ingested more than once
+  append <db.actions> <replay>...
+                    add rows from .slp or .slpz replays not already in the database
+  query <db.actions> <replay> [<frame>|all]
+                    search with the replay's interactions at an in-game frame, or all of them
+  export <in.actions> <out>
+  import <in> <out.actions>
+                    convert to or from .csv, .jsonl, .parquet, .arrow or .sqlite, chosen by extension";
+
 fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(|a| a.as_str()) {
+        None => build(),
+        Some("merge") if args.len() >= 3 => merge_command(&args[1], &args[2..]),
+        Some("split") if args.len() == 4 => split_command(&args[1], &args[2], &args[3]),
+        Some("dedup") if args.len() == 2 || args.len() == 3 => {
+            dedup_command(&args[1], args.get(2).unwrap_or(&args[1]))
+        }
+        Some("append") if args.len() >= 3 => append_command(&args[1], &args[2..]),
+        Some("query") if args.len() == 3 || args.len() == 4 => {
+            query_command(&args[1], &args[2], args.get(3).map_or("all", |a| a.as_str()))
+        }
+        Some("export") if args.len() == 3 => export_command(&args[1], &args[2]),
+        Some("import") if args.len() == 3 => import_command(&args[1], &args[2]),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_db(path: &str) -> Database {
+    let file = match std::fs::read(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("ERROR: could not open {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    match read_database(&file) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("ERROR: could not read {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_db(path: &str, db: &Database) {
+    let mut buf = Vec::with_capacity(db.rows.len() * Row::WRITTEN_SIZE + 1024);
+    write_database(&mut buf, db);
+    if let Err(e) = std::fs::write(path, buf) {
+        eprintln!("ERROR: could not write {}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+fn merge_command(out: &str, inputs: &[String]) {
+    let dbs = inputs.iter().map(|p| read_db(p)).collect::<Vec<_>>();
+    match merge::merge(dbs) {
+        Ok(db) => {
+            println!("merged {} rows from {} sources", db.rows.len(), db.sources.len());
+            write_db(out, &db);
+        }
+        Err(i) => {
+            eprintln!("ERROR: characters in {} don't match {}", inputs[i], inputs[0]);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn split_command(input: &str, by: &str, out_prefix: &str) {
+    let db = read_db(input);
+    let split = match by {
+        "stage" => merge::split_by_stage(&db),
+        "character" => merge::split_by_character(&db),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+    let split = match split {
+        Ok(split) => split,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let kept: usize = split.values().map(|part| part.rows.len()).sum();
+    if kept != db.rows.len() {
+        eprintln!("left out {} rows without a source, or from offline games between different characters", db.rows.len() - kept);
+    }
+
+    for (key, part) in split.iter() {
+        let path = format!("{}_{}.actions", out_prefix, key);
+        println!("{}: {} rows", path, part.rows.len());
+        write_db(&path, part);
+    }
+}
+
+fn dedup_command(input: &str, out: &str) {
+    let mut db = read_db(input);
+    let removed = merge::dedup(&mut db);
+    println!("removed {} duplicate rows", removed);
+    write_db(out, &db);
+}
+
+fn append_command(db_path: &str, replays: &[String]) {
+    let report = match append::append_file(std::path::Path::new(db_path), replays, &build::BuildOptions::default()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("ERROR: could not append to {}: {}", db_path, e);
+            std::process::exit(1);
+        }
+    };
+
+    for (path, e) in report.failed.iter() { eprintln!("{}: {}", path, e); }
+    println!(
+        "added {} rows from {} replays, {} already in the database",
+        report.added_rows, report.added_replays, report.already_present.len(),
+    );
+}
+
+fn query_command(db_path: &str, replay: &str, frame: &str) {
+    let selection = match frame {
+        "all" => replay_query::FrameSelection::All,
+        _ => match frame.parse() {
+            Ok(f) => replay_query::FrameSelection::Frame(f),
+            Err(_) => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let db = read_db(db_path);
+    let results = match replay_query::query_replay(&db, std::path::Path::new(replay), selection) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("ERROR: {}: {}", replay, e);
+            std::process::exit(1);
+        }
+    };
+
+    if results.is_empty() { println!("no interactions"); }
+    for m in results.iter() {
+        let (op, pl) = (&m.query.opponent_initiation, &m.query.player_response);
+        println!(
+            "frame {} port {}: {} at ({:.1}, {:.1}) against {} at ({:.1}, {:.1}), responded with {}",
+            m.frame, m.port + 1,
+            names::state_name(pl.start_state), pl.pos_x, pl.pos_y,
+            names::state_name(op.start_state), op.pos_x, op.pos_y,
+            names::action_name(m.action_taken),
+        );
+        println!("    {} rows", m.rows.len());
+        for summary in analysis::summarize_actions(&m.rows) {
+            println!(
+                "    {}: {} rows, weight {:.1}, mean score {:.2}",
+                names::action_name(summary.action), summary.count, summary.total_weight, summary.mean_score,
+            );
+        }
+    }
+}
+
+fn export_command(input: &str, out: &str) {
+    let db = read_db(input);
+    #[cfg(feature = "arrow")]
+    let out_buf = || std::io::BufWriter::new(std::fs::File::create(out).unwrap());
+
+    let result: Result<(), String> = match std::path::Path::new(out).extension().and_then(|e| e.to_str()) {
+        Some("csv") => std::fs::write(out, text::write_csv(&db)).map_err(|e| e.to_string()),
+        Some("jsonl") => std::fs::write(out, text::write_jsonl(&db)).map_err(|e| e.to_string()),
+        #[cfg(feature = "arrow")]
+        Some("parquet") => dataframe::write_parquet(out_buf(), &db).map_err(|e| e.to_string()),
+        #[cfg(feature = "arrow")]
+        Some("arrow" | "feather") => dataframe::write_ipc(out_buf(), &db).map_err(|e| e.to_string()),
+        #[cfg(feature = "sqlite")]
+        Some("sqlite" | "db") => sqlite::SqliteDatabase::create(out, &db).map(|_| ()).map_err(|e| e.to_string()),
+        _ => Err(unsupported_format()),
+    };
+
+    match result {
+        Ok(()) => println!("exported {} rows", db.rows.len()),
+        Err(e) => {
+            eprintln!("ERROR: could not export to {}: {}", out, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn import_command(input: &str, out: &str) {
+    #[cfg(feature = "arrow")]
+    let file = || std::fs::File::open(input).unwrap();
+
+    let result: Result<Database, String> = match std::path::Path::new(input).extension().and_then(|e| e.to_str()) {
+        Some("csv") => text::read_csv(&std::fs::read_to_string(input).unwrap()).map_err(|e| e.to_string()),
+        Some("jsonl") => text::read_jsonl(&std::fs::read_to_string(input).unwrap()).map_err(|e| e.to_string()),
+        #[cfg(feature = "arrow")]
+        Some("parquet") => dataframe::read_parquet(file()).map_err(|e| e.to_string()),
+        #[cfg(feature = "arrow")]
+        Some("arrow" | "feather") => dataframe::read_ipc(std::io::BufReader::new(file())).map_err(|e| e.to_string()),
+        #[cfg(feature = "sqlite")]
+        Some("sqlite" | "db") => sqlite::SqliteDatabase::open(input)
+            .and_then(|db| db.read_database())
+            .map_err(|e| e.to_string()),
+        _ => Err(unsupported_format()),
+    };
+
+    match result {
+        Ok(db) => {
+            println!("imported {} rows", db.rows.len());
+            write_db(out, &db);
+        }
+        Err(e) => {
+            eprintln!("ERROR: could not import {}: {}", input, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn unsupported_format() -> String {
+    let mut message = "unsupported file extension".to_string();
+    if !cfg!(feature = "arrow") { message.push_str(", .parquet and .arrow need the arrow feature"); }
+    if !cfg!(feature = "sqlite") { message.push_str(", .sqlite needs the sqlite feature"); }
+    message
+}
+
+fn build() {
+    // only the replays, not anything else that ends up in the directory
     let mut files = std::fs::read_dir("dataset_generator/output/").unwrap()
         .map(|e| e.unwrap().file_name())
+        .filter(|f| std::path::Path::new(f).extension().is_some_and(|ext| ext == "slpz"))
         .collect::<Vec<_>>();
 
     let size = files.len() / 8;
@@ -27,12 +262,14 @@ fn main() {
             let handle = s.spawn(move || {
                 let mut rows: Vec<Row> = Vec::with_capacity(8 * 1024 * 1024);
                 let thread_files = slices[i];
+                let first_source = size * i;
 
-                for f in thread_files {
+                for (j, f) in thread_files.iter().enumerate() {
+                    let source = (first_source + j) as u32;
                     let path = std::path::Path::new("dataset_generator/output/").join(f);
                     let bytes = std::fs::read(path).unwrap();
 
-                    let game = match parse_old_game::parse_old_file_slpz(&bytes) {
+                    let game = match parse_old_game::parse_old_file_full_slpz(&bytes) {
                         Ok(g) => g,
                         Err(e) => {
                             eprintln!("failed to parse: {}", e);
@@ -40,57 +277,8 @@ fn main() {
                         }
                     };
 
-                    let Some((low, high)) = game.info.low_high_ports() else {
-                        eprintln!("not two player!");
-                        continue;
-                    };
-
-                    let low_frames = game.frames[low].as_ref().unwrap();
-                    let high_frames = game.frames[low].as_ref().unwrap();
-
-                    let low_actions = slp_parser::parse_actions(low_frames);
-                    let high_actions = slp_parser::parse_actions(high_frames);
-
-                    let a = slp_parser::generate_interactions(game.info.stage, &low_actions, &high_actions, low_frames, high_frames);
-                    let b = slp_parser::generate_interactions(game.info.stage, &high_actions, &low_actions, high_frames, low_frames);
-
-                    rows.reserve(a.len() + b.len());
-
-                    fn push_row(
-                        rows: &mut Vec<Row>,
-                        interaction: slp_parser::InteractionRef<'_>,
-                        pl_frames: &[slp_parser::Frame],
-                        op_frames: &[slp_parser::Frame],
-                    ) {
-                        let Some((s1, s2)) = interaction.score else { return; };
-
-                        let pl_pos = pl_frames[interaction.player_response.frame_start].position;
-                        let op_pos = op_frames[interaction.opponent_initiation.frame_start].position;
-                    
-                        rows.push(Row {
-                            opponent_initiation: Situation {
-                                start_state: interaction.player_response.start_state,
-                                action_taken: interaction.player_response.action_taken,
-                                pos_x: pl_pos.x,
-                                pos_y: pl_pos.y,
-                            },
-                            player_response: Situation {
-                                start_state: interaction.opponent_initiation.start_state,
-                                action_taken: interaction.opponent_initiation.action_taken,
-                                pos_x: op_pos.x,
-                                pos_y: op_pos.y,
-                            },
-                            score: (s1.percent + s1.kill + s1.pos_x + s1.pos_y)
-                                - (s2.percent + s2.kill + s2.pos_x + s2.pos_y),
-                        });
-                    }
-
-                    for interaction in a {
-                        push_row(&mut rows, interaction, low_frames, high_frames);
-                    }
-
-                    for interaction in b {
-                        push_row(&mut rows, interaction, high_frames, low_frames);
+                    if let Err(reason) = build::push_game_rows(&mut rows, &game, source, &build::BuildOptions::default()) {
+                        eprintln!("skipped: {:?}", reason);
                     }
                 }
 
@@ -100,23 +288,33 @@ fn main() {
             handles[i] = Some(handle);
         }
 
-        let mut buf: Vec<u8> = Vec::with_capacity(16 * 1024 * 1024);
-
-        write_header(&mut buf, &Header {
-            version: VERSION,
-            player_character: slp_parser::Character::Fox,
-            opponent_character: slp_parser::Character::Fox,
-        });
+        let mut db = Database {
+            header: Header {
+                version: VERSION,
+                player_character: slp_parser::Character::Fox,
+                opponent_character: slp_parser::Character::Fox,
+                row_count: 0,
+                source_count: 0,
+                layout: Layout::Rows,
+                state_index: Vec::new(),
+                checksum: 0,
+            },
+            rows: Vec::new(),
+            sources: files.iter()
+                .map(|f| std::path::Path::new("dataset_generator/output/").join(f).to_string_lossy().into_owned())
+                .collect(),
+        };
 
         let mut size = 0usize;
         for i in 0..8 {
             let rows = handles[i].take().unwrap().join().unwrap();
-
-            for row in rows.iter() {
-                write_row(&mut buf, row);
-            }
+            db.rows.extend(rows);
         }
 
+        sort_database(&mut db);
+
+        let mut buf: Vec<u8> = Vec::with_capacity(16 * 1024 * 1024);
+        write_database(&mut buf, &db);
         std::fs::write("output.actions", buf).unwrap();
     });
 }
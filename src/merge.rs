@@ -0,0 +1,234 @@
+//! Combining, splitting and deduplicating databases, keeping each row's source.
+
+use crate::*;
+
+/// Concatenates databases, remapping each row's source into the combined manifest.
+/// Sources with the same path in several databases become one source.
+///
+/// The result is sorted if any input was. Its layout is the first database's.
+/// Returns the index of the first database whose characters differ from the first's.
+pub fn merge(dbs: Vec<Database>) -> Result<Database, usize> {
+    let mut dbs = dbs.into_iter();
+    let Some(mut merged) = dbs.next() else { return Err(0) };
+    let mut sorted = !merged.header.state_index.is_empty();
+
+    let mut source_indices: std::collections::HashMap<String, u32> = merged.sources.iter()
+        .enumerate()
+        .map(|(i, s)| (s.clone(), i as u32))
+        .collect();
+
+    for (i, db) in dbs.enumerate() {
+        if db.header.player_character != merged.header.player_character
+            || db.header.opponent_character != merged.header.opponent_character
+        {
+            return Err(i + 1);
+        }
+        sorted |= !db.header.state_index.is_empty();
+
+        let remap = db.sources.into_iter()
+            .map(|source| *source_indices.entry(source.clone()).or_insert_with(|| {
+                merged.sources.push(source);
+                merged.sources.len() as u32 - 1
+            }))
+            .collect::<Vec<u32>>();
+
+        merged.rows.extend(db.rows.into_iter().map(|mut row| {
+            row.source = remap.get(row.source as usize).copied().unwrap_or(Row::NO_SOURCE);
+            row
+        }));
+    }
+
+    merged.header.version = VERSION;
+    merged.header.row_count = merged.rows.len() as u32;
+    merged.header.source_count = merged.sources.len() as u32;
+    merged.header.state_index.clear();
+    if sorted { sort_database(&mut merged); }
+
+    Ok(merged)
+}
+
+/// Groups rows by `key`, one database per key with only the sources its rows use.
+/// Rows the key returns `None` for are left out.
+pub fn split_by<K: Ord>(
+    db: &Database,
+    mut key: impl FnMut(&Row) -> Option<K>,
+) -> std::collections::BTreeMap<K, Database> {
+    let mut split = std::collections::BTreeMap::new();
+
+    for row in db.rows.iter() {
+        let Some(k) = key(row) else { continue };
+        split.entry(k)
+            .or_insert_with(|| Database {
+                header: Header { state_index: Vec::new(), ..db.header.clone() },
+                rows: Vec::new(),
+                sources: db.sources.clone(),
+            })
+            .rows.push(row.clone());
+    }
+
+    for part in split.values_mut() {
+        remove_unused_sources(part);
+        if !db.header.state_index.is_empty() { sort_database(part); }
+    }
+
+    split
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    Io(String, std::io::Error),
+    Parse(String, slp_parser::SlpError),
+    /// An entry inside an archive, which has to be extracted before it can be read, see `is_archive_entry`.
+    InArchive(String),
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SourceError::Io(path, e) => write!(f, "could not open source '{}': {}", path, e),
+            SourceError::Parse(path, e) => write!(f, "could not parse source '{}': {}", path, e),
+            SourceError::InArchive(path) => write!(f, "source '{}' is inside an archive, extract it first", path),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Reads the game start of a `.slp` or `.slpz` source.
+pub fn read_game_start(path: &str) -> Result<slp_parser::GameStart, SourceError> {
+    if is_archive_entry(path) { return Err(SourceError::InArchive(path.to_string())); }
+    let mut file = std::fs::File::open(path).map_err(|e| SourceError::Io(path.to_string(), e))?;
+    if path.ends_with(".slpz") {
+        parse_old_game::parse_file_info_slpz(&mut file)
+    } else {
+        parse_old_game::parse_file_info(&mut file)
+    }.map_err(|e| SourceError::Parse(path.to_string(), e))
+}
+
+/// Reads the game start of every source, for splitting by stage or character.
+/// Fails on the first source that can't be opened or parsed, rather than leaving its rows out.
+pub fn source_game_starts(db: &Database) -> Result<Vec<slp_parser::GameStart>, SourceError> {
+    db.sources.iter().map(|path| read_game_start(path)).collect()
+}
+
+/// Splits by the stage each row's source replay was played on. Rows without a source are left out.
+pub fn split_by_stage(db: &Database) -> Result<std::collections::BTreeMap<String, Database>, SourceError> {
+    let game_starts = source_game_starts(db)?;
+    Ok(split_by(db, |row| {
+        let game_start = game_starts.get(row.source as usize)?;
+        Some(format!("{:?}", game_start.stage))
+    }))
+}
+
+/// Splits by the characters actually played in each row's source replay,
+/// keyed by `<player>_<opponent>` with the header's characters fixed to match.
+///
+/// The responding player is found by connect code. Offline replays, without connect codes,
+/// are only kept if both players are the same character. Rows without a source are left out.
+pub fn split_by_character(db: &Database) -> Result<std::collections::BTreeMap<String, Database>, SourceError> {
+    let game_starts = source_game_starts(db)?;
+    let mut characters = std::collections::HashMap::new();
+    let mut split = split_by(db, |row| {
+        let game_start = game_starts.get(row.source as usize)?;
+        let ports = game_start.starting_character_colours.iter()
+            .enumerate()
+            .filter_map(|(port, c)| Some((port, c.as_ref()?.character())))
+            .collect::<Vec<_>>();
+        let [(low, low_character), (_, high_character)] = ports[..] else { return None };
+
+        let pair = if row.player_code == [0; 10] {
+            if low_character != high_character { return None; }
+            (low_character, high_character)
+        } else if game_start.connect_codes[low] == row.player_code {
+            (low_character, high_character)
+        } else {
+            (high_character, low_character)
+        };
+
+        let key = format!("{:?}_{:?}", pair.0, pair.1);
+        characters.insert(key.clone(), pair);
+        Some(key)
+    });
+
+    for (key, part) in split.iter_mut() {
+        let (player, opponent) = characters[key];
+        part.header.player_character = player;
+        part.header.opponent_character = opponent;
+    }
+    Ok(split)
+}
+
+/// Removes rows that come from the same replay ingested more than once.
+///
+/// Sources with the same path, or whose rows are identical, are treated as one replay,
+/// and the rows of all but the first are dropped. Identical rows from one source are also dropped.
+/// Returns the number of rows removed.
+pub fn dedup(db: &mut Database) -> usize {
+    let before = db.rows.len();
+
+    // hash each source's rows, ignoring the source index, row order and repeated rows
+    let mut row_hashes: Vec<Vec<u64>> = vec![Vec::new(); db.sources.len()];
+    let mut row_buf = Vec::with_capacity(Row::WRITTEN_SIZE);
+    for row in db.rows.iter() {
+        let Some(hashes) = row_hashes.get_mut(row.source as usize) else { continue };
+        hashes.push(row_hash(&mut row_buf, row));
+    }
+
+    // first source with each path and each set of rows
+    let mut canonical = Vec::with_capacity(db.sources.len());
+    let mut by_path = std::collections::HashMap::new();
+    let mut by_rows = std::collections::HashMap::new();
+    for (i, hashes) in row_hashes.iter_mut().enumerate() {
+        hashes.sort_unstable();
+        hashes.dedup();
+        let mut source_hash = FNV_OFFSET;
+        for h in hashes.iter() { source_hash = fnv1a(source_hash, &h.to_le_bytes()); }
+
+        let mut first = *by_path.entry(db.sources[i].as_str()).or_insert(i);
+        if !hashes.is_empty() { first = first.min(*by_rows.entry(source_hash).or_insert(first)); }
+        canonical.push(first);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    db.rows.retain(|row| {
+        let source = row.source as usize;
+        match canonical.get(source) {
+            Some(&first) if first != source => false,
+            _ => seen.insert((row.source, row_hash(&mut row_buf, row))),
+        }
+    });
+
+    remove_unused_sources(db);
+    if !db.header.state_index.is_empty() { sort_database(db); }
+    before - db.rows.len()
+}
+
+fn row_hash(buf: &mut Vec<u8>, row: &Row) -> u64 {
+    buf.clear();
+    write_row(buf, &Row { source: 0, ..row.clone() });
+    fnv1a(FNV_OFFSET, buf)
+}
+
+/// Drops sources no row refers to and renumbers the rest, keeping their order.
+pub fn remove_unused_sources(db: &mut Database) {
+    let mut used = vec![false; db.sources.len()];
+    for row in db.rows.iter() {
+        if let Some(u) = used.get_mut(row.source as usize) { *u = true; }
+    }
+
+    let mut remap = vec![Row::NO_SOURCE; db.sources.len()];
+    let mut sources = Vec::new();
+    for (i, source) in std::mem::take(&mut db.sources).into_iter().enumerate() {
+        if !used[i] { continue; }
+        remap[i] = sources.len() as u32;
+        sources.push(source);
+    }
+
+    for row in db.rows.iter_mut() {
+        row.source = remap.get(row.source as usize).copied().unwrap_or(Row::NO_SOURCE);
+    }
+
+    db.sources = sources;
+    db.header.row_count = db.rows.len() as u32;
+    db.header.source_count = db.sources.len() as u32;
+}
@@ -0,0 +1,152 @@
+//! Symbolic names for characters, states and actions, as `slp_parser` spells them in `Debug`.
+//! Used by the text and dataframe exports, and by the `serde` feature.
+
+pub fn character_name(character: slp_parser::Character) -> String {
+    format!("{:?}", character)
+}
+
+pub fn state_name(state: slp_parser::BroadState) -> String {
+    format!("{:?}", state)
+}
+
+pub fn action_name(action: slp_parser::HighLevelAction) -> String {
+    format!("{:?}", action)
+}
+
+/// Also accepts the internal character id as a number.
+pub fn parse_character(name: &str) -> Option<slp_parser::Character> {
+    if let Ok(n) = name.parse::<u8>() { return slp_parser::Character::from_u8_internal(n); }
+    (0..=u8::MAX)
+        .filter_map(slp_parser::Character::from_u8_internal)
+        .find(|&c| character_name(c) == name)
+}
+
+/// Every state and action name for one character, to look names up by.
+#[derive(Clone, Debug)]
+pub struct NameTable {
+    pub character: slp_parser::Character,
+    states: std::collections::HashMap<String, slp_parser::BroadState>,
+    actions: std::collections::HashMap<String, slp_parser::HighLevelAction>,
+}
+
+impl NameTable {
+    pub fn new(character: slp_parser::Character) -> NameTable {
+        let mut states = std::collections::HashMap::new();
+        let mut actions = std::collections::HashMap::new();
+        for n in 0..=u16::MAX {
+            if let Some(state) = slp_parser::BroadState::from_u16(character, n) {
+                states.insert(state_name(state), state);
+            }
+            if let Some(action) = slp_parser::HighLevelAction::from_u16(character, n) {
+                actions.insert(action_name(action), action);
+            }
+        }
+        NameTable { character, states, actions }
+    }
+
+    /// Also accepts the numeric code.
+    pub fn state(&self, name: &str) -> Option<slp_parser::BroadState> {
+        if let Ok(n) = name.parse::<u16>() { return slp_parser::BroadState::from_u16(self.character, n); }
+        self.states.get(name).copied()
+    }
+
+    /// Also accepts the numeric code.
+    pub fn action(&self, name: &str) -> Option<slp_parser::HighLevelAction> {
+        if let Ok(n) = name.parse::<u16>() { return slp_parser::HighLevelAction::from_u16(self.character, n); }
+        self.actions.get(name).copied()
+    }
+}
+
+/// Every state and action of every character by name, built the first time it's needed.
+/// For reading names without knowing whose they are.
+/// Names that different characters resolve to different values are kept as `None`, see `NameError::Ambiguous`.
+struct AllNames {
+    states: std::collections::HashMap<String, Option<slp_parser::BroadState>>,
+    actions: std::collections::HashMap<String, Option<slp_parser::HighLevelAction>>,
+}
+
+fn all_names() -> &'static AllNames {
+    static ALL: std::sync::OnceLock<AllNames> = std::sync::OnceLock::new();
+    ALL.get_or_init(|| {
+        let mut all = AllNames { states: std::collections::HashMap::new(), actions: std::collections::HashMap::new() };
+        for character in (0..=u8::MAX).filter_map(slp_parser::Character::from_u8_internal) {
+            let table = NameTable::new(character);
+            for (name, state) in table.states {
+                all.states.entry(name).and_modify(|s| if *s != Some(state) { *s = None }).or_insert(Some(state));
+            }
+            for (name, action) in table.actions {
+                all.actions.entry(name).and_modify(|a| if *a != Some(action) { *a = None }).or_insert(Some(action));
+            }
+        }
+        all
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NameError {
+    /// No character has a state or action with this name.
+    Unknown,
+    /// Characters disagree on what the name means, so it can only be read knowing the character, see `NameTable`.
+    Ambiguous,
+}
+
+/// A state by name, for any character.
+pub fn parse_any_state(name: &str) -> Result<slp_parser::BroadState, NameError> {
+    all_names().states.get(name).ok_or(NameError::Unknown)?.ok_or(NameError::Ambiguous)
+}
+
+/// An action by name, for any character.
+pub fn parse_any_action(name: &str) -> Result<slp_parser::HighLevelAction, NameError> {
+    all_names().actions.get(name).ok_or(NameError::Unknown)?.ok_or(NameError::Ambiguous)
+}
+
+/// `#[serde(with = "...")]` modules writing `slp_parser` enums by name.
+#[cfg(feature = "serde")]
+pub mod serde_names {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub mod character {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(character: &slp_parser::Character, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&crate::names::character_name(*character))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<slp_parser::Character, D::Error> {
+            let name = String::deserialize(d)?;
+            crate::names::parse_character(&name).ok_or_else(|| D::Error::custom(format!("unknown character '{}'", name)))
+        }
+    }
+
+    pub mod state {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(state: &slp_parser::BroadState, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&crate::names::state_name(*state))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<slp_parser::BroadState, D::Error> {
+            let name = String::deserialize(d)?;
+            crate::names::parse_any_state(&name).map_err(|e| D::Error::custom(match e {
+                crate::names::NameError::Unknown => format!("unknown state '{}'", name),
+                crate::names::NameError::Ambiguous => format!("state '{}' means different states for different characters", name),
+            }))
+        }
+    }
+
+    pub mod action {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(action: &slp_parser::HighLevelAction, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&crate::names::action_name(*action))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<slp_parser::HighLevelAction, D::Error> {
+            let name = String::deserialize(d)?;
+            crate::names::parse_any_action(&name).map_err(|e| D::Error::custom(match e {
+                crate::names::NameError::Unknown => format!("unknown action '{}'", name),
+                crate::names::NameError::Ambiguous => format!("action '{}' means different actions for different characters", name),
+            }))
+        }
+    }
+}
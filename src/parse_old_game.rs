@@ -1,14 +1,15 @@
 use slp_parser::*;
 
-const EVENT_PAYLOADS:       u8 = 0x35;
-const GAME_START:           u8 = 0x36;
-const PRE_FRAME_UPDATE:     u8 = 0x37;
-const POST_FRAME_UPDATE:    u8 = 0x38;
-const GAME_END:             u8 = 0x39;
-//const FRAME_START:          u8 = 0x3A;
-const ITEM_UPDATE:          u8 = 0x3B;
-const FRAME_BOOKEND:        u8 = 0x3C;
-//const GECKO_LIST:           u8 = 0x3D;
+pub const MESSAGE_SPLITTER:     u8 = 0x10;
+pub const EVENT_PAYLOADS:       u8 = 0x35;
+pub const GAME_START:           u8 = 0x36;
+pub const PRE_FRAME_UPDATE:     u8 = 0x37;
+pub const POST_FRAME_UPDATE:    u8 = 0x38;
+pub const GAME_END:             u8 = 0x39;
+pub const FRAME_START:          u8 = 0x3A;
+pub const ITEM_UPDATE:          u8 = 0x3B;
+pub const FRAME_BOOKEND:        u8 = 0x3C;
+pub const GECKO_LIST:           u8 = 0x3D;
 
 pub const MAX_SUPPORTED_SLPZ_VERSION: u32 = 0;
 
@@ -17,6 +18,13 @@ pub const MIN_VERSION_MINOR: u8 = 0;
 
 pub const HEADER_LEN: u64 = 15;
 
+/// Frame numbers start here, so frame -123 is index 0 in `Game::frames`.
+pub const FIRST_FRAME: i32 = -123;
+
+/// Frames past this are refused, eight hours of frames at 60 per second.
+/// Longer games don't happen, and frame numbers are used to size `Game::frames`.
+pub const MAX_FRAME: i32 = 8 * 60 * 60 * 60;
+
 fn read_f32(bytes: &[u8], offset: usize) -> f32 { f32::from_be_bytes(bytes[offset..][..4].try_into().unwrap()) }
 fn read_u32(bytes: &[u8], offset: usize) -> u32 { u32::from_be_bytes(bytes[offset..][..4].try_into().unwrap()) }
 fn read_u16(bytes: &[u8], offset: usize) -> u16 { u16::from_be_bytes(bytes[offset..][..2].try_into().unwrap()) }
@@ -29,14 +37,77 @@ fn read_array<const SIZE: usize>(bytes: &[u8], offset: usize) -> [u8; SIZE] {
 
 type EventSizes = [u16; 255];
 
+/// A parsed game along with the parts of the replay that `Game` has no place for.
+pub struct FullGame {
+    pub game: Game,
+    pub metadata: Option<Metadata>,
+    /// `None` if the replay ended without a game end event, e.g. a crash or disconnect.
+    pub game_end: Option<GameEnd>,
+    /// Indexed the same as `Game::frames`. `None` for frames without a frame start event,
+    /// which includes every frame of replays older than 2.2.0.
+    pub frame_starts: Box<[Option<FrameStart>]>,
+    /// Frame numbers that were rolled back and replaced, once per rollback, in the order they happened.
+    pub rollback_frames: Vec<i32>,
+    /// `None` for replays older than 3.3.0.
+    pub gecko_codes: Option<crate::gecko::GeckoCodeList>,
+}
+
+impl FullGame {
+    /// Port of the winning player.
+    /// Uses the placements if the replay has them, otherwise the remaining stocks and percent on the last frame.
+    /// Returns `None` for ties and games that ended without a game end event.
+    pub fn winner(&self) -> Option<usize> {
+        let game_end = self.game_end.as_ref()?;
+
+        if let Some(placements) = game_end.placements {
+            return placements.iter().position(|&p| p == 0);
+        }
+
+        // the player who quit out loses
+        if let Some(initiator) = game_end.lras_initiator {
+            let (low, high) = self.game.info.low_high_ports()?;
+            if initiator as usize == low { return Some(high); }
+            if initiator as usize == high { return Some(low); }
+            return None;
+        }
+
+        let mut best: Option<(usize, u8, f32)> = None;
+        let mut tied = false;
+        for (port, frames) in self.game.frames.iter().enumerate() {
+            let Some(last) = frames.as_ref().and_then(|f| f.last()) else { continue };
+
+            match best {
+                Some((_, stocks, percent)) if stocks == last.stock_count && percent == last.percent => tied = true,
+                Some((_, stocks, percent)) if stocks > last.stock_count
+                    || (stocks == last.stock_count && percent < last.percent) => {}
+                _ => {
+                    best = Some((port, last.stock_count, last.percent));
+                    tied = false;
+                }
+            }
+        }
+
+        if tied { return None; }
+        best.map(|(port, _, _)| port)
+    }
+}
+
 pub fn parse_old_file_slpz(slpz: &[u8]) -> SlpResult<Game> {
+    parse_old_file_full_slpz(slpz).map(|g| g.game)
+}
+
+pub fn parse_old_file(slp: &[u8]) -> SlpResult<Game> {
+    parse_old_file_full(slp).map(|g| g.game)
+}
+
+pub fn parse_old_file_full_slpz(slpz: &[u8]) -> SlpResult<FullGame> {
     let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
     let slp = slpz::decompress(&mut decompressor, slpz)
         .map_err(|_| SlpError::InvalidFile(InvalidLocation::SlpzDecompression))?;
-    parse_old_file(&slp)
+    parse_old_file_full(&slp)
 }
 
-pub fn parse_old_file(slp: &[u8]) -> SlpResult<Game> {
+pub fn parse_old_file_full(slp: &[u8]) -> SlpResult<FullGame> {
     // parse header and metadata --------------------------------------------------------
 
     let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(slp)?;
@@ -44,44 +115,11 @@ pub fn parse_old_file(slp: &[u8]) -> SlpResult<Game> {
     let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
     let game_start = parse_game_start(&slp[game_start_offset..][..game_start_size])?;
 
-    // setup mem for event parsing --------------------------------------------------------
-
-    struct FrameWriteOp {
-        pub from_idx: usize,
-        pub to: Vec<Frame>,
-    }
-    let mut frame_ops = [
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-    ];
-    let mut frame_op_count = 0;
-
-    let frame_count_heuristic = 1024;
-    for i in 0..4 {
-        if let Some(ch_colour) = game_start.starting_character_colours[i] {
-            frame_ops[frame_op_count] = FrameWriteOp {
-                from_idx: i,
-                to: vec![Frame::NULL; frame_count_heuristic],
-            };
-            frame_op_count += 1;
-
-            if ch_colour.character() == Character::Popo {
-                frame_ops[frame_op_count] = FrameWriteOp {
-                    from_idx: i + 4,
-                    to: vec![Frame::NULL; frame_count_heuristic],
-                };
-                frame_op_count += 1;
-            }
-        }
-    }
-
-    let mut pre_frame_temp = [PreFrameUpdate::NULL; 8];
-    let mut post_frame_temp = [PostFrameUpdate::NULL; 8];
-
     // event parsing --------------------------------------------------------
 
+    let mut builder = GameBuilder::new(game_start);
+    let mut event_parser = EventParser::default();
+
     let mut event_cursor = game_start_offset + game_start_size;
     while event_cursor < metadata_offset {
         let event_cmd = slp[event_cursor];
@@ -89,69 +127,457 @@ pub fn parse_old_file(slp: &[u8]) -> SlpResult<Game> {
         let event_bytes = &slp[event_cursor..][..event_size];
         event_cursor += event_size;
 
-        match event_cmd {
-            PRE_FRAME_UPDATE => {
-                let pre_frame = parse_pre_frame_update(event_bytes)?;
-                let mut temp_idx = pre_frame.port_idx as usize;
-                if pre_frame.is_follower { temp_idx += 4 }
-                pre_frame_temp[temp_idx] = pre_frame;
+        if let Some(event) = event_parser.parse(event_bytes)? {
+            builder.push_event(event);
+            if builder.is_finished() { break; }
+        }
+    }
+
+    // finish up --------------------------------------------------------
+
+    let metadata = slp.get(metadata_offset..).and_then(parse_metadata);
+    Ok(builder.finish(metadata))
+}
+
+/// Parses a replay from any reader, one event at a time.
+/// Only the current event is held in memory, apart from what is accumulated into the game.
+pub fn parse_old_stream(reader: impl std::io::Read) -> SlpResult<FullGame> {
+    let mut parser = StreamParser::new(reader)?;
+    let mut builder = GameBuilder::new(parser.game_start().clone());
+
+    while let Some(event) = parser.next_event()? {
+        builder.push_event(event);
+    }
+
+    let metadata = parser.read_metadata()?;
+    Ok(builder.finish(metadata))
+}
+
+// EVENT PROCESSING ------------------------------------------------------------------------
+
+/// A single event from the raw event stream.
+#[derive(Clone, Debug)]
+pub enum Event {
+    PreFrameUpdate(PreFrameUpdate),
+    PostFrameUpdate(PostFrameUpdate),
+    ItemUpdate(ItemUpdate),
+    FrameStart(FrameStart),
+    FrameBookend { frame: i32 },
+    GameEnd(GameEnd),
+    GeckoList(crate::gecko::GeckoCodeList),
+    /// Any other event, identified by its command byte.
+    Other(u8),
+}
+
+/// Turns raw event bytes into `Event`s.
+/// Holds the pieces of split messages until they are complete.
+#[derive(Clone, Debug, Default)]
+pub struct EventParser {
+    split_message: Vec<u8>,
+}
+
+impl EventParser {
+    /// `event_bytes` includes the command byte.
+    /// Returns `None` for pieces of split messages that are not yet complete.
+    pub fn parse(&mut self, event_bytes: &[u8]) -> SlpResult<Option<Event>> {
+        let event_cmd = event_bytes[0];
+
+        let event = match event_cmd {
+            PRE_FRAME_UPDATE => Event::PreFrameUpdate(parse_pre_frame_update(event_bytes)?),
+            POST_FRAME_UPDATE => Event::PostFrameUpdate(parse_post_frame_update(event_bytes)?),
+            ITEM_UPDATE => match parse_item_update(event_bytes) {
+                Ok(item) => Event::ItemUpdate(item),
+                // items from before 3.6.0 are shorter, nothing uses them so don't fail the whole replay
+                Err(_) => Event::Other(event_cmd),
+            },
+            MESSAGE_SPLITTER => {
+                // large events are sent in 512 byte pieces
+                if event_bytes.len() < 0x205 { return Ok(Some(Event::Other(event_cmd))); }
+                let size = (read_u16(event_bytes, 0x201) as usize).min(512);
+                let internal_cmd = read_u8(event_bytes, 0x203);
+                let is_last = read_u8(event_bytes, 0x204) != 0;
+
+                self.split_message.extend_from_slice(&event_bytes[0x1..][..size]);
+                if !is_last { return Ok(None); }
+
+                let event = match internal_cmd {
+                    GECKO_LIST => Event::GeckoList(crate::gecko::parse_gecko_list(&self.split_message)),
+                    _ => Event::Other(internal_cmd),
+                };
+                self.split_message.clear();
+                event
+            }
+            GECKO_LIST => Event::GeckoList(crate::gecko::parse_gecko_list(&event_bytes[1..])),
+            FRAME_START => Event::FrameStart(parse_frame_start(event_bytes)),
+            FRAME_BOOKEND => Event::FrameBookend { frame: read_i32(event_bytes, 0x1) },
+            GAME_END => match parse_game_end(event_bytes) {
+                Some(game_end) => Event::GameEnd(game_end),
+                None => Event::Other(event_cmd),
+            },
+            _ => Event::Other(event_cmd),
+        };
+
+        // frames and ports are used as indices, so a corrupt or hostile stream can't be trusted with them.
+        // slp_parser has no location for frame events, post frame update is the closest
+        let bad_frame = SlpError::InvalidFile(InvalidLocation::PostFrameUpdate);
+        match &event {
+            Event::FrameStart(FrameStart { frame, .. })
+            | Event::FrameBookend { frame } if !(FIRST_FRAME..=MAX_FRAME).contains(frame) => {
+                return Err(bad_frame);
             }
-            POST_FRAME_UPDATE => {
-                let post_frame = parse_post_frame_update(event_bytes)?;
-                let mut temp_idx = post_frame.port_idx as usize;
-                if post_frame.is_follower { temp_idx += 4 }
-                post_frame_temp[temp_idx] = post_frame;
+            Event::PreFrameUpdate(PreFrameUpdate { port_idx, .. })
+            | Event::PostFrameUpdate(PostFrameUpdate { port_idx, .. }) if *port_idx >= 4 => {
+                return Err(bad_frame);
             }
-            FRAME_BOOKEND => {
-                let frame_idx = (read_i32(event_bytes, 0x1) + 123) as usize;
+            _ => {}
+        }
+
+        Ok(Some(event))
+    }
+}
+
+/// Holds the latest pre and post frame update for each character until the frame ends.
+/// Indexed by port, with followers (nana) at port + 4.
+#[derive(Clone, Debug)]
+pub struct FrameMerger {
+    pre_frame_temp: [PreFrameUpdate; 8],
+    post_frame_temp: [PostFrameUpdate; 8],
+}
+
+impl Default for FrameMerger {
+    fn default() -> Self {
+        FrameMerger {
+            pre_frame_temp: [PreFrameUpdate::NULL; 8],
+            post_frame_temp: [PostFrameUpdate::NULL; 8],
+        }
+    }
+}
+
+impl FrameMerger {
+    pub fn push_pre(&mut self, pre_frame: PreFrameUpdate) {
+        let mut temp_idx = pre_frame.port_idx as usize;
+        if pre_frame.is_follower { temp_idx += 4 }
+        self.pre_frame_temp[temp_idx] = pre_frame;
+    }
+
+    pub fn push_post(&mut self, post_frame: PostFrameUpdate) {
+        let mut temp_idx = post_frame.port_idx as usize;
+        if post_frame.is_follower { temp_idx += 4 }
+        self.post_frame_temp[temp_idx] = post_frame;
+    }
+
+    pub fn frame(&self, temp_idx: usize) -> Frame {
+        merge_pre_post_frames(&self.pre_frame_temp[temp_idx], &self.post_frame_temp[temp_idx])
+    }
+}
+
+/// Indices into `FrameMerger` for every character in the game, including followers.
+fn character_indices(game_start: &GameStart) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(8);
+    for i in 0..4 {
+        if let Some(ch_colour) = game_start.starting_character_colours[i] {
+            indices.push(i);
+            if ch_colour.character() == Character::Popo { indices.push(i + 4); }
+        }
+    }
+    indices
+}
+
+struct FrameWriteOp {
+    pub from_idx: usize,
+    pub to: Vec<Frame>,
+}
+
+/// Accumulates events into a `FullGame`.
+pub struct GameBuilder {
+    game_start: GameStart,
+    frame_ops: Vec<FrameWriteOp>,
+    merger: FrameMerger,
+    game_end: Option<GameEnd>,
+    frame_starts: Vec<Option<FrameStart>>,
+    rollback_frames: Vec<i32>,
+    gecko_codes: Option<crate::gecko::GeckoCodeList>,
+    frame_count: usize,
+}
+
+impl GameBuilder {
+    pub fn new(game_start: GameStart) -> GameBuilder {
+        let frame_count_heuristic = 1024;
+
+        let frame_ops = character_indices(&game_start)
+            .into_iter()
+            .map(|from_idx| FrameWriteOp { from_idx, to: vec![Frame::NULL; frame_count_heuristic] })
+            .collect();
+
+        GameBuilder {
+            game_start,
+            frame_ops,
+            merger: FrameMerger::default(),
+            game_end: None,
+            frame_starts: Vec::with_capacity(frame_count_heuristic),
+            rollback_frames: Vec::new(),
+            gecko_codes: None,
+            frame_count: 0,
+        }
+    }
+
+    pub fn game_start(&self) -> &GameStart { &self.game_start }
+
+    /// Number of frames that have finished, counting from frame -123.
+    pub fn frame_count(&self) -> usize { self.frame_count }
 
-                for i in 0..frame_op_count {
-                    let op = &mut frame_ops[i];
-                    let pre = &pre_frame_temp[op.from_idx];
-                    let post = &post_frame_temp[op.from_idx];
+    /// True once the game end event has been pushed.
+    pub fn is_finished(&self) -> bool { self.game_end.is_some() }
 
+    /// Frames so far for a port, or its follower at port + 4.
+    /// Frames that have not finished yet are `Frame::NULL`.
+    pub fn frames(&self, idx: usize) -> Option<&[Frame]> {
+        self.frame_ops.iter().find(|op| op.from_idx == idx).map(|op| op.to.as_slice())
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        match event {
+            Event::PreFrameUpdate(pre_frame) => self.merger.push_pre(pre_frame),
+            Event::PostFrameUpdate(post_frame) => self.merger.push_post(post_frame),
+            Event::GeckoList(codes) => self.gecko_codes = Some(codes),
+            Event::FrameStart(frame_start) => {
+                let frame_idx = (frame_start.frame - FIRST_FRAME) as usize;
+
+                if self.frame_starts.len() <= frame_idx { self.frame_starts.resize(frame_idx+1, None); }
+                if self.frame_starts[frame_idx].is_some() { self.rollback_frames.push(frame_start.frame); }
+                self.frame_starts[frame_idx] = Some(frame_start);
+            }
+            Event::FrameBookend { frame } => {
+                let frame_idx = (frame - FIRST_FRAME) as usize;
+                self.frame_count = self.frame_count.max(frame_idx + 1);
+
+                for op in self.frame_ops.iter_mut() {
                     // no need to special case rollback, just overwrite the frame
                     if op.to.len() <= frame_idx { op.to.resize(frame_idx+1, Frame::NULL); }
-                    op.to[frame_idx] = merge_pre_post_frames(pre, post);
+                    op.to[frame_idx] = self.merger.frame(op.from_idx);
                 }
             }
-            GAME_END => break,
-            _ => {}
+            Event::GameEnd(game_end) => self.game_end = Some(game_end),
+            // like `parse_old_file`, `Game::items` is left empty so memory doesn't grow with items
+            Event::ItemUpdate(_) | Event::Other(_) => {}
         }
     }
 
-    // finish up --------------------------------------------------------
+    pub fn finish(self, metadata: Option<Metadata>) -> FullGame {
+        let mut frames = [None, None, None, None];
+        let mut follower_frames = [None, None, None, None];
+
+        for mut op in self.frame_ops {
+            // drop the unused part of the prefilled frames
+            op.to.truncate(self.frame_count);
+            let to = Some(op.to.into_boxed_slice());
+            if op.from_idx < 4 {
+                frames[op.from_idx] = to;
+            } else {
+                follower_frames[op.from_idx - 4] = to;
+            }
+        }
+
+        let frame_count = frames.iter().flatten().next().map_or(0, |f| f.len());
+        let info = merge_metadata(self.game_start, metadata.as_ref(), frame_count);
+
+        let game = Game {
+            frame_count,
+            frames,
+            follower_frames,
+            info,
+            items: Box::new([]),
+            item_idx: Box::new([]),
+            stage_info: None,
+        };
+
+        FullGame {
+            game,
+            metadata,
+            game_end: self.game_end,
+            frame_starts: self.frame_starts.into_boxed_slice(),
+            rollback_frames: self.rollback_frames,
+            gecko_codes: self.gecko_codes,
+        }
+    }
+}
 
-    let info = merge_metadata(game_start);
+// STREAMING ------------------------------------------------------------------------
 
-    let mut frames = [None, None, None, None];
-    let mut follower_frames = [None, None, None, None];
+/// Every character's frame at a frame bookend.
+#[derive(Clone, Debug)]
+pub struct StreamFrame {
+    pub frame: i32,
+    pub frames: [Option<Frame>; 4],
+    pub follower_frames: [Option<Frame>; 4],
+}
 
-    for i in 0..frame_op_count {
-        let op = &mut frame_ops[i];
+/// Reads a replay event by event from any reader.
+///
+/// Use `next_event` (or iterate) to see every event, or `next_frame` to get merged frames
+/// one at a time without keeping the whole game in memory.
+pub struct StreamParser<R: std::io::Read> {
+    reader: R,
+    game_start: GameStart,
+    event_sizes: EventSizes,
+    /// Event bytes left in the raw element.
+    /// `None` if the length was written as 0, as Slippi does for replays that are still being written.
+    raw_remaining: Option<usize>,
+    event_buf: Vec<u8>,
+    event_parser: EventParser,
+    merger: FrameMerger,
+    character_indices: Vec<usize>,
+    game_end: Option<GameEnd>,
+    done: bool,
+}
 
-        let to = std::mem::replace(&mut op.to, Vec::new());
-        let to = Some(to.into_boxed_slice());
-        if op.from_idx < 4 {
-            frames[op.from_idx] = to;
-        } else {
-            follower_frames[op.from_idx - 4] = to;
+impl<R: std::io::Read> StreamParser<R> {
+    /// Reads the header, event sizes and game start.
+    pub fn new(mut reader: R) -> SlpResult<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(&header)?;
+        let raw_len = metadata_offset - event_sizes_offset;
+
+        let mut payloads = vec![0u8; 2];
+        reader.read_exact(&mut payloads)?;
+        let info_size = payloads[1] as usize;
+        if info_size < 1 { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)); }
+        payloads.resize(info_size + 1, 0);
+        reader.read_exact(&mut payloads[2..])?;
+        let EventSizesRet { game_start_offset: _, event_sizes } = event_sizes(&payloads, 0)?;
+
+        let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
+        let mut game_start_bytes = vec![0u8; game_start_size];
+        reader.read_exact(&mut game_start_bytes)?;
+        let game_start = parse_game_start(&game_start_bytes)?;
+
+        let raw_remaining = match raw_len {
+            0 => None,
+            _ => Some(raw_len.checked_sub(payloads.len() + game_start_size)
+                .ok_or(SlpError::InvalidFile(InvalidLocation::EventSizes))?),
+        };
+
+        Ok(StreamParser {
+            reader,
+            character_indices: character_indices(&game_start),
+            game_start,
+            event_sizes,
+            raw_remaining,
+            event_buf: Vec::with_capacity(1024),
+            event_parser: EventParser::default(),
+            merger: FrameMerger::default(),
+            game_end: None,
+            done: false,
+        })
+    }
+
+    pub fn game_start(&self) -> &GameStart { &self.game_start }
+
+    /// The game end event, once it has been read.
+    pub fn game_end(&self) -> Option<&GameEnd> { self.game_end.as_ref() }
+
+    /// Reads the next event into `event_buf`. Returns false at the end of the event data.
+    fn next_raw_event(&mut self) -> SlpResult<bool> {
+        if self.done || self.raw_remaining == Some(0) {
+            self.done = true;
+            return Ok(false);
         }
+
+        let mut event_cmd = [0u8];
+        if let Err(e) = self.reader.read_exact(&mut event_cmd) {
+            // without a raw length, the end of the file is the end of the events
+            if self.raw_remaining.is_none() && e.kind() == std::io::ErrorKind::UnexpectedEof {
+                self.done = true;
+                return Ok(false);
+            }
+            return Err(e.into());
+        }
+
+        let event_size = self.event_sizes[event_cmd[0] as usize] as usize + 1;
+        if let Some(remaining) = self.raw_remaining {
+            if event_size > remaining { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)); }
+            self.raw_remaining = Some(remaining - event_size);
+        }
+
+        self.event_buf.clear();
+        self.event_buf.resize(event_size, 0);
+        self.event_buf[0] = event_cmd[0];
+        self.reader.read_exact(&mut self.event_buf[1..])?;
+
+        Ok(true)
     }
 
-    let frame_count = frames.iter().find(|f| f.is_some()).unwrap().as_ref().unwrap().len();
+    /// Returns `None` after the game end event or at the end of the event data.
+    pub fn next_event(&mut self) -> SlpResult<Option<Event>> {
+        while self.next_raw_event()? {
+            if let Some(event) = self.event_parser.parse(&self.event_buf)? {
+                if let Event::GameEnd(game_end) = event {
+                    self.game_end = Some(game_end);
+                    self.done = true;
+                }
+                return Ok(Some(event));
+            }
+        }
 
-    let game = Game {
-        frame_count,
-        frames,
-        follower_frames,
-        info,
-        items: Box::new([]),
-        item_idx: Box::new([]),
-        stage_info: None,
-    };
+        Ok(None)
+    }
+
+    /// Reads events up to the next frame bookend and returns every character's frame.
+    /// Events other than frame updates are skipped, apart from the game end which is kept in `game_end`.
+    /// Rolled back frames are returned again each time they are replayed.
+    pub fn next_frame(&mut self) -> SlpResult<Option<StreamFrame>> {
+        while let Some(event) = self.next_event()? {
+            match event {
+                Event::PreFrameUpdate(pre_frame) => self.merger.push_pre(pre_frame),
+                Event::PostFrameUpdate(post_frame) => self.merger.push_post(post_frame),
+                Event::FrameBookend { frame } => {
+                    let mut stream_frame = StreamFrame {
+                        frame,
+                        frames: [None, None, None, None],
+                        follower_frames: [None, None, None, None],
+                    };
+
+                    for &idx in self.character_indices.iter() {
+                        let merged = Some(self.merger.frame(idx));
+                        if idx < 4 {
+                            stream_frame.frames[idx] = merged;
+                        } else {
+                            stream_frame.follower_frames[idx - 4] = merged;
+                        }
+                    }
+
+                    return Ok(Some(stream_frame));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
 
-    Ok(game)
+    /// Skips any remaining events and parses the metadata block.
+    pub fn read_metadata(mut self) -> SlpResult<Option<Metadata>> {
+        if let Some(remaining) = self.raw_remaining {
+            let mut rest = std::io::Read::take(&mut self.reader, remaining as u64);
+            std::io::copy(&mut rest, &mut std::io::sink())?;
+        } else {
+            // no raw length means no metadata
+            return Ok(None);
+        }
+
+        let mut metadata = Vec::new();
+        self.reader.read_to_end(&mut metadata)?;
+        Ok(parse_metadata(&metadata))
+    }
+}
+
+impl<R: std::io::Read> Iterator for StreamParser<R> {
+    type Item = SlpResult<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
 }
 
 // EVENTS ------------------------------------------------------------------------
@@ -172,7 +598,13 @@ pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
 
     let timer = read_u32(game_info_block, 0x10);
     
+    // display names and connect codes were added in 3.9.0
+    let has_names = game_start.len() >= 0x221 + 0xA*4;
+
     let mut starting_character_colours = [None; 4];
+    let mut names = [[0u8; 31]; 4];
+    let mut connect_codes = [[0u8; 10]; 4];
+
     for i in 0..4 {
         if read_u8(game_info_block, 0x61 + 0x24*i) == 3 { continue; }
 
@@ -182,14 +614,127 @@ pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
             .ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
 
         starting_character_colours[i] = Some(character_colour);
+
+        if has_names {
+            names[i] = read_array::<31>(game_start, 0x1A5 + 0x1F*i);
+            connect_codes[i] = read_array::<10>(game_start, 0x221 + 0xA*i);
+        }
     }
 
     Ok(GameStart {
         stage,
         starting_character_colours,
         timer,
-        names: [[0u8; 31]; 4],
-        connect_codes: [[0u8; 10]; 4],
+        names,
+        connect_codes,
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameStart {
+    pub frame: i32,
+    pub random_seed: u32,
+    /// Frames since the scene started. Added in 3.10.0.
+    pub scene_frame_counter: Option<u32>,
+}
+
+pub fn parse_frame_start(frame_start: &[u8]) -> FrameStart {
+    FrameStart {
+        frame               : read_i32(frame_start, 0x1),
+        random_seed         : read_u32(frame_start, 0x5),
+        scene_frame_counter : if frame_start.len() >= 0xD { Some(read_u32(frame_start, 0x9)) } else { None },
+    }
+}
+
+/// Returns the frame numbers between the first and last frame start that never had a frame start event.
+/// Empty for a continuous replay.
+pub fn missing_frames(frame_starts: &[Option<FrameStart>]) -> Vec<i32> {
+    let Some(first) = frame_starts.iter().position(|f| f.is_some()) else { return Vec::new() };
+    let last = frame_starts.iter().rposition(|f| f.is_some()).unwrap();
+
+    (first..=last)
+        .filter(|&i| frame_starts[i].is_none())
+        .map(|i| i as i32 + FIRST_FRAME)
+        .collect()
+}
+
+/// Returns the frame numbers where the scene frame counter does not advance by exactly one
+/// from the previous frame, indicating a desync or dropped frames in the recording.
+pub fn scene_counter_discontinuities(frame_starts: &[Option<FrameStart>]) -> Vec<i32> {
+    let mut discontinuities = Vec::new();
+    let mut prev_counter = None;
+
+    for frame_start in frame_starts.iter() {
+        let Some(FrameStart { frame, scene_frame_counter: Some(counter), .. }) = *frame_start else {
+            prev_counter = None;
+            continue;
+        };
+
+        if let Some(prev) = prev_counter {
+            if counter != prev + 1 { discontinuities.push(frame); }
+        }
+        prev_counter = Some(counter);
+    }
+
+    discontinuities
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameEndMethod {
+    /// Older replays only.
+    Unresolved,
+    Time,
+    Game,
+    /// Older replays only.
+    Resolved,
+    NoContest,
+    Unknown(u8),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GameEnd {
+    pub method: GameEndMethod,
+    /// Port of the player who quit out with L+R+A+Start. Added in 2.0.0.
+    pub lras_initiator: Option<u8>,
+    /// Placement of each port, 0 being the winner, -1 for empty ports. Added in 3.13.0.
+    pub placements: Option<[i8; 4]>,
+}
+
+impl GameEnd {
+    pub fn is_quit_out(&self) -> bool {
+        self.method == GameEndMethod::NoContest || self.lras_initiator.is_some()
+    }
+}
+
+/// Returns `None` if the event is too short to hold the end method.
+pub fn parse_game_end(game_end: &[u8]) -> Option<GameEnd> {
+    if game_end.len() < 2 || game_end[0] != GAME_END { return None; }
+
+    let method = match read_u8(game_end, 0x1) {
+        0 => GameEndMethod::Unresolved,
+        1 => GameEndMethod::Time,
+        2 => GameEndMethod::Game,
+        3 => GameEndMethod::Resolved,
+        7 => GameEndMethod::NoContest,
+        n => GameEndMethod::Unknown(n),
+    };
+
+    let lras_initiator = if game_end.len() > 0x2 {
+        u8::try_from(read_i8(game_end, 0x2)).ok()
+    } else {
+        None
+    };
+
+    let placements = if game_end.len() >= 0x7 {
+        Some(read_array::<4>(game_end, 0x3).map(|p| p as i8))
+    } else {
+        None
+    };
+
+    Some(GameEnd {
+        method,
+        lras_initiator,
+        placements,
     })
 }
 
@@ -232,7 +777,7 @@ pub mod buttons_mask {
 }
 
 #[derive(Copy, Clone, Debug)]
-struct PreFrameUpdate {
+pub struct PreFrameUpdate {
     pub port_idx: u8,
     pub is_follower: bool,
     pub buttons_mask: ButtonsMask,
@@ -270,7 +815,7 @@ fn parse_pre_frame_update(pre_frame_update: &[u8]) -> SlpResult<PreFrameUpdate>
 }
 
 #[derive(Copy, Clone, Debug)]
-struct PostFrameUpdate {
+pub struct PostFrameUpdate {
     pub port_idx: u8,
     pub is_follower: bool,
     pub character: Character,
@@ -390,6 +935,7 @@ pub fn event_sizes(slp: &[u8], event_sizes_offset: usize) -> SlpResult<EventSize
     if slp[event_sizes_offset] != EVENT_PAYLOADS { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)) }
 
     let info_size = slp[event_sizes_offset+1] as usize;
+    if info_size < 1 { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)) }
     if slp.len() < event_sizes_offset + info_size + 1 { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)) }
     let event_count = (info_size - 1) / 3;
 
@@ -426,7 +972,7 @@ pub fn parse_raw_header(slp: &[u8]) -> SlpResult<RawHeaderRet> {
     let raw_len = read_u32(slp, HEADER.len()) as usize;
     Ok(RawHeaderRet {
         event_sizes_offset: HEADER.len() + 4,
-        metadata_offset: HEADER.len() + raw_len,
+        metadata_offset: HEADER.len() + 4 + raw_len,
     })
 }
 
@@ -482,15 +1028,152 @@ pub fn parse_file_info_slpz(reader: &mut (impl std::io::Read + std::io::Seek)) -
     Ok(game_start)
 }
 
-fn merge_metadata(game_start: GameStart) -> GameInfo {
+fn merge_metadata(game_start: GameStart, metadata: Option<&Metadata>, frame_count: usize) -> GameInfo {
+    let start_time = metadata.and_then(|m| m.start_time).unwrap_or(Time(0));
+
+    let duration = match metadata.and_then(|m| m.last_frame) {
+        Some(last_frame) if last_frame >= FIRST_FRAME => (last_frame - FIRST_FRAME + 1) as u32,
+        _ => frame_count as u32,
+    };
+
     GameInfo {
         stage                      : game_start.stage,
         port_used                  : game_start.starting_character_colours.map(|c| c.is_some()),
         starting_character_colours : game_start.starting_character_colours,
-        start_time                 : Time(0),
+        start_time,
         timer                      : game_start.timer,
-        names: game_start.names,
-        connect_codes: game_start.connect_codes,
-        duration                   : 0,
+        names                      : game_start.names,
+        connect_codes              : game_start.connect_codes,
+        duration,
+    }
+}
+
+// METADATA ------------------------------------------------------------------------
+
+/// The UBJSON metadata block that follows the raw event data.
+/// Every field is optional, as older and in-progress replays may be missing any of them.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// `startAt` as written, e.g. `2020-07-12T18:31:45Z`.
+    pub start_at: Option<String>,
+    /// `startAt` as seconds since the unix epoch.
+    pub start_time: Option<Time>,
+    /// Index of the last frame, where the first playable frame is 0.
+    pub last_frame: Option<i32>,
+    /// `dolphin`, `network` or `nintendont`.
+    pub played_on: Option<String>,
+    pub console_nick: Option<String>,
+    pub players: [Option<MetadataPlayer>; 4],
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MetadataPlayer {
+    pub netplay_name: Option<String>,
+    pub netplay_code: Option<String>,
+    /// Internal character id and the number of frames played as that character.
+    pub characters: Vec<(u8, u32)>,
+}
+
+/// Parses the metadata block starting at `metadata_offset`.
+/// Returns `None` if it is missing or not valid UBJSON.
+pub fn parse_metadata(metadata: &[u8]) -> Option<Metadata> {
+    const KEY: &[u8] = b"U\x08metadata";
+
+    let value_bytes = metadata.strip_prefix(KEY)?;
+    let (value, _) = crate::ubjson::parse_value(value_bytes)?;
+    let crate::ubjson::Value::Object(_) = value else { return None };
+
+    let start_at = value.get("startAt").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let start_time = start_at.as_deref().and_then(parse_iso_8601);
+    let last_frame = value.get("lastFrame")
+        .and_then(|v| v.as_int())
+        .and_then(|n| i32::try_from(n).ok());
+    let played_on = value.get("playedOn").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let console_nick = value.get("consoleNick").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    let mut players: [Option<MetadataPlayer>; 4] = Default::default();
+    if let Some(crate::ubjson::Value::Object(entries)) = value.get("players") {
+        for (port, player) in entries.iter() {
+            let Ok(port) = port.parse::<usize>() else { continue };
+            if port >= 4 { continue; }
+
+            let names = player.get("names");
+            let netplay_name = names.and_then(|n| n.get("netplay")).and_then(|v| v.as_str()).map(|s| s.to_string());
+            let netplay_code = names.and_then(|n| n.get("code")).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+            let mut characters = Vec::new();
+            if let Some(crate::ubjson::Value::Object(chars)) = player.get("characters") {
+                for (character, frames) in chars.iter() {
+                    let Ok(character) = character.parse::<u8>() else { continue };
+                    let Some(frames) = frames.as_int().and_then(|n| u32::try_from(n).ok()) else { continue };
+                    characters.push((character, frames));
+                }
+            }
+
+            players[port] = Some(MetadataPlayer { netplay_name, netplay_code, characters });
+        }
+    }
+
+    Some(Metadata {
+        start_at,
+        start_time,
+        last_frame,
+        played_on,
+        console_nick,
+        players,
+    })
+}
+
+/// Parses a `YYYY-MM-DDTHH:MM:SS` timestamp with optional fractional seconds and zone,
+/// as written by Slippi, into seconds since the unix epoch.
+pub fn parse_iso_8601(s: &str) -> Option<Time> {
+    fn num(s: &str, range: std::ops::Range<usize>) -> Option<i64> {
+        let digits = s.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) { return None; }
+        digits.parse().ok()
+    }
+
+    let bytes = s.as_bytes();
+    if bytes.len() < 19 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T'
+        || bytes[13] != b':' || bytes[16] != b':' { return None; }
+
+    let year = num(s, 0..4)?;
+    let month = num(s, 5..7)?;
+    let day = num(s, 8..10)?;
+    let hour = num(s, 11..13)?;
+    let minute = num(s, 14..16)?;
+    let second = num(s, 17..19)?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None; }
+
+    // skip fractional seconds
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digit_count = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        rest = &fraction[digit_count..];
     }
+
+    let offset = match rest.as_bytes().first() {
+        None | Some(b'Z') => 0,
+        Some(b'+') | Some(b'-') => {
+            let sign = if rest.starts_with('-') { -1 } else { 1 };
+            let offset_hour = num(rest, 1..3)?;
+            let offset_minute = num(rest, 4..6).or(num(rest, 3..5))?;
+            sign * (offset_hour * 3600 + offset_minute * 60)
+        }
+        _ => return None,
+    };
+
+    // days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    if secs < 0 { return None; }
+    Some(Time(secs as u64))
 }
@@ -0,0 +1,163 @@
+//! Exporting search hits as a Slippi Dolphin playback queue, to watch each interaction in turn.
+//!
+//! Pass the written file to Dolphin with `-i <queue.json>`.
+
+use crate::*;
+
+#[derive(Copy, Clone, Debug)]
+pub struct QueueOptions {
+    /// Frames to show before the interaction starts.
+    pub lead_in: i32,
+    /// Frames to keep playing after the interaction ends, see `interaction_end_frame`.
+    pub lead_out: i32,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        QueueOptions {
+            lead_in: 60,
+            lead_out: 120,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueEntry {
+    /// A `.slp` file Dolphin can open.
+    pub path: String,
+    pub start_frame: i32,
+    pub end_frame: i32,
+}
+
+#[derive(Debug)]
+pub enum PlaybackError {
+    Io(String, std::io::Error),
+    Parse(String, slp_parser::SlpError),
+    /// Not a `.slp` or `.slpz` file, such as an `archive/entry` path for a replay that was never extracted.
+    UnsupportedSource(String),
+    /// The source replay has no interaction matching the row, so it isn't the replay the row came from.
+    MissingInteraction(String, i32),
+}
+
+impl std::fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlaybackError::Io(path, e) => write!(f, "could not access '{}': {}", path, e),
+            PlaybackError::Parse(path, e) => write!(f, "could not parse '{}': {}", path, e),
+            PlaybackError::UnsupportedSource(path) => write!(f, "'{}' is not a .slp or .slpz file Dolphin can be given", path),
+            PlaybackError::MissingInteraction(path, frame) => write!(f, "'{}' has no interaction on frame {} matching the row", path, frame),
+        }
+    }
+}
+
+impl std::error::Error for PlaybackError {}
+
+/// One entry per hit, in the order given. Hits without a source in `sources` are skipped.
+///
+/// Each source is parsed to find where the hit's interaction ends.
+/// `.slpz` sources are decompressed into `extract_dir`, once each, since Dolphin only opens `.slp` files.
+/// Entries inside archives and any other kind of file are refused with `PlaybackError::UnsupportedSource`.
+pub fn queue_entries(
+    hits: &[Row],
+    sources: &[String],
+    options: &QueueOptions,
+    extract_dir: &std::path::Path,
+) -> Result<Vec<QueueEntry>, PlaybackError> {
+    let mut resolved: std::collections::HashMap<u32, (String, slp_parser::Game)> = std::collections::HashMap::new();
+    let mut entries = Vec::with_capacity(hits.len());
+
+    for row in hits {
+        let Some(source) = sources.get(row.source as usize) else { continue };
+
+        let (path, game) = match resolved.entry(row.source) {
+            std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+            std::collections::hash_map::Entry::Vacant(e) => e.insert(resolve_source(source, extract_dir)?),
+        };
+
+        let end = interaction_end_frame(game, row)
+            .ok_or_else(|| PlaybackError::MissingInteraction(source.clone(), row.frame))?;
+
+        entries.push(QueueEntry {
+            path: path.clone(),
+            // replays start at frame -123
+            start_frame: (row.frame - options.lead_in).max(parse_old_game::FIRST_FRAME),
+            end_frame: end + options.lead_out,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Returns the `.slp` path to give Dolphin and the parsed game.
+fn resolve_source(source: &str, extract_dir: &std::path::Path) -> Result<(String, slp_parser::Game), PlaybackError> {
+    let path = std::path::Path::new(source);
+    let io_error = |e| PlaybackError::Io(source.to_string(), e);
+    let parse_error = |e| PlaybackError::Parse(source.to_string(), e);
+
+    if is_archive_entry(source) { return Err(PlaybackError::UnsupportedSource(source.to_string())); }
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("slp") => {
+            let slp = std::fs::read(path).map_err(io_error)?;
+            let game = parse_old_game::parse_old_file(&slp).map_err(parse_error)?;
+            Ok((source.to_string(), game))
+        }
+        Some("slpz") => {
+            let slpz = std::fs::read(path).map_err(io_error)?;
+            let mut decompressor = slpz::Decompressor::new()
+                .ok_or(PlaybackError::Parse(source.to_string(), slp_parser::SlpError::ZstdInitError))?;
+            let slp = slpz::decompress(&mut decompressor, &slpz)
+                .map_err(|_| parse_error(slp_parser::SlpError::InvalidFile(slp_parser::InvalidLocation::SlpzDecompression)))?;
+            let game = parse_old_game::parse_old_file(&slp).map_err(parse_error)?;
+
+            // named by the full source path so files with the same name in different directories don't collide
+            let extracted = extract_dir.join(format!("{:016x}.slp", fnv1a(FNV_OFFSET, source.as_bytes())));
+            std::fs::write(&extracted, &slp)
+                .map_err(|e| PlaybackError::Io(extracted.to_string_lossy().into_owned(), e))?;
+            Ok((extracted.to_string_lossy().into_owned(), game))
+        }
+        _ => Err(PlaybackError::UnsupportedSource(source.to_string())),
+    }
+}
+
+/// The frame the later of the row's two actions ends on, as `Row::frame` counts,
+/// found by generating the game's interactions again the way `build::push_game_rows` does.
+/// `None` if the game has no such interaction.
+pub fn interaction_end_frame(game: &slp_parser::Game, row: &Row) -> Option<i32> {
+    let (low, high) = game.info.low_high_ports()?;
+    let low_frames = game.frames[low].as_ref()?;
+    let high_frames = game.frames[high].as_ref()?;
+
+    let low_actions = slp_parser::parse_actions(low_frames);
+    let high_actions = slp_parser::parse_actions(high_frames);
+
+    let a = slp_parser::generate_interactions(game.info.stage, &low_actions, &high_actions, low_frames, high_frames);
+    let b = slp_parser::generate_interactions(game.info.stage, &high_actions, &low_actions, high_frames, low_frames);
+
+    // the row's situations are swapped relative to the interaction's, see build::interaction_row
+    a.into_iter().chain(b)
+        .find(|i| {
+            i.player_response.frame_start as i32 + parse_old_game::FIRST_FRAME == row.frame
+                && i.player_response.start_state == row.opponent_initiation.start_state
+                && i.player_response.action_taken == row.opponent_initiation.action_taken
+                && i.opponent_initiation.start_state == row.player_response.start_state
+                && i.opponent_initiation.action_taken == row.player_response.action_taken
+        })
+        .map(|i| i.opponent_initiation.frame_end.max(i.player_response.frame_end) as i32 + parse_old_game::FIRST_FRAME)
+}
+
+pub fn write_queue_json(entries: &[QueueEntry]) -> String {
+    let mut json = String::with_capacity(128 + entries.len() * 128);
+    json.push_str("{\n  \"mode\": \"queue\",\n  \"replay\": \"\",\n  \"isRealTimeMode\": false,\n  \"outputOverlayFiles\": true,\n  \"queue\": [");
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i != 0 { json.push(','); }
+        json.push_str("\n    {\"path\": ");
+        json::push_string(&mut json, &entry.path);
+        json.push_str(&format!(", \"startFrame\": {}, \"endFrame\": {}, \"gameStartAt\": \"\", \"gameStation\": \"\"}}",
+            entry.start_frame, entry.end_frame));
+    }
+
+    json.push_str("\n  ]\n}\n");
+    json
+}
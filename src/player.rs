@@ -0,0 +1,138 @@
+//! Connect codes and per-player rows, for scouting how one player responds compared to everyone else.
+
+use crate::*;
+
+/// Converts a connect code such as `ABCD#123` to its in-game Shift JIS form.
+/// Returns `None` if the code does not fit or contains characters other than ascii alphanumerics and '#'.
+pub fn connect_code_from_str(s: &str) -> Option<ConnectCode> {
+    let mut code = [0u8; 10];
+    let mut len = 0;
+
+    for c in s.bytes() {
+        if c == b'#' {
+            // fullwidth number sign
+            if len + 2 > code.len() { return None; }
+            code[len] = 0x81;
+            code[len+1] = 0x94;
+            len += 2;
+        } else if c.is_ascii_alphanumeric() {
+            if len + 1 > code.len() { return None; }
+            code[len] = c.to_ascii_uppercase();
+            len += 1;
+        } else {
+            return None;
+        }
+    }
+
+    Some(code)
+}
+
+/// Converts an in-game connect code back to its usual `ABCD#123` form.
+pub fn connect_code_to_string(code: &ConnectCode) -> String {
+    let mut s = String::with_capacity(code.len());
+
+    let mut i = 0;
+    while i < code.len() && code[i] != 0 {
+        if code[i] == 0x81 && code.get(i+1) == Some(&0x94) {
+            s.push('#');
+            i += 2;
+        } else {
+            if code[i].is_ascii() { s.push(code[i] as char); }
+            i += 1;
+        }
+    }
+
+    s
+}
+
+/// Returns the rows where the player with `code` took `player_response`.
+pub fn player_rows(rows: &[Row], code: &ConnectCode) -> Vec<Row> {
+    rows.iter()
+        .filter(|row| row.player_code == *code)
+        .cloned()
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ActionComparison {
+    pub action: slp_parser::HighLevelAction,
+
+    /// Number of this player's matching rows that took this action.
+    pub player_count: usize,
+    /// Fraction of this player's matching rows that took this action.
+    pub player_frequency: f32,
+    /// Mean score of this player's matching rows that took this action. Zero if never taken.
+    pub player_mean_score: f32,
+
+    /// As above, over every other player's matching rows.
+    pub population_count: usize,
+    pub population_frequency: f32,
+    pub population_mean_score: f32,
+}
+
+/// For each query, compares how often the player with `code` responds with each action
+/// against how often every other player in `rows` does.
+///
+/// Comparisons are sorted by the difference in frequency, actions this player favours first.
+pub fn compare_player(
+    rows: &[Row],
+    code: &ConnectCode,
+    queries: &[SearchQuery],
+) -> Vec<Vec<ActionComparison>> {
+    search(rows, queries)
+        .into_iter()
+        .map(|results| compare_results(&results, code))
+        .collect()
+}
+
+fn compare_results(results: &[Row], code: &ConnectCode) -> Vec<ActionComparison> {
+    let mut comparisons: Vec<ActionComparison> = Vec::new();
+    let mut player_total = 0usize;
+    let mut population_total = 0usize;
+
+    for row in results {
+        let action = row.player_response.action_taken;
+        let i = match comparisons.iter().position(|c| c.action == action) {
+            Some(i) => i,
+            None => {
+                comparisons.push(ActionComparison {
+                    action,
+                    player_count: 0,
+                    player_frequency: 0.0,
+                    player_mean_score: 0.0,
+                    population_count: 0,
+                    population_frequency: 0.0,
+                    population_mean_score: 0.0,
+                });
+                comparisons.len() - 1
+            }
+        };
+
+        // accumulate score sums in the mean fields, divided out below
+        let comparison = &mut comparisons[i];
+        if row.player_code == *code {
+            comparison.player_count += 1;
+            comparison.player_mean_score += row.score;
+            player_total += 1;
+        } else {
+            comparison.population_count += 1;
+            comparison.population_mean_score += row.score;
+            population_total += 1;
+        }
+    }
+
+    for c in comparisons.iter_mut() {
+        if c.player_count != 0 { c.player_mean_score /= c.player_count as f32; }
+        if c.population_count != 0 { c.population_mean_score /= c.population_count as f32; }
+        if player_total != 0 { c.player_frequency = c.player_count as f32 / player_total as f32; }
+        if population_total != 0 { c.population_frequency = c.population_count as f32 / population_total as f32; }
+    }
+
+    comparisons.sort_by(|a, b| {
+        let a_diff = a.player_frequency - a.population_frequency;
+        let b_diff = b.player_frequency - b.population_frequency;
+        b_diff.total_cmp(&a_diff)
+    });
+
+    comparisons
+}
@@ -0,0 +1,133 @@
+//! Searching a database with the interactions in a replay, without parsing it and building queries by hand.
+
+use crate::*;
+
+/// Which of the replay's interactions to search with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameSelection {
+    All,
+    /// For each responding player, the last interaction that started at or before this in-game frame, as `Row::frame` counts.
+    Frame(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct InteractionMatches {
+    /// Port of the player who responded.
+    pub port: usize,
+    pub player_code: ConnectCode,
+    /// As `Row::frame` counts.
+    pub frame: i32,
+    pub query: SearchQuery,
+    /// What the player did respond with, to compare with the rows'.
+    pub action_taken: slp_parser::HighLevelAction,
+    /// Rows within `SEARCH_DISTANCE` of the query.
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug)]
+pub enum ReplayQueryError {
+    Io(std::io::Error),
+    Parse(slp_parser::SlpError),
+    NotTwoPlayer,
+    /// Neither player is the database's player character against its opponent character.
+    /// Holds the replay's characters.
+    WrongMatchup(slp_parser::Character, slp_parser::Character),
+}
+
+impl std::fmt::Display for ReplayQueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayQueryError::Io(e) => write!(f, "could not read replay: {}", e),
+            ReplayQueryError::Parse(e) => write!(f, "could not parse replay: {}", e),
+            ReplayQueryError::NotTwoPlayer => write!(f, "replay is not a two player game"),
+            ReplayQueryError::WrongMatchup(a, b) => write!(
+                f, "replay is {} against {}, which the database has no rows for",
+                names::character_name(*a), names::character_name(*b),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayQueryError {}
+
+/// Reads and parses a `.slp` or `.slpz` replay, then searches with its interactions, see `query_game`.
+pub fn query_replay(
+    db: &Database,
+    path: &std::path::Path,
+    selection: FrameSelection,
+) -> Result<Vec<InteractionMatches>, ReplayQueryError> {
+    let bytes = std::fs::read(path).map_err(ReplayQueryError::Io)?;
+    let game = if path.extension().is_some_and(|e| e == "slpz") {
+        parse_old_game::parse_old_file_slpz(&bytes)
+    } else {
+        parse_old_game::parse_old_file(&bytes)
+    };
+    query_game(db, &game.map_err(ReplayQueryError::Parse)?, selection)
+}
+
+/// Searches with the selected interactions of each player who is the database's player character
+/// against its opponent character, both players in a ditto. Queries match rows the way
+/// `build::push_game_rows` would have made them from this game, unscored interactions included.
+///
+/// Results are in frame order.
+pub fn query_game(
+    db: &Database,
+    game: &slp_parser::Game,
+    selection: FrameSelection,
+) -> Result<Vec<InteractionMatches>, ReplayQueryError> {
+    let (low, high) = game.info.low_high_ports().ok_or(ReplayQueryError::NotTwoPlayer)?;
+    let character = |port: usize| game.info.starting_character_colours[port].map(|c| c.character());
+    let (Some(low_character), Some(high_character)) = (character(low), character(high)) else {
+        return Err(ReplayQueryError::NotTwoPlayer);
+    };
+
+    let low_frames = game.frames[low].as_ref().ok_or(ReplayQueryError::NotTwoPlayer)?;
+    let high_frames = game.frames[high].as_ref().ok_or(ReplayQueryError::NotTwoPlayer)?;
+
+    let low_actions = slp_parser::parse_actions(low_frames);
+    let high_actions = slp_parser::parse_actions(high_frames);
+
+    // same perspectives as build::push_game_rows
+    let perspectives = [
+        (high, high_character, low_character, &low_actions, &high_actions, &low_frames[..], &high_frames[..]),
+        (low, low_character, high_character, &high_actions, &low_actions, &high_frames[..], &low_frames[..]),
+    ];
+
+    let mut found = Vec::new();
+    let mut any_matchup = false;
+    for (port, pl_character, op_character, first_actions, second_actions, first_frames, second_frames) in perspectives {
+        if pl_character != db.header.player_character || op_character != db.header.opponent_character { continue; }
+        any_matchup = true;
+
+        let interactions = slp_parser::generate_interactions(
+            game.info.stage, first_actions, second_actions, first_frames, second_frames,
+        );
+
+        let mut selected = interactions.into_iter()
+            .map(|interaction| InteractionMatches {
+                port,
+                player_code: game.info.connect_codes[port],
+                frame: interaction.player_response.frame_start as i32 + parse_old_game::FIRST_FRAME,
+                query: build::interaction_query(interaction, first_frames, second_frames),
+                action_taken: interaction.opponent_initiation.action_taken,
+                rows: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        if let FrameSelection::Frame(frame) = selection {
+            selected = selected.into_iter().filter(|m| m.frame <= frame).max_by_key(|m| m.frame).into_iter().collect();
+        }
+
+        found.extend(selected);
+    }
+
+    if !any_matchup { return Err(ReplayQueryError::WrongMatchup(low_character, high_character)); }
+
+    found.sort_by_key(|m| (m.frame, m.port));
+    let queries = found.iter().map(|m| m.query.clone()).collect::<Vec<_>>();
+    for (m, rows) in found.iter_mut().zip(db.search(&queries)) {
+        m.rows = rows;
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,210 @@
+//! A JSON HTTP API over one or more loaded databases, for tools that can't link this crate.
+//!
+//! - `GET /health`
+//! - `GET /metadata`: each matchup's characters, row and source counts.
+//! - `POST /search` with `{"matchup": .., "queries": [SearchQuery, ..]}`: the rows within `SEARCH_DISTANCE` of each query.
+//! - `POST /nearest` with `{"matchup": .., "query": SearchQuery, "k": 10}`: the `k` nearest rows, see `analysis::nearest`.
+//! - `POST /actions` with `{"matchup": .., "query": SearchQuery, "k": ..}`: the player's responses summarized,
+//!   over the search results, or the `k` nearest rows if `k` is given. See `analysis::summarize_actions`.
+//!
+//! Queries, rows and summaries are written as with the `serde` feature, with states, actions and characters by name.
+//! `matchup` is the `matchup_name` of a loaded database and can be left out if only one is loaded.
+//! Errors are `{"error": ".."}` with a 4xx status.
+//! `OPTIONS` on any of these paths answers a CORS preflight with 204 and no body.
+
+use crate::*;
+use crate::analysis::*;
+
+/// Request bodies larger than this are refused.
+pub const MAX_BODY_SIZE: usize = 16 << 20;
+
+/// `k` for `/nearest` if the request doesn't give one.
+pub const DEFAULT_K: usize = 10;
+
+#[derive(Debug)]
+pub enum ServerError {
+    Bind(Box<dyn std::error::Error + Send + Sync>),
+    /// Two databases with the same characters were given.
+    DuplicateMatchup(String),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ServerError::Bind(e) => write!(f, "could not listen: {}", e),
+            ServerError::DuplicateMatchup(name) => write!(f, "more than one database for {}, merge them first", name),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// "Player_Opponent", as `merge::split_by_character` names its parts.
+pub fn matchup_name(header: &Header) -> String {
+    format!("{}_{}", names::character_name(header.player_character), names::character_name(header.opponent_character))
+}
+
+pub struct Matchup {
+    pub name: String,
+    pub db: Database,
+}
+
+/// The loaded databases and the request handling, without the HTTP server.
+pub struct Api {
+    pub matchups: Vec<Matchup>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SearchRequest {
+    matchup: Option<String>,
+    queries: Vec<SearchQuery>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NearestRequest {
+    matchup: Option<String>,
+    query: SearchQuery,
+    k: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+struct Hit<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f32>,
+    row: &'a Row,
+    /// `None` for rows without a source.
+    source_path: Option<&'a str>,
+}
+
+impl Hit<'_> {
+    fn new<'a>(db: &'a Database, row: &'a Row, distance: Option<f32>) -> Hit<'a> {
+        Hit { distance, row, source_path: db.sources.get(row.source as usize).map(|s| s.as_str()) }
+    }
+}
+
+type Response = (u16, String);
+
+fn error(status: u16, message: impl std::fmt::Display) -> Response {
+    (status, serde_json::json!({ "error": message.to_string() }).to_string())
+}
+
+fn ok(value: serde_json::Value) -> Response {
+    (200, value.to_string())
+}
+
+impl Api {
+    pub fn new(dbs: Vec<Database>) -> Result<Api, ServerError> {
+        let mut matchups: Vec<Matchup> = Vec::with_capacity(dbs.len());
+        for db in dbs {
+            let name = matchup_name(&db.header);
+            if matchups.iter().any(|m| m.name == name) { return Err(ServerError::DuplicateMatchup(name)); }
+            matchups.push(Matchup { name, db });
+        }
+        Ok(Api { matchups })
+    }
+
+    fn matchup(&self, name: Option<&str>) -> Result<&Matchup, Response> {
+        match name {
+            Some(name) => self.matchups.iter()
+                .find(|m| m.name == name)
+                .ok_or_else(|| error(404, format_args!("no matchup '{}' is loaded", name))),
+            None if self.matchups.len() == 1 => Ok(&self.matchups[0]),
+            None => Err(error(400, "more than one matchup is loaded, give 'matchup'")),
+        }
+    }
+
+    /// Returns the status code and JSON body, empty for a preflight. `path` may have a query string, which is ignored.
+    pub fn handle(&self, method: &str, path: &str, body: &[u8]) -> (u16, String) {
+        let path = path.split('?').next().unwrap_or(path);
+        let result = match (method, path) {
+            ("GET", "/health") => Ok(ok(serde_json::json!({ "status": "ok", "matchups": self.matchups.len() }))),
+            ("GET", "/metadata") => Ok(self.metadata()),
+            ("POST", "/search") => parse(body).and_then(|r| self.search(r)),
+            ("POST", "/nearest") => parse(body).and_then(|r| self.nearest(r)),
+            ("POST", "/actions") => parse(body).and_then(|r| self.actions(r)),
+            ("OPTIONS", "/health" | "/metadata" | "/search" | "/nearest" | "/actions") => Ok((204, String::new())),
+            (_, "/health" | "/metadata" | "/search" | "/nearest" | "/actions") => Err(error(405, "method not allowed")),
+            _ => Err(error(404, "not found")),
+        };
+        result.unwrap_or_else(|e| e)
+    }
+
+    fn metadata(&self) -> Response {
+        let matchups = self.matchups.iter()
+            .map(|m| serde_json::json!({
+                "name": m.name,
+                "version": m.db.header.version,
+                "player_character": names::character_name(m.db.header.player_character),
+                "opponent_character": names::character_name(m.db.header.opponent_character),
+                "row_count": m.db.rows.len(),
+                "source_count": m.db.sources.len(),
+                "sorted": !m.db.header.state_index.is_empty(),
+            }))
+            .collect::<Vec<_>>();
+        ok(serde_json::json!({ "search_distance": SEARCH_DISTANCE, "matchups": matchups }))
+    }
+
+    fn search(&self, request: SearchRequest) -> Result<Response, Response> {
+        let m = self.matchup(request.matchup.as_deref())?;
+        let results = m.db.search(&request.queries);
+        let results = results.iter()
+            .map(|rows| rows.iter().map(|row| Hit::new(&m.db, row, None)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        Ok(ok(serde_json::json!({ "matchup": m.name, "results": results })))
+    }
+
+    fn nearest(&self, request: NearestRequest) -> Result<Response, Response> {
+        let m = self.matchup(request.matchup.as_deref())?;
+        let neighbours = m.db.nearest(&request.query, request.k.unwrap_or(DEFAULT_K));
+        let neighbours = neighbours.iter()
+            .map(|n| Hit::new(&m.db, &n.row, Some(n.distance)))
+            .collect::<Vec<_>>();
+        Ok(ok(serde_json::json!({ "matchup": m.name, "neighbours": neighbours })))
+    }
+
+    fn actions(&self, request: NearestRequest) -> Result<Response, Response> {
+        let m = self.matchup(request.matchup.as_deref())?;
+        let rows = match request.k {
+            Some(k) => m.db.nearest(&request.query, k).into_iter().map(|n| n.row).collect(),
+            None => m.db.search(std::slice::from_ref(&request.query)).pop().unwrap(),
+        };
+        let actions = summarize_actions(&rows);
+        Ok(ok(serde_json::json!({ "matchup": m.name, "row_count": rows.len(), "actions": actions })))
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, Response> {
+    serde_json::from_slice(body).map_err(|e| error(400, format_args!("bad request body: {}", e)))
+}
+
+/// Listens on `addr`. Port 0 picks a free port, see `tiny_http::Server::server_addr`.
+pub fn bind(addr: impl std::net::ToSocketAddrs) -> Result<tiny_http::Server, ServerError> {
+    tiny_http::Server::http(addr).map_err(ServerError::Bind)
+}
+
+/// Answers requests one at a time until `server` is unblocked.
+pub fn serve(server: &tiny_http::Server, api: &Api) {
+    for mut request in server.incoming_requests() {
+        let mut body = Vec::new();
+        let (status, json) = match std::io::Read::read_to_end(
+            &mut std::io::Read::take(request.as_reader(), MAX_BODY_SIZE as u64 + 1),
+            &mut body,
+        ) {
+            Err(e) => error(400, format_args!("could not read request body: {}", e)),
+            Ok(_) if body.len() > MAX_BODY_SIZE => error(413, "request body too large"),
+            Ok(_) => api.handle(request.method().as_str(), request.url(), &body),
+        };
+
+        let response = tiny_http::Response::from_string(json)
+            .with_status_code(status)
+            .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"Access-Control-Allow-Origin"[..], &b"*"[..]).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"Access-Control-Allow-Methods"[..], &b"GET, POST, OPTIONS"[..]).unwrap())
+            .with_header(tiny_http::Header::from_bytes(&b"Access-Control-Allow-Headers"[..], &b"Content-Type"[..]).unwrap());
+
+        // the client hanging up early is its own problem
+        let _ = request.respond(response);
+    }
+}
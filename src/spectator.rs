@@ -0,0 +1,255 @@
+//! Client for the Slippi console mirroring protocol, as spoken by Nintendont and Dolphin's spectator server.
+//!
+//! Messages are a big endian u32 length followed by a UBJSON object with a `type` and `payload`.
+//! Replay messages carry raw event bytes, starting with the event payloads event of each game.
+
+use crate::parse_old_game::{self, Event, EventParser, EventSizesRet, FullGame, GameBuilder, EVENT_PAYLOADS, GAME_START};
+use crate::ubjson::{self, Value};
+use slp_parser::{SlpError, SlpResult, InvalidLocation};
+
+pub const DEFAULT_PORT: u16 = 51441;
+
+pub const MESSAGE_HANDSHAKE: i64 = 1;
+pub const MESSAGE_REPLAY: i64 = 2;
+pub const MESSAGE_KEEP_ALIVE: i64 = 3;
+
+/// Messages larger than this are rejected rather than allocated.
+const MAX_MESSAGE_LEN: usize = 1 << 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Handshake {
+        nick: Option<String>,
+        version: Option<String>,
+        /// Position of the next replay message.
+        pos: u64,
+    },
+    Replay {
+        pos: u64,
+        next_pos: u64,
+        data: Vec<u8>,
+    },
+    KeepAlive,
+}
+
+impl Message {
+    pub fn from_value(value: &Value) -> Option<Message> {
+        let payload = value.get("payload");
+        let cursor = |key: &str| -> Option<u64> {
+            let bytes = payload?.get(key)?.as_bytes()?;
+            Some(u64::from_be_bytes(bytes.try_into().ok()?))
+        };
+
+        Some(match value.get("type")?.as_int()? {
+            MESSAGE_HANDSHAKE => Message::Handshake {
+                nick: payload.and_then(|p| p.get("nick")).and_then(Value::as_str).map(str::to_owned),
+                version: payload.and_then(|p| p.get("nintendontVersion")).and_then(Value::as_str).map(str::to_owned),
+                pos: cursor("pos").unwrap_or(0),
+            },
+            MESSAGE_REPLAY => Message::Replay {
+                pos: cursor("pos")?,
+                next_pos: cursor("nextPos")?,
+                data: payload?.get("data")?.as_bytes()?.to_vec(),
+            },
+            MESSAGE_KEEP_ALIVE => Message::KeepAlive,
+            _ => return None,
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        let cursor = |pos: u64| Value::Bytes(pos.to_be_bytes().to_vec());
+
+        let (typ, payload) = match self {
+            Message::Handshake { nick, version, pos } => {
+                let mut entries = vec![("pos".to_string(), cursor(*pos))];
+                if let Some(nick) = nick { entries.push(("nick".to_string(), Value::String(nick.clone()))); }
+                if let Some(version) = version {
+                    entries.push(("nintendontVersion".to_string(), Value::String(version.clone())));
+                }
+                (MESSAGE_HANDSHAKE, entries)
+            }
+            Message::Replay { pos, next_pos, data } => (MESSAGE_REPLAY, vec![
+                ("pos".to_string(), cursor(*pos)),
+                ("nextPos".to_string(), cursor(*next_pos)),
+                ("data".to_string(), Value::Bytes(data.clone())),
+            ]),
+            Message::KeepAlive => (MESSAGE_KEEP_ALIVE, Vec::new()),
+        };
+
+        Value::Object(vec![
+            ("type".to_string(), Value::Int(typ)),
+            ("payload".to_string(), Value::Object(payload)),
+        ])
+    }
+}
+
+/// Reads one length prefixed UBJSON message.
+/// Returns `None` if the connection was closed between messages.
+pub fn read_message(reader: &mut impl std::io::Read) -> std::io::Result<Option<Value>> {
+    let mut len = [0u8; 4];
+    let mut read = 0;
+    while read < 4 {
+        match reader.read(&mut len[read..])? {
+            0 if read == 0 => return Ok(None),
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN { return Err(std::io::ErrorKind::InvalidData.into()); }
+
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    match ubjson::parse_value(&bytes) {
+        Some((value, _)) => Ok(Some(value)),
+        None => Err(std::io::ErrorKind::InvalidData.into()),
+    }
+}
+
+pub fn write_message(writer: &mut impl std::io::Write, value: &Value) -> std::io::Result<()> {
+    let mut bytes = Vec::new();
+    ubjson::write_value(&mut bytes, value);
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Receives games from a console or Dolphin spectator server.
+pub struct SpectatorClient<S: std::io::Read + std::io::Write> {
+    stream: S,
+    /// Position of the next replay message we haven't seen.
+    cursor: u64,
+    nick: Option<String>,
+    feed: EventFeed,
+}
+
+impl SpectatorClient<std::net::TcpStream> {
+    pub fn connect(addr: impl std::net::ToSocketAddrs) -> SlpResult<Self> {
+        SpectatorClient::new(std::net::TcpStream::connect(addr)?)
+    }
+}
+
+impl<S: std::io::Read + std::io::Write> SpectatorClient<S> {
+    /// Sends the handshake and waits for the server's.
+    pub fn new(mut stream: S) -> SlpResult<Self> {
+        let handshake = Value::Object(vec![
+            ("type".to_string(), Value::Int(MESSAGE_HANDSHAKE)),
+            ("payload".to_string(), Value::Object(vec![
+                ("cursor".to_string(), Value::Bytes(0u64.to_be_bytes().to_vec())),
+                ("clientToken".to_string(), Value::Bytes(0u32.to_be_bytes().to_vec())),
+                ("isRealtime".to_string(), Value::Bool(false)),
+            ])),
+        ]);
+        write_message(&mut stream, &handshake)?;
+
+        loop {
+            let value = read_message(&mut stream)?.ok_or(SlpError::IOError)?;
+            match Message::from_value(&value) {
+                Some(Message::Handshake { nick, pos, .. }) => {
+                    return Ok(SpectatorClient { stream, cursor: pos, nick, feed: EventFeed::default() });
+                }
+                Some(Message::KeepAlive) => continue,
+                _ => return Err(SlpError::IOError),
+            }
+        }
+    }
+
+    /// Console nickname from the server's handshake.
+    pub fn nick(&self) -> Option<&str> { self.nick.as_deref() }
+
+    /// Blocks until the next game ends.
+    /// Returns `None` once the server closes the connection between games.
+    /// A game cut off by the connection closing is returned without a game end.
+    pub fn next_game(&mut self) -> SlpResult<Option<FullGame>> {
+        loop {
+            if let Some(game) = self.feed.next_game()? { return Ok(Some(game)); }
+
+            let Some(value) = read_message(&mut self.stream)? else {
+                return Ok(self.feed.finish());
+            };
+
+            match Message::from_value(&value) {
+                Some(Message::Replay { pos, next_pos, data }) => {
+                    // resent after a reconnect
+                    if pos < self.cursor { continue; }
+                    self.cursor = next_pos;
+                    self.feed.push(&data);
+                }
+                Some(Message::KeepAlive) | Some(Message::Handshake { .. }) | None => {}
+            }
+        }
+    }
+}
+
+impl<S: std::io::Read + std::io::Write> Iterator for SpectatorClient<S> {
+    type Item = SlpResult<FullGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_game().transpose()
+    }
+}
+
+/// Raw event bytes without the replay header, as sent by the console.
+/// Runs each game through `EventParser` and `GameBuilder`, the same as `parse_old_file_full`.
+#[derive(Default)]
+pub struct EventFeed {
+    buf: Vec<u8>,
+    event_sizes: Option<[u16; 255]>,
+    builder: Option<GameBuilder>,
+    event_parser: EventParser,
+}
+
+impl EventFeed {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Parses every complete event pushed so far. Returns the game once its game end event arrives.
+    pub fn next_game(&mut self) -> SlpResult<Option<FullGame>> {
+        let mut cursor = 0;
+        let mut game = None;
+
+        while game.is_none() {
+            let Some(&event_cmd) = self.buf.get(cursor) else { break };
+
+            let Some(event_sizes) = self.event_sizes else {
+                if event_cmd != EVENT_PAYLOADS { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)); }
+                let Some(&info_size) = self.buf.get(cursor + 1) else { break };
+                if self.buf.len() < cursor + info_size as usize + 1 { break; }
+
+                let EventSizesRet { event_sizes, .. } = parse_old_game::event_sizes(&self.buf[cursor..], 0)?;
+                self.event_sizes = Some(event_sizes);
+                cursor += info_size as usize + 1;
+                continue;
+            };
+
+            let event_size = event_sizes[event_cmd as usize] as usize + 1;
+            let Some(event_bytes) = self.buf.get(cursor..cursor + event_size) else { break };
+            cursor += event_size;
+
+            let Some(builder) = self.builder.as_mut() else {
+                if event_cmd != GAME_START { return Err(SlpError::InvalidFile(InvalidLocation::GameStart)); }
+                self.builder = Some(GameBuilder::new(parse_old_game::parse_game_start(event_bytes)?));
+                continue;
+            };
+
+            if let Some(event) = self.event_parser.parse(event_bytes)? {
+                let is_end = matches!(event, Event::GameEnd(_));
+                builder.push_event(event);
+                if is_end { game = self.finish(); }
+            }
+        }
+
+        self.buf.drain(..cursor);
+        Ok(game)
+    }
+
+    /// Finishes the current game, if one has started, and resets for the next.
+    /// Console games have no metadata block.
+    pub fn finish(&mut self) -> Option<FullGame> {
+        self.event_sizes = None;
+        self.event_parser = EventParser::default();
+        self.builder.take().map(|builder| builder.finish(None))
+    }
+}
@@ -0,0 +1,356 @@
+//! SQLite storage for ad-hoc SQL over rows. Enabled with the `sqlite` feature.
+//!
+//! Tables:
+//! - `header`: one row of the database header, with character names alongside their ids.
+//! - `sources`: the source manifest, `id` is the index rows refer to, with the `stage` the game was played on.
+//! - `players`: each player in each source's game, by `source` and `port`, with their character and connect code.
+//! - `rows`: one row per `Row` in order, with state and action names alongside their codes.
+//!   `source` is null for `Row::NO_SOURCE`, and NaN positions and scores are null.
+//!   Indexed on the start state pair.
+//! - `interactions`: a view of `rows` with each source's path as `source_path`, its `stage`,
+//!   and the responding player's `player_port` and `player_game_character`, found by connect code.
+//!
+//! The game context is read from the source replays when the file is created.
+//! Sources that can't be read, such as entries inside archives, have a null `stage` and no players,
+//! and offline games, without connect codes, have a null `player_port` and `player_game_character`.
+//!
+//! For example, the average score of each player response to an opponent's state:
+//!
+//! ```sql
+//! SELECT player_action_name, AVG(score), COUNT(*) FROM rows
+//! WHERE opponent_state_name = 'Standard(DashAttack)' GROUP BY player_action_name;
+//! ```
+//!
+//! or the same on one stage, from the `interactions` view:
+//!
+//! ```sql
+//! SELECT player_action_name, AVG(score), COUNT(*) FROM interactions
+//! WHERE opponent_state_name = 'Standard(DashAttack)' AND stage = 'FinalDestination' GROUP BY player_action_name;
+//! ```
+
+use crate::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+const SCHEMA: &str = "
+CREATE TABLE header (
+    version INTEGER NOT NULL,
+    player_character INTEGER NOT NULL,
+    player_character_name TEXT NOT NULL,
+    opponent_character INTEGER NOT NULL,
+    opponent_character_name TEXT NOT NULL,
+    layout TEXT NOT NULL,
+    sorted INTEGER NOT NULL
+);
+CREATE TABLE sources (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    stage TEXT
+);
+CREATE TABLE players (
+    source INTEGER NOT NULL REFERENCES sources (id),
+    port INTEGER NOT NULL,
+    character INTEGER NOT NULL,
+    character_name TEXT NOT NULL,
+    connect_code BLOB NOT NULL,
+    PRIMARY KEY (source, port)
+);
+CREATE TABLE rows (
+    id INTEGER PRIMARY KEY,
+    opponent_state INTEGER NOT NULL,
+    opponent_state_name TEXT NOT NULL,
+    opponent_action INTEGER NOT NULL,
+    opponent_action_name TEXT NOT NULL,
+    opponent_x REAL,
+    opponent_y REAL,
+    player_state INTEGER NOT NULL,
+    player_state_name TEXT NOT NULL,
+    player_action INTEGER NOT NULL,
+    player_action_name TEXT NOT NULL,
+    player_x REAL,
+    player_y REAL,
+    score REAL,
+    weight REAL,
+    player_code BLOB NOT NULL,
+    source INTEGER REFERENCES sources (id),
+    frame INTEGER NOT NULL
+);
+CREATE INDEX rows_state_pair ON rows (opponent_state, player_state);
+CREATE VIEW interactions AS
+    SELECT rows.*, sources.path AS source_path, sources.stage AS stage,
+        players.port AS player_port, players.character_name AS player_game_character
+    FROM rows
+    LEFT JOIN sources ON sources.id = rows.source
+    LEFT JOIN players ON players.source = rows.source
+        AND players.connect_code = rows.player_code AND rows.player_code != zeroblob(10);
+";
+
+/// Columns read back into a `Row`, in `read_rows` order.
+const ROW_COLUMNS: &str = "opponent_state, opponent_action, opponent_x, opponent_y,
+    player_state, player_action, player_x, player_y, score, weight, player_code, source, frame, id";
+
+#[derive(Debug)]
+pub enum SqliteError {
+    Sqlite(rusqlite::Error),
+    /// The file has no header row, or it can't be read.
+    BadHeader,
+    /// A value is out of range, such as a state code the character doesn't have.
+    BadValue { column: &'static str, row: usize },
+}
+
+impl std::fmt::Display for SqliteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqliteError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            SqliteError::BadHeader => write!(f, "missing or invalid header"),
+            SqliteError::BadValue { column, row } => write!(f, "invalid value in column '{}' in row {}", column, row),
+        }
+    }
+}
+
+impl std::error::Error for SqliteError {}
+
+impl From<rusqlite::Error> for SqliteError {
+    fn from(e: rusqlite::Error) -> Self { SqliteError::Sqlite(e) }
+}
+
+pub struct SqliteDatabase {
+    /// Always has an empty state index, see `sorted`.
+    pub header: Header,
+    /// Whether the rows were sorted with `sort_database`, so `read_database` can rebuild the state index.
+    pub sorted: bool,
+    connection: Connection,
+}
+
+impl SqliteDatabase {
+    /// Writes `db` to a new SQLite file, with the game context read from each source replay.
+    /// Fails if the file already holds a database.
+    pub fn create(path: impl AsRef<std::path::Path>, db: &Database) -> Result<SqliteDatabase, SqliteError> {
+        let game_starts = db.sources.iter().map(|s| merge::read_game_start(s).ok()).collect::<Vec<_>>();
+        SqliteDatabase::create_with_game_starts(path, db, &game_starts)
+    }
+
+    /// `create` with the game start of each source already read, `None` for sources without context.
+    pub fn create_with_game_starts(
+        path: impl AsRef<std::path::Path>,
+        db: &Database,
+        game_starts: &[Option<slp_parser::GameStart>],
+    ) -> Result<SqliteDatabase, SqliteError> {
+        let mut connection = Connection::open(path)?;
+        write(&mut connection, db, game_starts)?;
+
+        let header = Header {
+            row_count: db.rows.len() as u32,
+            source_count: db.sources.len() as u32,
+            state_index: Vec::new(),
+            ..db.header.clone()
+        };
+        Ok(SqliteDatabase { header, sorted: !db.header.state_index.is_empty(), connection })
+    }
+
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<SqliteDatabase, SqliteError> {
+        let connection = Connection::open(path)?;
+        let (header, sorted) = read_header(&connection)?;
+        Ok(SqliteDatabase { header, sorted, connection })
+    }
+
+    /// For ad-hoc queries, see the module docs for the tables.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn sources(&self) -> Result<Vec<String>, SqliteError> {
+        let mut statement = self.connection.prepare("SELECT path FROM sources ORDER BY id")?;
+        let sources = statement.query_map([], |r| r.get(0))?.collect::<Result<Vec<String>, _>>()?;
+        Ok(sources)
+    }
+
+    pub fn read_database(&self) -> Result<Database, SqliteError> {
+        let mut statement = self.connection.prepare(&format!("SELECT {} FROM rows ORDER BY id", ROW_COLUMNS))?;
+        let rows = read_rows(&mut statement, [], &self.header)?;
+
+        let mut header = self.header.clone();
+        if self.sorted { header.state_index = state_index(&rows); }
+        Ok(Database { header, rows, sources: self.sources()? })
+    }
+
+    /// Same results as `crate::search` over every row.
+    ///
+    /// Only rows with each query's start state pair and near its positions are read from the file,
+    /// then `crate::search` picks the matches so floating point edge cases agree exactly.
+    pub fn search(&self, queries: &[SearchQuery]) -> Result<Vec<Vec<Row>>, SqliteError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {} FROM rows WHERE opponent_state = ?1 AND player_state = ?2
+                AND (opponent_x IS NULL OR opponent_y IS NULL
+                    OR (opponent_x BETWEEN ?3 AND ?4 AND opponent_y BETWEEN ?5 AND ?6))
+                AND (player_x IS NULL OR player_y IS NULL
+                    OR (player_x BETWEEN ?7 AND ?8 AND player_y BETWEEN ?9 AND ?10))
+            ORDER BY id",
+            ROW_COLUMNS,
+        ))?;
+
+        let mut results = Vec::with_capacity(queries.len());
+        for query in queries {
+            let op = &query.opponent_initiation;
+            let pl = &query.player_response;
+            let [op_x_min, op_x_max, op_y_min, op_y_max] = bounds(op);
+            let [pl_x_min, pl_x_max, pl_y_min, pl_y_max] = bounds(pl);
+
+            let candidates = read_rows(&mut statement, params![
+                op.start_state.as_u16(), pl.start_state.as_u16(),
+                op_x_min, op_x_max, op_y_min, op_y_max,
+                pl_x_min, pl_x_max, pl_y_min, pl_y_max,
+            ], &self.header)?;
+            results.push(search(&candidates, std::slice::from_ref(query)).pop().unwrap());
+        }
+
+        Ok(results)
+    }
+}
+
+/// Box of positions that could be within `SEARCH_DISTANCE` of the situation's, with room for rounding,
+/// as `[x_min, x_max, y_min, y_max]`.
+///
+/// A NaN distance never fails `crate::search`'s check, so a row with a NaN coordinate always matches,
+/// and so can any row if the query has a coordinate that isn't finite. Those aren't narrowed down here.
+fn bounds(situation: &SearchSituation) -> [f64; 4] {
+    if !situation.pos_x.is_finite() || !situation.pos_y.is_finite() {
+        return [f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY];
+    }
+
+    let distance = SEARCH_DISTANCE as f64 * 1.001;
+    let (x, y) = (situation.pos_x as f64, situation.pos_y as f64);
+    [x - distance, x + distance, y - distance, y + distance]
+}
+
+fn write(connection: &mut Connection, db: &Database, game_starts: &[Option<slp_parser::GameStart>]) -> Result<(), SqliteError> {
+    let tx = connection.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+
+    tx.execute(
+        "INSERT INTO header VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            VERSION,
+            db.header.player_character.to_u8_internal(),
+            names::character_name(db.header.player_character),
+            db.header.opponent_character.to_u8_internal(),
+            names::character_name(db.header.opponent_character),
+            match db.header.layout { Layout::Rows => "rows", Layout::Columnar => "columnar" },
+            !db.header.state_index.is_empty(),
+        ],
+    )?;
+
+    {
+        let mut insert_source = tx.prepare("INSERT INTO sources VALUES (?1, ?2, ?3)")?;
+        let mut insert_player = tx.prepare("INSERT INTO players VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for (i, source) in db.sources.iter().enumerate() {
+            let game_start = game_starts.get(i).and_then(|g| g.as_ref());
+            insert_source.execute(params![i, source, game_start.map(|g| format!("{:?}", g.stage))])?;
+
+            let Some(game_start) = game_start else { continue };
+            for (port, colour) in game_start.starting_character_colours.iter().enumerate() {
+                let Some(character) = colour.as_ref().map(|c| c.character()) else { continue };
+                insert_player.execute(params![
+                    i, port,
+                    character.to_u8_internal(), names::character_name(character),
+                    &game_start.connect_codes[port][..],
+                ])?;
+            }
+        }
+
+        let mut insert_row = tx.prepare("INSERT INTO rows VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)")?;
+        for (i, row) in db.rows.iter().enumerate() {
+            let op = &row.opponent_initiation;
+            let pl = &row.player_response;
+            insert_row.execute(params![
+                i,
+                op.start_state.as_u16(), names::state_name(op.start_state),
+                op.action_taken.as_u16(), names::action_name(op.action_taken),
+                op.pos_x, op.pos_y,
+                pl.start_state.as_u16(), names::state_name(pl.start_state),
+                pl.action_taken.as_u16(), names::action_name(pl.action_taken),
+                pl.pos_x, pl.pos_y,
+                row.score, row.weight,
+                &row.player_code[..],
+                (row.source != Row::NO_SOURCE).then_some(row.source),
+                row.frame,
+            ])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Returns the header and whether the rows are sorted.
+fn read_header(connection: &Connection) -> Result<(Header, bool), SqliteError> {
+    let header = connection.query_row(
+        "SELECT player_character, opponent_character, layout, sorted FROM header",
+        [],
+        |r| Ok((r.get::<_, u8>(0)?, r.get::<_, u8>(1)?, r.get::<_, String>(2)?, r.get::<_, bool>(3)?)),
+    ).optional()?;
+    let Some((player_character, opponent_character, layout, sorted)) = header else { return Err(SqliteError::BadHeader) };
+
+    let row_count: u32 = connection.query_row("SELECT COUNT(*) FROM rows", [], |r| r.get(0))?;
+    let source_count: u32 = connection.query_row("SELECT COUNT(*) FROM sources", [], |r| r.get(0))?;
+
+    // the stored version is the library's that wrote the file, the rows read back are current
+    let header = Header {
+        version: VERSION,
+        player_character: slp_parser::Character::from_u8_internal(player_character).ok_or(SqliteError::BadHeader)?,
+        opponent_character: slp_parser::Character::from_u8_internal(opponent_character).ok_or(SqliteError::BadHeader)?,
+        row_count,
+        source_count,
+        layout: match layout.as_str() {
+            "rows" => Layout::Rows,
+            "columnar" => Layout::Columnar,
+            _ => return Err(SqliteError::BadHeader),
+        },
+        state_index: Vec::new(),
+        checksum: 0,
+    };
+    Ok((header, sorted))
+}
+
+fn read_rows(
+    statement: &mut rusqlite::Statement,
+    params: impl rusqlite::Params,
+    header: &Header,
+) -> Result<Vec<Row>, SqliteError> {
+    let mut rows = Vec::new();
+    let mut query = statement.query(params)?;
+    while let Some(r) = query.next()? {
+        let id: usize = r.get(13)?;
+        let bad = |column| SqliteError::BadValue { column, row: id };
+
+        // NaN is stored as null
+        let float = |i: usize| -> rusqlite::Result<f32> { Ok(r.get::<_, Option<f64>>(i)?.map_or(f32::NAN, |f| f as f32)) };
+        let state = |i: usize, character, column| -> Result<slp_parser::BroadState, SqliteError> {
+            slp_parser::BroadState::from_u16(character, r.get(i)?).ok_or(bad(column))
+        };
+        let action = |i: usize, character, column| -> Result<slp_parser::HighLevelAction, SqliteError> {
+            slp_parser::HighLevelAction::from_u16(character, r.get(i)?).ok_or(bad(column))
+        };
+        let player_code: Vec<u8> = r.get(10)?;
+
+        rows.push(Row {
+            opponent_initiation: Situation {
+                start_state: state(0, header.opponent_character, "opponent_state")?,
+                action_taken: action(1, header.opponent_character, "opponent_action")?,
+                pos_x: float(2)?,
+                pos_y: float(3)?,
+            },
+            player_response: Situation {
+                start_state: state(4, header.player_character, "player_state")?,
+                action_taken: action(5, header.player_character, "player_action")?,
+                pos_x: float(6)?,
+                pos_y: float(7)?,
+            },
+            score: float(8)?,
+            weight: float(9)?,
+            player_code: player_code.try_into().map_err(|_| bad("player_code"))?,
+            source: r.get::<_, Option<u32>>(11)?.unwrap_or(Row::NO_SOURCE),
+            frame: r.get(12)?,
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,214 @@
+//! Parsing replays that are still being written, for querying between stocks of a live game.
+
+use crate::*;
+use crate::parse_old_game::{EventParser, GameBuilder, EventSizesRet, RawHeaderRet, GAME_START, HEADER_LEN};
+use slp_parser::{SlpError, SlpResult, InvalidLocation};
+
+/// Frames an interaction has to be in the past before it is emitted,
+/// so that its score is not cut short by the end of the frames written so far.
+pub const SETTLE_FRAMES: usize = 180;
+
+/// Frames kept before the earliest interaction that may still be emitted,
+/// so actions near it are parsed the same as over the whole game.
+pub const LOOKBACK_FRAMES: usize = 600;
+
+/// Reads a replay that Slippi is still writing.
+///
+/// Call `poll` whenever the file may have grown. Partial events at the end of the file are left
+/// until the rest is written. The `raw` length may be 0 and the metadata may be missing,
+/// as they are only filled in once the game is over.
+pub struct TailParser<R: std::io::Read> {
+    reader: R,
+    buf: Vec<u8>,
+    cursor: usize,
+    state: Option<TailState>,
+    /// (rows for the high port, opponent initiation frame, player response frame)
+    emitted: std::collections::HashSet<(bool, usize, usize)>,
+    /// Frame count when rows were last generated.
+    emitted_frame_count: usize,
+    /// First frame actions are parsed from. Interactions before it have all been emitted,
+    /// so each poll only parses the end of the game.
+    window_start: usize,
+}
+
+struct TailState {
+    event_sizes: [u16; 255],
+    builder: GameBuilder,
+    event_parser: EventParser,
+    /// Event bytes left in the raw element. `None` if the length was written as 0.
+    raw_remaining: Option<usize>,
+}
+
+impl<R: std::io::Read> TailParser<R> {
+    pub fn new(reader: R) -> Self {
+        TailParser {
+            reader,
+            buf: Vec::with_capacity(1 << 16),
+            cursor: 0,
+            state: None,
+            emitted: std::collections::HashSet::new(),
+            emitted_frame_count: 0,
+            window_start: 0,
+        }
+    }
+
+    /// `None` until the header and game start have been written.
+    pub fn builder(&self) -> Option<&GameBuilder> { self.state.as_ref().map(|s| &s.builder) }
+
+    /// True once the game end event or the end of the raw element has been read.
+    pub fn is_finished(&self) -> bool {
+        self.state.as_ref().is_some_and(|s| s.builder.is_finished() || s.raw_remaining == Some(0))
+    }
+
+    /// Reads everything written since the last poll and returns rows for the interactions that finished since.
+    /// Once the game is over, every remaining scored interaction is returned.
+    pub fn poll(&mut self) -> SlpResult<Vec<Row>> {
+        if self.is_finished() { return Ok(Vec::new()); }
+
+        self.reader.read_to_end(&mut self.buf)?;
+
+        if self.state.is_none() {
+            match self.parse_header()? {
+                Some(state) => self.state = Some(state),
+                None => return Ok(Vec::new()),
+            }
+        }
+
+        self.parse_events()?;
+
+        // drop consumed bytes so the buffer only holds the partial event
+        self.buf.drain(..self.cursor);
+        self.cursor = 0;
+
+        Ok(self.new_rows())
+    }
+
+    /// Returns `None` if not enough of the file has been written yet.
+    fn parse_header(&mut self) -> SlpResult<Option<TailState>> {
+        let header_len = HEADER_LEN as usize;
+        let Some(header) = self.buf.get(..header_len) else { return Ok(None) };
+        let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_old_game::parse_raw_header(header)?;
+        let raw_len = metadata_offset - event_sizes_offset;
+
+        let Some(&info_size) = self.buf.get(header_len + 1) else { return Ok(None) };
+        if info_size < 1 { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)); }
+        let payloads_len = info_size as usize + 1;
+        let Some(payloads) = self.buf.get(header_len..header_len + payloads_len) else { return Ok(None) };
+        let EventSizesRet { event_sizes, .. } = parse_old_game::event_sizes(payloads, 0)?;
+
+        let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
+        let game_start_offset = header_len + payloads_len;
+        let Some(game_start) = self.buf.get(game_start_offset..game_start_offset + game_start_size) else {
+            return Ok(None)
+        };
+        let game_start = parse_old_game::parse_game_start(game_start)?;
+
+        let raw_remaining = match raw_len {
+            0 => None,
+            _ => Some(raw_len.checked_sub(payloads_len + game_start_size)
+                .ok_or(SlpError::InvalidFile(InvalidLocation::EventSizes))?),
+        };
+
+        self.cursor = game_start_offset + game_start_size;
+        Ok(Some(TailState {
+            event_sizes,
+            builder: GameBuilder::new(game_start),
+            event_parser: EventParser::default(),
+            raw_remaining,
+        }))
+    }
+
+    /// Parses every complete event in the buffer.
+    fn parse_events(&mut self) -> SlpResult<()> {
+        let Some(state) = self.state.as_mut() else { return Ok(()) };
+
+        while !state.builder.is_finished() && state.raw_remaining != Some(0) {
+            let Some(&event_cmd) = self.buf.get(self.cursor) else { break };
+            let event_size = state.event_sizes[event_cmd as usize] as usize + 1;
+            let Some(event_bytes) = self.buf.get(self.cursor..self.cursor + event_size) else { break };
+
+            if let Some(remaining) = state.raw_remaining {
+                if event_size > remaining { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)); }
+                state.raw_remaining = Some(remaining - event_size);
+            }
+            self.cursor += event_size;
+
+            if let Some(event) = state.event_parser.parse(event_bytes)? {
+                state.builder.push_event(event);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn new_rows(&mut self) -> Vec<Row> {
+        let finished = self.is_finished();
+        let Some(state) = self.state.as_ref() else { return Vec::new() };
+        let builder = &state.builder;
+
+        let frame_count = builder.frame_count();
+        if frame_count == self.emitted_frame_count && !finished { return Vec::new(); }
+        self.emitted_frame_count = frame_count;
+
+        let game_start = builder.game_start();
+        let mut ports = (0..4).filter(|&i| game_start.starting_character_colours[i].is_some());
+        let (Some(low), Some(high), None) = (ports.next(), ports.next(), ports.next()) else { return Vec::new() };
+        let (Some(low_frames), Some(high_frames)) = (builder.frames(low), builder.frames(high)) else {
+            return Vec::new()
+        };
+
+        // frame indices from here on are relative to the window
+        let start = self.window_start.min(frame_count);
+        let low_frames = &low_frames[start..frame_count];
+        let high_frames = &high_frames[start..frame_count];
+
+        let low_actions = slp_parser::parse_actions(low_frames);
+        let high_actions = slp_parser::parse_actions(high_frames);
+
+        let a = slp_parser::generate_interactions(game_start.stage, &low_actions, &high_actions, low_frames, high_frames);
+        let b = slp_parser::generate_interactions(game_start.stage, &high_actions, &low_actions, high_frames, low_frames);
+
+        let mut rows = Vec::new();
+        let perspectives = [
+            (true, a, low_frames, high_frames, game_start.connect_codes[high]),
+            (false, b, high_frames, low_frames, game_start.connect_codes[low]),
+        ];
+
+        // the next window has to include every interaction not emitted yet,
+        // and the latest actions, which later interactions may start from
+        let mut keep_from = [&low_actions, &high_actions].iter()
+            .map(|actions| actions.last().map_or(frame_count, |action| start + action.frame_start))
+            .min()
+            .unwrap()
+            .min(frame_count.saturating_sub(SETTLE_FRAMES));
+
+        // same mapping as build::push_game_rows
+        for (is_high, interactions, pl_frames, op_frames, code) in perspectives {
+            for interaction in interactions {
+                let op_start = start + interaction.opponent_initiation.frame_start;
+                let pl_start = start + interaction.player_response.frame_start;
+
+                // actions this close to the window's start may be cut off by it.
+                // any real interaction there was emitted by an earlier poll
+                if start != 0 && op_start.min(pl_start) < start + LOOKBACK_FRAMES { continue; }
+
+                if !finished && pl_start.max(op_start) + SETTLE_FRAMES > frame_count {
+                    keep_from = keep_from.min(op_start.min(pl_start));
+                    continue;
+                }
+                let key = (is_high, op_start, pl_start);
+                if self.emitted.contains(&key) { continue; }
+
+                if let Some(mut row) = build::interaction_row(interaction, pl_frames, op_frames, code, 1.0, Row::NO_SOURCE) {
+                    row.frame += start as i32;
+                    self.emitted.insert(key);
+                    rows.push(row);
+                }
+            }
+        }
+
+        self.window_start = self.window_start.max(keep_from.saturating_sub(LOOKBACK_FRAMES));
+        self.emitted.retain(|&(_, op_start, pl_start)| op_start.min(pl_start) >= self.window_start);
+        rows
+    }
+}
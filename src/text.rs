@@ -0,0 +1,508 @@
+//! CSV and JSON Lines export and import, for small hand-edited databases and readable diffs.
+//!
+//! Characters, states and actions are written by name, see `names`, and read back from names or numeric codes.
+//! Floats are written in their shortest exact form, so importing an export gives back the same rows.
+//! NaN and infinities are written as `NaN`, `inf` and `-inf`, as strings in json.
+//! Connect codes are written as text, with `\xNN` for bytes that aren't printable ascii and trailing zeros left off.
+//! Missing sources are written as an empty field or `null`.
+//!
+//! CSV files start with `#` comment lines holding the header and the source manifest, one path per line:
+//!
+//! ```text
+//! # player_character Fox
+//! # opponent_character Falco
+//! # layout rows
+//! # sorted true
+//! # source replays/Game_1.slp
+//! opponent_state,opponent_action,opponent_x,opponent_y,player_state,player_action,player_x,player_y,score,weight,player_code,source,frame
+//! ```
+//!
+//! JSON Lines files start with a header object holding the same fields, with `sources` as an array,
+//! followed by one object per row with `opponent` and `player` objects of `state`, `action`, `x` and `y`.
+//!
+//! Rows of a sorted database are re-sorted on import, so edited rows don't have to be kept in order.
+
+use crate::*;
+use names::NameTable;
+
+pub const CSV_COLUMNS: [&str; 13] = [
+    "opponent_state", "opponent_action", "opponent_x", "opponent_y",
+    "player_state", "player_action", "player_x", "player_y",
+    "score", "weight", "player_code", "source", "frame",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextErrorKind {
+    MissingHeader(&'static str),
+    MissingField(&'static str),
+    BadField(&'static str),
+    UnknownCharacter(String),
+    UnknownState(String),
+    UnknownAction(String),
+    /// A source index past the end of the manifest.
+    BadSource(u32),
+    BadJson,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextError {
+    pub kind: TextErrorKind,
+    /// Line number, starting from 1.
+    pub line: usize,
+}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            TextErrorKind::MissingHeader(h) => write!(f, "missing header field '{}'", h)?,
+            TextErrorKind::MissingField(c) => write!(f, "missing field '{}'", c)?,
+            TextErrorKind::BadField(c) => write!(f, "invalid value for '{}'", c)?,
+            TextErrorKind::UnknownCharacter(s) => write!(f, "unknown character '{}'", s)?,
+            TextErrorKind::UnknownState(s) => write!(f, "unknown state '{}'", s)?,
+            TextErrorKind::UnknownAction(s) => write!(f, "unknown action '{}'", s)?,
+            TextErrorKind::BadSource(n) => write!(f, "source {} is not in the source manifest", n)?,
+            TextErrorKind::BadJson => write!(f, "invalid json")?,
+        }
+        write!(f, " on line {}", self.line)
+    }
+}
+
+impl std::error::Error for TextError {}
+
+fn err(kind: TextErrorKind, line: usize) -> TextError {
+    TextError { kind, line }
+}
+
+// writing ------------------------------------------------------------------
+
+pub fn write_csv(db: &Database) -> String {
+    let mut csv = String::with_capacity(256 + db.rows.len() * 128);
+    csv.push_str(&format!("# player_character {}\n", names::character_name(db.header.player_character)));
+    csv.push_str(&format!("# opponent_character {}\n", names::character_name(db.header.opponent_character)));
+    csv.push_str(&format!("# layout {}\n", layout_name(db.header.layout)));
+    csv.push_str(&format!("# sorted {}\n", !db.header.state_index.is_empty()));
+    for source in db.sources.iter() { csv.push_str(&format!("# source {}\n", source)); }
+    csv.push_str(&CSV_COLUMNS.join(","));
+    csv.push('\n');
+
+    for row in db.rows.iter() {
+        let source = if row.source == Row::NO_SOURCE { String::new() } else { row.source.to_string() };
+        let fields = [
+            names::state_name(row.opponent_initiation.start_state),
+            names::action_name(row.opponent_initiation.action_taken),
+            format!("{:?}", row.opponent_initiation.pos_x),
+            format!("{:?}", row.opponent_initiation.pos_y),
+            names::state_name(row.player_response.start_state),
+            names::action_name(row.player_response.action_taken),
+            format!("{:?}", row.player_response.pos_x),
+            format!("{:?}", row.player_response.pos_y),
+            format!("{:?}", row.score),
+            format!("{:?}", row.weight),
+            code_text(&row.player_code),
+            source,
+            row.frame.to_string(),
+        ];
+
+        for (i, field) in fields.iter().enumerate() {
+            if i != 0 { csv.push(','); }
+            push_csv_field(&mut csv, field);
+        }
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn push_csv_field(csv: &mut String, field: &str) {
+    if !field.contains([',', '"', '\n', '\r']) { return csv.push_str(field); }
+    csv.push('"');
+    csv.push_str(&field.replace('"', "\"\""));
+    csv.push('"');
+}
+
+pub fn write_jsonl(db: &Database) -> String {
+    let mut jsonl = String::with_capacity(256 + db.rows.len() * 256);
+
+    jsonl.push_str("{\"player_character\": ");
+    json::push_string(&mut jsonl, &names::character_name(db.header.player_character));
+    jsonl.push_str(", \"opponent_character\": ");
+    json::push_string(&mut jsonl, &names::character_name(db.header.opponent_character));
+    jsonl.push_str(&format!(", \"layout\": \"{}\", \"sorted\": {}, \"sources\": [",
+        layout_name(db.header.layout), !db.header.state_index.is_empty()));
+    for (i, source) in db.sources.iter().enumerate() {
+        if i != 0 { jsonl.push_str(", "); }
+        json::push_string(&mut jsonl, source);
+    }
+    jsonl.push_str("]}\n");
+
+    for row in db.rows.iter() {
+        jsonl.push_str("{\"opponent\": ");
+        push_json_situation(&mut jsonl, &row.opponent_initiation);
+        jsonl.push_str(", \"player\": ");
+        push_json_situation(&mut jsonl, &row.player_response);
+        jsonl.push_str(", \"score\": ");
+        push_json_f32(&mut jsonl, row.score);
+        jsonl.push_str(", \"weight\": ");
+        push_json_f32(&mut jsonl, row.weight);
+        jsonl.push_str(", \"player_code\": ");
+        json::push_string(&mut jsonl, &code_text(&row.player_code));
+        jsonl.push_str(", \"source\": ");
+        if row.source == Row::NO_SOURCE {
+            jsonl.push_str("null");
+        } else {
+            jsonl.push_str(&row.source.to_string());
+        }
+        jsonl.push_str(&format!(", \"frame\": {}}}\n", row.frame));
+    }
+
+    jsonl
+}
+
+fn push_json_situation(json: &mut String, situation: &Situation) {
+    json.push_str("{\"state\": ");
+    json::push_string(json, &names::state_name(situation.start_state));
+    json.push_str(", \"action\": ");
+    json::push_string(json, &names::action_name(situation.action_taken));
+    json.push_str(", \"x\": ");
+    push_json_f32(json, situation.pos_x);
+    json.push_str(", \"y\": ");
+    push_json_f32(json, situation.pos_y);
+    json.push('}');
+}
+
+/// Json has no NaN or infinity, so those are written as strings.
+fn push_json_f32(json: &mut String, n: f32) {
+    if n.is_finite() {
+        json.push_str(&format!("{:?}", n));
+    } else {
+        json.push_str(&format!("\"{:?}\"", n));
+    }
+}
+
+fn layout_name(layout: Layout) -> &'static str {
+    match layout {
+        Layout::Rows => "rows",
+        Layout::Columnar => "columnar",
+    }
+}
+
+fn code_text(code: &ConnectCode) -> String {
+    let len = code.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    let mut text = String::with_capacity(len);
+    for &b in code[..len].iter() {
+        match b {
+            b'\\' => text.push_str("\\\\"),
+            0x20..=0x7E => text.push(b as char),
+            _ => text.push_str(&format!("\\x{:02X}", b)),
+        }
+    }
+    text
+}
+
+fn parse_code(text: &str) -> Option<ConnectCode> {
+    let mut code = [0u8; 10];
+    let mut len = 0;
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        let b = match b {
+            b'\\' => match bytes.next()? {
+                b'\\' => b'\\',
+                b'x' => {
+                    let hex = [bytes.next()?, bytes.next()?];
+                    u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+                }
+                _ => return None,
+            },
+            0x20..=0x7E => b,
+            _ => return None,
+        };
+        *code.get_mut(len)? = b;
+        len += 1;
+    }
+    Some(code)
+}
+
+// reading ------------------------------------------------------------------
+
+/// Header fields as read from either format, before names are resolved.
+struct TextHeader<'a> {
+    player_character: Option<&'a str>,
+    opponent_character: Option<&'a str>,
+    layout: Option<&'a str>,
+    sorted: Option<&'a str>,
+    sources: Vec<String>,
+}
+
+struct Tables {
+    header: Header,
+    sorted: bool,
+    player: NameTable,
+    opponent: NameTable,
+}
+
+impl<'a> TextHeader<'a> {
+    fn resolve(self, line: usize) -> Result<(Tables, Vec<String>), TextError> {
+        let character = |name: Option<&str>, field| -> Result<slp_parser::Character, TextError> {
+            let name = name.ok_or(err(TextErrorKind::MissingHeader(field), line))?;
+            names::parse_character(name).ok_or_else(|| err(TextErrorKind::UnknownCharacter(name.to_string()), line))
+        };
+        let player_character = character(self.player_character, "player_character")?;
+        let opponent_character = character(self.opponent_character, "opponent_character")?;
+
+        let layout = match self.layout {
+            None | Some("rows") => Layout::Rows,
+            Some("columnar") => Layout::Columnar,
+            Some(_) => return Err(err(TextErrorKind::BadField("layout"), line)),
+        };
+        let sorted = match self.sorted {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(_) => return Err(err(TextErrorKind::BadField("sorted"), line)),
+        };
+
+        let header = Header {
+            version: VERSION,
+            player_character,
+            opponent_character,
+            row_count: 0,
+            source_count: self.sources.len() as u32,
+            layout,
+            state_index: Vec::new(),
+            checksum: 0,
+        };
+
+        let tables = Tables {
+            header,
+            sorted,
+            player: NameTable::new(player_character),
+            opponent: NameTable::new(opponent_character),
+        };
+        Ok((tables, self.sources))
+    }
+}
+
+/// Row fields as read from either format, before names are resolved.
+struct TextRow<'a> {
+    opponent_state: &'a str,
+    opponent_action: &'a str,
+    opponent_x: &'a str,
+    opponent_y: &'a str,
+    player_state: &'a str,
+    player_action: &'a str,
+    player_x: &'a str,
+    player_y: &'a str,
+    score: &'a str,
+    weight: &'a str,
+    player_code: &'a str,
+    /// `None` for a missing source.
+    source: Option<&'a str>,
+    frame: &'a str,
+}
+
+impl<'a> TextRow<'a> {
+    fn resolve(&self, tables: &Tables, source_count: usize, line: usize) -> Result<Row, TextError> {
+        let state = |table: &NameTable, name: &str| {
+            table.state(name).ok_or_else(|| err(TextErrorKind::UnknownState(name.to_string()), line))
+        };
+        let action = |table: &NameTable, name: &str| {
+            table.action(name).ok_or_else(|| err(TextErrorKind::UnknownAction(name.to_string()), line))
+        };
+        let float = |field, text: &str| text.trim().parse::<f32>().map_err(|_| err(TextErrorKind::BadField(field), line));
+
+        let source = match self.source {
+            None => Row::NO_SOURCE,
+            Some(text) => {
+                let n = text.trim().parse::<u32>().map_err(|_| err(TextErrorKind::BadField("source"), line))?;
+                if n as usize >= source_count { return Err(err(TextErrorKind::BadSource(n), line)); }
+                n
+            }
+        };
+
+        Ok(Row {
+            opponent_initiation: Situation {
+                start_state: state(&tables.opponent, self.opponent_state)?,
+                action_taken: action(&tables.opponent, self.opponent_action)?,
+                pos_x: float("opponent_x", self.opponent_x)?,
+                pos_y: float("opponent_y", self.opponent_y)?,
+            },
+            player_response: Situation {
+                start_state: state(&tables.player, self.player_state)?,
+                action_taken: action(&tables.player, self.player_action)?,
+                pos_x: float("player_x", self.player_x)?,
+                pos_y: float("player_y", self.player_y)?,
+            },
+            score: float("score", self.score)?,
+            weight: float("weight", self.weight)?,
+            player_code: parse_code(self.player_code).ok_or(err(TextErrorKind::BadField("player_code"), line))?,
+            source,
+            frame: self.frame.trim().parse().map_err(|_| err(TextErrorKind::BadField("frame"), line))?,
+        })
+    }
+}
+
+fn finish(tables: Tables, rows: Vec<Row>, sources: Vec<String>) -> Database {
+    let mut db = Database { header: tables.header, rows, sources };
+    db.header.row_count = db.rows.len() as u32;
+    if tables.sorted { sort_database(&mut db); }
+    db
+}
+
+/// Columns may be in any order, and columns other than `CSV_COLUMNS` are ignored.
+pub fn read_csv(text: &str) -> Result<Database, TextError> {
+    let mut lines = text.lines().map(|l| l.strip_suffix('\r').unwrap_or(l)).enumerate();
+
+    let mut header = TextHeader {
+        player_character: None,
+        opponent_character: None,
+        layout: None,
+        sorted: None,
+        sources: Vec::new(),
+    };
+
+    let (column_line, columns) = loop {
+        let (i, line) = lines.next().ok_or(err(TextErrorKind::MissingHeader("columns"), 0))?;
+        let Some(comment) = line.strip_prefix('#') else { break (i + 1, split_csv_line(line)) };
+
+        let comment = comment.strip_prefix(' ').unwrap_or(comment);
+        let (key, value) = comment.split_once(' ').unwrap_or((comment, ""));
+        match key {
+            "player_character" => header.player_character = Some(value.trim()),
+            "opponent_character" => header.opponent_character = Some(value.trim()),
+            "layout" => header.layout = Some(value.trim()),
+            "sorted" => header.sorted = Some(value.trim()),
+            "source" => header.sources.push(value.to_string()),
+            _ => (),
+        }
+    };
+
+    let columns = columns.ok_or(err(TextErrorKind::MissingHeader("columns"), column_line))?;
+    let mut column_indices = [0usize; CSV_COLUMNS.len()];
+    for (index, name) in column_indices.iter_mut().zip(CSV_COLUMNS) {
+        *index = columns.iter().position(|c| c == name).ok_or(err(TextErrorKind::MissingField(name), column_line))?;
+    }
+
+    let (tables, sources) = header.resolve(column_line)?;
+    let mut rows = Vec::new();
+
+    for (i, line) in lines {
+        let line_number = i + 1;
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let fields = split_csv_line(line).ok_or(err(TextErrorKind::BadField("quoting"), line_number))?;
+        let field = |c: usize| -> Result<&str, TextError> {
+            fields.get(column_indices[c]).map(|f| f.as_str()).ok_or(err(TextErrorKind::MissingField(CSV_COLUMNS[c]), line_number))
+        };
+
+        let source = field(11)?;
+        let row = TextRow {
+            opponent_state: field(0)?,
+            opponent_action: field(1)?,
+            opponent_x: field(2)?,
+            opponent_y: field(3)?,
+            player_state: field(4)?,
+            player_action: field(5)?,
+            player_x: field(6)?,
+            player_y: field(7)?,
+            score: field(8)?,
+            weight: field(9)?,
+            player_code: field(10)?,
+            source: (!source.trim().is_empty()).then_some(source),
+            frame: field(12)?,
+        };
+        rows.push(row.resolve(&tables, sources.len(), line_number)?);
+    }
+
+    Ok(finish(tables, rows, sources))
+}
+
+/// `None` if a quoted field isn't closed.
+fn split_csv_line(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted { return None; }
+    fields.push(field);
+    Some(fields)
+}
+
+pub fn read_jsonl(text: &str) -> Result<Database, TextError> {
+    let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+    let (i, first) = lines.next().ok_or(err(TextErrorKind::MissingHeader("header"), 0))?;
+    let header_line = i + 1;
+    let header_json = json::parse(first).ok_or(err(TextErrorKind::BadJson, header_line))?;
+
+    let string = |key: &str| header_json.get(key).and_then(|v| v.as_str());
+    let sources = match header_json.get("sources") {
+        None => Vec::new(),
+        Some(json::Json::Array(sources)) => sources.iter()
+            .map(|s| s.as_str().map(|s| s.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(err(TextErrorKind::BadField("sources"), header_line))?,
+        Some(_) => return Err(err(TextErrorKind::BadField("sources"), header_line)),
+    };
+    let sorted = match header_json.get("sorted") {
+        None => None,
+        Some(json::Json::Bool(true)) => Some("true"),
+        Some(json::Json::Bool(false)) => Some("false"),
+        Some(_) => return Err(err(TextErrorKind::BadField("sorted"), header_line)),
+    };
+
+    let header = TextHeader {
+        player_character: string("player_character"),
+        opponent_character: string("opponent_character"),
+        layout: string("layout"),
+        sorted,
+        sources,
+    };
+    let (tables, sources) = header.resolve(header_line)?;
+    let mut rows = Vec::new();
+
+    for (i, line) in lines {
+        let line_number = i + 1;
+        let row_json = json::parse(line).ok_or(err(TextErrorKind::BadJson, line_number))?;
+
+        let field = |object: &'static str, key: &'static str| -> Result<&str, TextError> {
+            let value = match object {
+                "" => row_json.get(key),
+                _ => row_json.get(object).and_then(|o| o.get(key)),
+            };
+            value.and_then(|v| v.as_text()).ok_or(err(TextErrorKind::MissingField(key), line_number))
+        };
+
+        let source = match row_json.get("source") {
+            None | Some(json::Json::Null) => None,
+            Some(v) => Some(v.as_text().ok_or(err(TextErrorKind::BadField("source"), line_number))?),
+        };
+
+        let row = TextRow {
+            opponent_state: field("opponent", "state")?,
+            opponent_action: field("opponent", "action")?,
+            opponent_x: field("opponent", "x")?,
+            opponent_y: field("opponent", "y")?,
+            player_state: field("player", "state")?,
+            player_action: field("player", "action")?,
+            player_x: field("player", "x")?,
+            player_y: field("player", "y")?,
+            score: field("", "score")?,
+            weight: field("", "weight")?,
+            player_code: field("", "player_code")?,
+            source,
+            frame: field("", "frame")?,
+        };
+        rows.push(row.resolve(&tables, sources.len(), line_number)?);
+    }
+
+    Ok(finish(tables, rows, sources))
+}
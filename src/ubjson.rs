@@ -0,0 +1,278 @@
+//! Minimal UBJSON reader and writer, enough for replay metadata and the console protocol.
+//! Never panics on malformed input, returns `None` instead.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// Strongly typed uint8 arrays (`[$U#`), which slippi uses for raw byte data.
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    /// Keys in file order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+const MAX_DEPTH: usize = 32;
+
+/// Parses a single value from the start of `bytes`.
+/// Returns the value and the number of bytes it took up.
+pub fn parse_value(bytes: &[u8]) -> Option<(Value, usize)> {
+    let mut reader = Reader { bytes, cursor: 0 };
+    let value = reader.value(0)?;
+    Some((value, reader.cursor))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let b = self.bytes.get(self.cursor..)?.get(..n)?;
+        self.cursor += n;
+        Some(b)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.bytes.get(self.cursor)?;
+        self.cursor += 1;
+        Some(b)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.cursor).copied()
+    }
+
+    fn int(&mut self, marker: u8) -> Option<i64> {
+        Some(match marker {
+            b'i' => self.byte()? as i8 as i64,
+            b'U' => self.byte()? as i64,
+            b'I' => i16::from_be_bytes(self.take(2)?.try_into().unwrap()) as i64,
+            b'l' => i32::from_be_bytes(self.take(4)?.try_into().unwrap()) as i64,
+            b'L' => i64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            _ => return None,
+        })
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let marker = self.byte()?;
+        let len = self.int(marker)?;
+        if len < 0 { return None; }
+        let len = len as usize;
+
+        // reject lengths that can't possibly fit before allocating
+        if len > self.bytes.len() - self.cursor { return None; }
+        Some(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.len()?;
+        let b = self.take(len)?;
+        Some(String::from_utf8_lossy(b).into_owned())
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        let marker = self.byte()?;
+        self.value_with_marker(marker, depth)
+    }
+
+    fn value_with_marker(&mut self, marker: u8, depth: usize) -> Option<Value> {
+        Some(match marker {
+            b'Z' => Value::Null,
+            b'T' => Value::Bool(true),
+            b'F' => Value::Bool(false),
+            b'i' | b'U' | b'I' | b'l' | b'L' => Value::Int(self.int(marker)?),
+            b'd' => Value::Float(f32::from_be_bytes(self.take(4)?.try_into().unwrap()) as f64),
+            b'D' => Value::Float(f64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            b'C' => Value::String((self.byte()? as char).to_string()),
+            b'S' | b'H' => Value::String(self.string()?),
+            b'[' => self.array(depth + 1)?,
+            b'{' => self.object(depth + 1)?,
+            _ => return None,
+        })
+    }
+
+    /// Reads the optional `$type` and `#count` that follow a container's opening marker.
+    fn container_params(&mut self) -> Option<(Option<u8>, Option<usize>)> {
+        let mut typ = None;
+        if self.peek()? == b'$' {
+            self.cursor += 1;
+            typ = Some(self.byte()?);
+        }
+
+        let mut count = None;
+        if self.peek()? == b'#' {
+            self.cursor += 1;
+            count = Some(self.len()?);
+        }
+
+        // a type without a count is invalid
+        if typ.is_some() && count.is_none() { return None; }
+        Some((typ, count))
+    }
+
+    fn array(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH { return None; }
+        let (typ, count) = self.container_params()?;
+
+        if let (Some(b'U'), Some(count)) = (typ, count) {
+            return Some(Value::Bytes(self.take(count)?.to_vec()));
+        }
+
+        let mut values = Vec::new();
+        match count {
+            Some(count) => {
+                for _ in 0..count {
+                    let value = match typ {
+                        Some(t) => self.value_with_marker(t, depth)?,
+                        None => self.value(depth)?,
+                    };
+                    values.push(value);
+                }
+            }
+            None => loop {
+                match self.peek()? {
+                    b']' => { self.cursor += 1; break; }
+                    b'N' => { self.cursor += 1; }
+                    _ => values.push(self.value(depth)?),
+                }
+            }
+        }
+
+        Some(Value::Array(values))
+    }
+
+    fn object(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH { return None; }
+        let (typ, count) = self.container_params()?;
+
+        let mut entries = Vec::new();
+        match count {
+            Some(count) => {
+                for _ in 0..count {
+                    let key = self.string()?;
+                    let value = match typ {
+                        Some(t) => self.value_with_marker(t, depth)?,
+                        None => self.value(depth)?,
+                    };
+                    entries.push((key, value));
+                }
+            }
+            None => loop {
+                match self.peek()? {
+                    b'}' => { self.cursor += 1; break; }
+                    b'N' => { self.cursor += 1; }
+                    _ => {
+                        let key = self.string()?;
+                        let value = self.value(depth)?;
+                        entries.push((key, value));
+                    }
+                }
+            }
+        }
+
+        Some(Value::Object(entries))
+    }
+}
+
+/// Appends `value` to `out`, using the smallest integer type that fits.
+pub fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(b'Z'),
+        Value::Bool(true) => out.push(b'T'),
+        Value::Bool(false) => out.push(b'F'),
+        Value::Int(n) => write_int(out, *n),
+        Value::Float(f) => {
+            out.push(b'D');
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        Value::String(s) => {
+            out.push(b'S');
+            write_string(out, s);
+        }
+        Value::Bytes(b) => {
+            out.extend_from_slice(b"[$U#");
+            write_int(out, b.len() as i64);
+            out.extend_from_slice(b);
+        }
+        Value::Array(values) => {
+            out.push(b'[');
+            for v in values { write_value(out, v); }
+            out.push(b']');
+        }
+        Value::Object(entries) => {
+            out.push(b'{');
+            for (k, v) in entries {
+                write_string(out, k);
+                write_value(out, v);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+fn write_int(out: &mut Vec<u8>, n: i64) {
+    if let Ok(n) = i8::try_from(n) {
+        out.push(b'i');
+        out.push(n as u8);
+    } else if let Ok(n) = u8::try_from(n) {
+        out.push(b'U');
+        out.push(n);
+    } else if let Ok(n) = i16::try_from(n) {
+        out.push(b'I');
+        out.extend_from_slice(&n.to_be_bytes());
+    } else if let Ok(n) = i32::try_from(n) {
+        out.push(b'l');
+        out.extend_from_slice(&n.to_be_bytes());
+    } else {
+        out.push(b'L');
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// Length and bytes, without a marker, as used for object keys.
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    write_int(out, s.len() as i64);
+    out.extend_from_slice(s.as_bytes());
+}
@@ -0,0 +1,165 @@
+//! Writing replays back out, e.g. cut down to the frames around a search hit.
+
+use crate::parse_old_game::{self, EventSizesRet, RawHeaderRet, GAME_END, GAME_START};
+use crate::ubjson::{self, Value};
+use slp_parser::{SlpError, SlpResult, InvalidLocation};
+
+const RAW_HEADER: &[u8] = b"{U\x03raw[$U#l";
+const METADATA_KEY: &[u8] = b"U\x08metadata";
+
+/// Events that start with the frame number they belong to.
+/// Pre and post frame updates, frame start, item update, frame bookend,
+/// and the fountain of dreams, whispy and stadium transformation stage events.
+const FRAME_EVENTS: [u8; 8] = [0x37, 0x38, 0x3A, 0x3B, 0x3C, 0x3F, 0x40, 0x41];
+
+/// The pieces of a replay needed to write it back out.
+#[derive(Clone, Debug)]
+pub struct RawReplay<'a> {
+    pub event_sizes: [u16; 255],
+    /// The event payloads event, including its command byte.
+    pub event_payloads: &'a [u8],
+    /// The game start event, including its command byte.
+    pub game_start: &'a [u8],
+    /// Every event after the game start.
+    pub events: &'a [u8],
+    pub metadata: Option<Value>,
+}
+
+impl<'a> RawReplay<'a> {
+    /// Splits an uncompressed replay into its pieces.
+    /// Replays still being written, with a raw length of 0, take every remaining byte as events.
+    pub fn parse(slp: &'a [u8]) -> SlpResult<RawReplay<'a>> {
+        let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_old_game::parse_raw_header(slp)?;
+        let EventSizesRet { game_start_offset, event_sizes } = parse_old_game::event_sizes(slp, event_sizes_offset)?;
+        let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
+        let events_offset = game_start_offset + game_start_size;
+
+        let (events_end, metadata) = if metadata_offset == event_sizes_offset {
+            (slp.len(), None)
+        } else {
+            let metadata = slp.get(metadata_offset..)
+                .and_then(|m| m.strip_prefix(METADATA_KEY))
+                .and_then(ubjson::parse_value)
+                .map(|(value, _)| value);
+            (metadata_offset, metadata)
+        };
+
+        let events = slp.get(events_offset..events_end).ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
+        Ok(RawReplay {
+            event_sizes,
+            event_payloads: &slp[event_sizes_offset..game_start_offset],
+            game_start: &slp[game_start_offset..events_offset],
+            events,
+            metadata,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    Parse(SlpError),
+    Compress(slpz::CompError),
+}
+
+impl From<SlpError> for WriteError {
+    fn from(e: SlpError) -> Self { WriteError::Parse(e) }
+}
+
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::Parse(e) => write!(f, "could not parse replay: {}", e),
+            WriteError::Compress(e) => write!(f, "could not compress replay: {}", e),
+        }
+    }
+}
+
+/// Writes a replay holding only the frames in `frames`, given as in-game frame numbers.
+///
+/// Frames keep their numbers, so a parsed trim has `Frame::NULL` before the first kept frame.
+/// Events before the first frame, such as the gecko code list, and the game end are always kept.
+/// The metadata's `lastFrame` is updated to match.
+///
+/// Dolphin plays replays back by simulating the game from its first frame, so a trim that starts
+/// mid-game is for tools reading the frames, not for watching. To watch part of a game,
+/// queue the whole replay with a start and end frame, see `playback`.
+pub fn write_slp(replay: &RawReplay, frames: std::ops::RangeInclusive<i32>) -> SlpResult<Vec<u8>> {
+    let mut raw = Vec::with_capacity(replay.event_payloads.len() + replay.game_start.len() + replay.events.len());
+    raw.extend_from_slice(replay.event_payloads);
+    raw.extend_from_slice(replay.game_start);
+
+    let mut last_frame = None;
+    let mut current_frame = None;
+    let mut cursor = 0;
+    while cursor < replay.events.len() {
+        let event_cmd = replay.events[cursor];
+        let event_size = replay.event_sizes[event_cmd as usize] as usize + 1;
+        let event = replay.events.get(cursor..cursor + event_size)
+            .ok_or(SlpError::InvalidFile(InvalidLocation::EventSizes))?;
+        cursor += event_size;
+
+        if FRAME_EVENTS.contains(&event_cmd) && event.len() >= 5 {
+            let frame = i32::from_be_bytes(event[1..5].try_into().unwrap());
+            current_frame = Some(frame);
+            if !frames.contains(&frame) { continue; }
+
+            raw.extend_from_slice(event);
+            last_frame = last_frame.max(Some(frame));
+        } else if event_cmd == GAME_END || current_frame.is_none_or(|f| frames.contains(&f)) {
+            raw.extend_from_slice(event);
+        }
+    }
+
+    let mut slp = Vec::with_capacity(raw.len() + 1024);
+    slp.extend_from_slice(RAW_HEADER);
+    slp.extend_from_slice(&(raw.len() as u32).to_be_bytes());
+    slp.extend_from_slice(&raw);
+
+    if let Some(Value::Object(entries)) = &replay.metadata {
+        let mut entries = entries.clone();
+        entries.retain(|(key, _)| key != "lastFrame");
+        if let Some(last_frame) = last_frame {
+            entries.push(("lastFrame".to_string(), Value::Int(last_frame as i64)));
+        }
+
+        slp.extend_from_slice(METADATA_KEY);
+        ubjson::write_value(&mut slp, &Value::Object(entries));
+    }
+    slp.push(b'}');
+
+    Ok(slp)
+}
+
+/// As `write_slp`, compressed to slpz.
+pub fn write_slpz(
+    compressor: &mut slpz::Compressor,
+    replay: &RawReplay,
+    frames: std::ops::RangeInclusive<i32>,
+) -> Result<Vec<u8>, WriteError> {
+    let slp = write_slp(replay, frames)?;
+    slpz::compress(compressor, &slp).map_err(WriteError::Compress)
+}
+
+/// Cuts a replay down to `frames`, compressing the result if a compressor is given.
+/// Accepts slp or slpz input.
+pub fn trim(
+    file: &[u8],
+    frames: std::ops::RangeInclusive<i32>,
+    compressor: Option<&mut slpz::Compressor>,
+) -> Result<Vec<u8>, WriteError> {
+    let decompressed;
+    let slp = if file.starts_with(RAW_HEADER) {
+        file
+    } else {
+        let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
+        decompressed = slpz::decompress(&mut decompressor, file)
+            .map_err(|_| SlpError::InvalidFile(InvalidLocation::SlpzDecompression))?;
+        &decompressed
+    };
+
+    let replay = RawReplay::parse(slp)?;
+    match compressor {
+        Some(compressor) => write_slpz(compressor, &replay, frames),
+        None => Ok(write_slp(&replay, frames)?),
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::append::*;
+use slp_parser::Character;
+use common::finished_replay;
+use sample::{sample_database, sample_queries, write_version};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("slp_action_db_append_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes the synthetic replay to `dir`, returning its path.
+fn write_replay(dir: &std::path::Path, name: &str, frame_count: i32) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, finished_replay(frame_count).0).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn append_game_updates_counts() {
+    let mut db = sample_database(Character::Fox, Character::Fox, 20);
+    let game = parse_old_game::parse_old_file_full(&finished_replay(600).0).unwrap();
+
+    let added = append_game(&mut db, &game, "new.slp".to_string(), &build::BuildOptions::default()).unwrap();
+    assert!(added > 0);
+    assert_eq!(db.rows.len(), 20 + added);
+    assert_eq!(db.header.row_count as usize, db.rows.len());
+    assert_eq!(db.sources.len(), 4);
+    assert_eq!(db.header.source_count, 4);
+    assert_eq!(db.sources[3], "new.slp");
+    assert!(db.rows[20..].iter().all(|row| row.source == 3));
+}
+
+#[test]
+fn append_replays_skips_known_sources() {
+    let dir = temp_dir("known");
+    let first = write_replay(&dir, "first.slp", 600);
+    let second = write_replay(&dir, "second.slp", 400);
+
+    let mut db = sample_database(Character::Fox, Character::Fox, 20);
+    let report = append_replays(&mut db, std::slice::from_ref(&first), &build::BuildOptions::default());
+    assert_eq!(report.added_replays, 1);
+    assert!(report.already_present.is_empty());
+    let rows_after_first = db.rows.len();
+
+    let missing = dir.join("missing.slp").to_string_lossy().into_owned();
+    let report = append_replays(&mut db, &[first.clone(), second.clone(), missing.clone()], &build::BuildOptions::default());
+    assert_eq!(report.added_replays, 1);
+    assert_eq!(report.already_present, [first.as_str()]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, missing);
+    assert!(matches!(report.failed[0].1, AppendError::Io(_)));
+    assert_eq!(db.rows.len(), rows_after_first + report.added_rows);
+    assert_eq!(db.sources.iter().filter(|s| **s == first).count(), 1);
+    assert_eq!(db.sources[4], second);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn append_keeps_sorted_databases_sorted() {
+    let dir = temp_dir("sorted");
+    let replay = write_replay(&dir, "game.slp", 600);
+
+    let mut db = sample_database(Character::Fox, Character::Fox, 200);
+    sort_database(&mut db);
+    let report = append_replays(&mut db, &[replay], &build::BuildOptions::default());
+    assert!(report.added_rows > 0);
+    assert!(!db.header.state_index.is_empty());
+
+    for (i, row) in db.rows.iter().enumerate() {
+        let range = db.header.state_range(row.opponent_initiation.start_state, row.player_response.start_state).unwrap();
+        assert!(range.contains(&i));
+    }
+
+    let queries = sample_queries(&db);
+    assert_eq!(format!("{:?}", db.search(&queries)), format!("{:?}", search(&db.rows, &queries)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn append_file_upgrades_and_checksums() {
+    let dir = temp_dir("file");
+    let replay = write_replay(&dir, "game.slp", 600);
+    let path = dir.join("db.actions");
+
+    let db = sample_database(Character::Fox, Character::Fox, 20);
+    std::fs::write(&path, write_version(&db, 3)).unwrap();
+
+    let report = append_file(&path, std::slice::from_ref(&replay), &build::BuildOptions::default()).unwrap();
+    assert_eq!(report.added_replays, 1);
+    let file = std::fs::read(&path).unwrap();
+    assert_eq!(u32::from_le_bytes(file[..4].try_into().unwrap()), VERSION);
+    let (header, rows) = read_file(&file).unwrap();
+    assert_eq!(header.version, VERSION);
+    assert_eq!(rows.len(), 20 + report.added_rows);
+
+    // nothing new, but a past version is still rewritten
+    std::fs::write(&path, write_version(&db, 3)).unwrap();
+    let report = append_file(&path, &["a/game 1.slp".to_string()], &build::BuildOptions::default()).unwrap();
+    assert_eq!(report.added_replays, 0);
+    assert_eq!(report.already_present.len(), 1);
+    let file = std::fs::read(&path).unwrap();
+    assert_eq!(read_file(&file).unwrap().0.version, VERSION);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupted_body_fails_the_checksum() {
+    let db = sample_database(Character::Fox, Character::Marth, 20);
+    let mut file = Vec::new();
+    write_database(&mut file, &db);
+    assert!(read_database(&file).is_ok());
+
+    // the score of the last row, which still reads as a valid row
+    let score = file.len() - db.sources.iter().map(|s| 2 + s.len()).sum::<usize>() - Row::WRITTEN_SIZE + 24;
+    file[score] ^= 0x40;
+    let e = read_database(&file).unwrap_err();
+    assert_eq!(e.kind, DBErrorKind::BadChecksum);
+}
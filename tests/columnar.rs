@@ -0,0 +1,63 @@
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::columnar::*;
+use slp_parser::Character;
+use sample::{sample_database, sample_queries};
+
+#[test]
+fn columnar_round_trip() {
+    // several blocks, the last one partial
+    let mut db = sample_database(Character::Fox, Character::Marth, BLOCK_ROWS * 2 + 100);
+    db.header.layout = Layout::Columnar;
+    db.rows[7].score = f32::NAN;
+    db.rows[8].player_response.pos_x = f32::INFINITY;
+
+    let mut file = Vec::new();
+    write_database(&mut file, &db);
+    assert_eq!(file[6], 1);
+
+    let read = read_database(&file).unwrap();
+    assert_eq!(read.header.version, VERSION);
+    assert_eq!(read.header.layout, Layout::Columnar);
+    assert_eq!(read.header.row_count as usize, db.rows.len());
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+    assert_eq!(read.sources, db.sources);
+
+    let mut rewritten = Vec::new();
+    write_database(&mut rewritten, &read);
+    assert_eq!(rewritten, file);
+
+    let parsed = ColumnarFile::parse(&file).unwrap();
+    assert_eq!(parsed.blocks.len(), 3);
+    assert_eq!(parsed.read_sources().unwrap(), db.sources);
+}
+
+#[test]
+fn columnar_search_matches_search() {
+    let mut db = sample_database(Character::Fox, Character::Marth, BLOCK_ROWS * 2 + 100);
+    db.header.layout = Layout::Columnar;
+    let mut file = Vec::new();
+    write_database(&mut file, &db);
+
+    let queries = sample_queries(&db);
+    let expected = search(&db.rows, &queries);
+    assert!(expected.iter().any(|rows| !rows.is_empty()));
+    assert!(expected.last().unwrap().is_empty());
+
+    let parsed = ColumnarFile::parse(&file).unwrap();
+    assert_eq!(format!("{:?}", parsed.search(&queries).unwrap()), format!("{:?}", expected));
+    assert_eq!(format!("{:?}", search_file(&file, &queries).unwrap()), format!("{:?}", expected));
+}
+
+#[test]
+fn corrupted_block_is_an_error() {
+    let mut db = sample_database(Character::Fox, Character::Marth, 100);
+    db.header.layout = Layout::Columnar;
+    let mut file = Vec::new();
+    write_database(&mut file, &db);
+
+    let offset = ColumnarFile::parse(&file).unwrap().blocks[0].offset;
+    file[offset + 4] ^= 0xFF;
+    assert!(read_database(&file).is_err());
+}
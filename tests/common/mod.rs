@@ -0,0 +1,178 @@
+// each test crate uses only some of these
+#![allow(dead_code)]
+
+use slp_action_db::parse_old_game::FIRST_FRAME;
+
+const GAME_START_SIZE: u16 = 0x248;
+const PRE_FRAME_SIZE: u16 = 0x3F;
+const POST_FRAME_SIZE: u16 = 0x34;
+const BOOKEND_SIZE: u16 = 8;
+const FRAME_START_SIZE: u16 = 0xC;
+
+/// Display names of ports 1 and 2 in the synthetic replay.
+pub const NAMES: [&str; 2] = ["Alpha", "Beta"];
+
+/// Connect codes of ports 1 and 2 in the synthetic replay, as the game writes them with a fullwidth '#'.
+pub const CONNECT_CODES: [&[u8]; 2] = [b"AB\x81\x94123", b"CD\x81\x94456"];
+
+/// A two player Fox ditto on Final Destination where both players walk back and forth
+/// and cycle through a few actions out of step with each other,
+/// written the way Slippi writes a game in progress: raw length 0 and no metadata.
+/// Returns the file and the offsets where each frame ends.
+pub fn synthetic_replay(frame_count: i32) -> (Vec<u8>, Vec<usize>) {
+    Synthetic::new(frame_count).write()
+}
+
+/// The synthetic replay with its raw length filled in, as Slippi writes a finished game.
+pub fn finished_replay(frame_count: i32) -> (Vec<u8>, Vec<usize>) {
+    Synthetic::new(frame_count).finished()
+}
+
+/// The synthetic replay, with the parts tests vary.
+#[derive(Clone, Debug)]
+pub struct Synthetic {
+    pub frame_count: i32,
+    /// The game end event after its command byte. Defaults to an ordinary game end without placements.
+    pub game_end: Vec<u8>,
+    /// Stocks and percent of ports 1 and 2 on the last frame. Both have 4 stocks and 0% otherwise.
+    pub last_frame: [(u8, f32); 2],
+    /// Write a frame start event before each frame, with the scene frame counter, as 3.10.0 and later do.
+    pub frame_starts: bool,
+    /// A frame left out entirely, as if dropped from the recording.
+    pub dropped_frame: Option<i32>,
+    /// A frame first written with both players elsewhere, then rolled back and written again as usual.
+    pub rolled_back_frame: Option<i32>,
+    /// From this frame on the scene frame counter runs one ahead, as if the game lagged a frame the recording missed.
+    pub desynced_frame: Option<i32>,
+}
+
+impl Synthetic {
+    pub fn new(frame_count: i32) -> Synthetic {
+        Synthetic {
+            frame_count,
+            game_end: vec![2, 0xFF],
+            last_frame: [(4, 0.0); 2],
+            frame_starts: false,
+            dropped_frame: None,
+            rolled_back_frame: None,
+            desynced_frame: None,
+        }
+    }
+
+    /// Raw length 0, as in `synthetic_replay`.
+    pub fn write(&self) -> (Vec<u8>, Vec<usize>) {
+        write_synthetic(self)
+    }
+
+    /// Raw length filled in, as in `finished_replay`.
+    pub fn finished(&self) -> (Vec<u8>, Vec<usize>) {
+        let (mut slp, frame_ends) = self.write();
+        let raw_len = (slp.len() - 15) as u32;
+        slp[11..15].copy_from_slice(&raw_len.to_be_bytes());
+        (slp, frame_ends)
+    }
+}
+
+fn write_synthetic(synthetic: &Synthetic) -> (Vec<u8>, Vec<usize>) {
+    let mut slp = Vec::new();
+    slp.extend_from_slice(b"{U\x03raw[$U#l");
+    slp.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut sizes = vec![
+        (0x36, GAME_START_SIZE),
+        (0x37, PRE_FRAME_SIZE),
+        (0x38, POST_FRAME_SIZE),
+        (0x39, synthetic.game_end.len() as u16),
+        (0x3C, BOOKEND_SIZE),
+    ];
+    if synthetic.frame_starts { sizes.push((0x3A, FRAME_START_SIZE)); }
+    slp.push(0x35);
+    slp.push(1 + 3 * sizes.len() as u8);
+    for (cmd, size) in sizes {
+        slp.push(cmd);
+        slp.extend_from_slice(&size.to_be_bytes());
+    }
+
+    let mut game_start = vec![0u8; GAME_START_SIZE as usize + 1];
+    game_start[0] = 0x36;
+    game_start[1..5].copy_from_slice(&[3, 9, 0, 0]); // names and connect codes were added in 3.9.0
+    let block = &mut game_start[5..];
+    block[0xE..0x10].copy_from_slice(&32u16.to_be_bytes());
+    for i in 0..4 {
+        block[0x60 + 0x24*i] = 2; // fox
+        block[0x61 + 0x24*i] = if i < 2 { 0 } else { 3 };
+    }
+    for i in 0..2 {
+        game_start[0x1A5 + 0x1F*i..][..NAMES[i].len()].copy_from_slice(NAMES[i].as_bytes());
+        game_start[0x221 + 0xA*i..][..CONNECT_CODES[i].len()].copy_from_slice(CONNECT_CODES[i]);
+    }
+    slp.extend_from_slice(&game_start);
+
+    let mut frame_ends = Vec::new();
+    for frame in FIRST_FRAME..FIRST_FRAME + synthetic.frame_count {
+        if synthetic.dropped_frame == Some(frame) { continue; }
+        if synthetic.rolled_back_frame == Some(frame) {
+            push_frame(&mut slp, synthetic, frame, 10.0);
+            frame_ends.push(slp.len());
+        }
+        push_frame(&mut slp, synthetic, frame, 0.0);
+        frame_ends.push(slp.len());
+    }
+
+    slp.push(0x39);
+    slp.extend_from_slice(&synthetic.game_end);
+    (slp, frame_ends)
+}
+
+/// Writes one frame's events, with both players moved `x_offset` along.
+fn push_frame(slp: &mut Vec<u8>, synthetic: &Synthetic, frame: i32, x_offset: f32) {
+    if synthetic.frame_starts {
+        let mut frame_start = vec![0u8; FRAME_START_SIZE as usize + 1];
+        frame_start[0] = 0x3A;
+        frame_start[1..5].copy_from_slice(&frame.to_be_bytes());
+        frame_start[5..9].copy_from_slice(&((frame - FIRST_FRAME) as u32 * 31).to_be_bytes());
+        // the scene started a few frames before the game
+        let lag = synthetic.desynced_frame.is_some_and(|f| frame >= f) as u32;
+        frame_start[9..13].copy_from_slice(&((frame - FIRST_FRAME) as u32 + 7 + lag).to_be_bytes());
+        slp.extend_from_slice(&frame_start);
+    }
+
+    for port in 0..2u8 {
+        let mut pre = vec![0u8; PRE_FRAME_SIZE as usize + 1];
+        pre[0] = 0x37;
+        pre[1..5].copy_from_slice(&frame.to_be_bytes());
+        pre[5] = port;
+        slp.extend_from_slice(&pre);
+
+        let mut post = vec![0u8; POST_FRAME_SIZE as usize + 1];
+        post[0] = 0x38;
+        post[1..5].copy_from_slice(&frame.to_be_bytes());
+        post[5] = port;
+        post[7] = 1; // fox
+        post[8..10].copy_from_slice(&synthetic_state(frame, port).to_be_bytes());
+        let x = ((frame - FIRST_FRAME + 40 * port as i32) % 160 - 80) as f32 * 0.5 + x_offset;
+        post[0xA..0xE].copy_from_slice(&x.to_be_bytes());
+        let (stocks, percent) = if frame == FIRST_FRAME + synthetic.frame_count - 1 { synthetic.last_frame[port as usize] } else { (4, 0.0) };
+        post[0x16..0x1A].copy_from_slice(&percent.to_be_bytes());
+        post[0x21] = stocks;
+        slp.extend_from_slice(&post);
+    }
+
+    let mut bookend = vec![0u8; BOOKEND_SIZE as usize + 1];
+    bookend[0] = 0x3C;
+    bookend[1..5].copy_from_slice(&frame.to_be_bytes());
+    slp.extend_from_slice(&bookend);
+}
+
+/// Appends a metadata block to a finished replay and closes the outer object.
+pub fn push_metadata(slp: &mut Vec<u8>, metadata: &slp_action_db::ubjson::Value) {
+    slp.extend_from_slice(b"U\x08metadata");
+    slp_action_db::ubjson::write_value(slp, metadata);
+    slp.push(b'}');
+}
+
+/// Wait, dash, jab, jump squat, for a few dozen frames each.
+fn synthetic_state(frame: i32, port: u8) -> u16 {
+    const STATES: [u16; 4] = [14, 20, 44, 24];
+    STATES[((frame - FIRST_FRAME + 17 * port as i32) / 23 % 4) as usize]
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "arrow")]
+
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::dataframe::*;
+use slp_parser::Character;
+use sample::{sample_database, sample_queries};
+
+/// The sample rows with the values that are easy to lose on the way through another format.
+fn awkward_database() -> Database {
+    let mut db = sample_database(Character::Fox, Character::Marth, 50);
+    db.header.version = 2;
+    db.rows[0].score = f32::NAN;
+    db.rows[1].player_response.pos_x = f32::NEG_INFINITY;
+    db.rows[2].opponent_initiation.pos_y = -0.0;
+    db.rows[3].player_code = [0xFF; 10];
+    db.rows[4].frame = i32::MIN;
+    assert!(db.rows.iter().any(|r| r.source == Row::NO_SOURCE));
+    db
+}
+
+fn assert_same(read: &Database, db: &Database) {
+    assert_eq!(read.header.version, VERSION);
+    assert_eq!(read.header.player_character, db.header.player_character);
+    assert_eq!(read.header.opponent_character, db.header.opponent_character);
+    assert_eq!(read.header.row_count as usize, db.rows.len());
+    assert_eq!(read.header.state_index, db.header.state_index);
+    assert_eq!(read.sources, db.sources);
+    // Debug shows NaN and -0.0 as themselves
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+}
+
+fn ipc_round_trip(db: &Database) -> Database {
+    let mut buf = Vec::new();
+    write_ipc(&mut buf, db).unwrap();
+    read_ipc(std::io::Cursor::new(buf)).unwrap()
+}
+
+fn parquet_round_trip(db: &Database, name: &str) -> Database {
+    let path = std::env::temp_dir().join(format!("slp_action_db_{}_{}.parquet", name, std::process::id()));
+    write_parquet(std::fs::File::create(&path).unwrap(), db).unwrap();
+    let read = read_parquet(std::fs::File::open(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    read
+}
+
+#[test]
+fn ipc_round_trip_keeps_rows() {
+    let db = awkward_database();
+    let read = ipc_round_trip(&db);
+    assert_same(&read, &db);
+
+    // and writes back out as the current version
+    let mut buf = Vec::new();
+    write_database(&mut buf, &read);
+    assert_eq!(u32::from_le_bytes(buf[..4].try_into().unwrap()), VERSION);
+    assert!(read_database(&buf).is_ok());
+}
+
+#[test]
+fn parquet_round_trip_keeps_rows() {
+    let mut db = awkward_database();
+    sort_database(&mut db);
+    let read = parquet_round_trip(&db, "sorted");
+    assert_same(&read, &db);
+
+    let queries = sample_queries(&db);
+    assert_eq!(format!("{:?}", read.search(&queries)), format!("{:?}", db.search(&queries)));
+}
+
+#[test]
+fn unusual_source_manifests_round_trip() {
+    let db = awkward_database();
+    let manifests = [
+        vec![],
+        vec![String::new()],
+        vec![String::new(), String::new()],
+        vec!["a:1".to_string(), "\u{e9}\0".to_string(), "12:".to_string()],
+    ];
+    for sources in manifests {
+        let source = if sources.is_empty() { Row::NO_SOURCE } else { 0 };
+        let db = Database {
+            rows: db.rows.iter().map(|r| Row { source, ..r.clone() }).collect(),
+            header: Header { source_count: sources.len() as u32, ..db.header.clone() },
+            sources: sources.clone(),
+        };
+        assert_eq!(ipc_round_trip(&db).sources, sources);
+        assert_eq!(parquet_round_trip(&db, "sources").sources, sources);
+    }
+}
@@ -0,0 +1,115 @@
+mod sample;
+
+use slp_action_db::*;
+use slp_parser::Character;
+use sample::{sample_database, write_version};
+
+/// Fills in the checksum again after the body was changed, so reading gets as far as the change.
+fn refill_checksum(file: &mut [u8]) {
+    let checksum = fnv1a(FNV_OFFSET, &file[Header::WRITTEN_SIZE..]);
+    file[16..24].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn written(db: &Database) -> Vec<u8> {
+    let mut file = Vec::new();
+    write_database(&mut file, db);
+    file
+}
+
+#[test]
+fn bad_codes_give_the_row_and_offset() {
+    let db = sample_database(Character::Fox, Character::Marth, 20);
+    let row_start = |i: usize| Header::WRITTEN_SIZE + i * Row::WRITTEN_SIZE;
+
+    // the player's start state in row 7
+    let mut file = written(&db);
+    file[row_start(7) + 12..][..2].copy_from_slice(&u16::MAX.to_le_bytes());
+    refill_checksum(&mut file);
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind.clone(), e.offset, e.row), (DBErrorKind::BadStateCode(u16::MAX), row_start(7) + 12, Some(7)));
+    assert_eq!(e.to_string(), format!("invalid state code 65535 at byte {} in row 7", row_start(7) + 12));
+
+    // the opponent's action in row 3
+    let mut file = written(&db);
+    file[row_start(3) + 2..][..2].copy_from_slice(&u16::MAX.to_le_bytes());
+    refill_checksum(&mut file);
+    let e = read_file(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::BadActionCode(u16::MAX), row_start(3) + 2, Some(3)));
+
+    // without a checksum, in a past version, offsets are still in the file as given
+    let mut file = write_version(&db, 3);
+    file[16 + 5 * Row::WRITTEN_SIZE..][..2].copy_from_slice(&u16::MAX.to_le_bytes());
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::BadStateCode(u16::MAX), 16 + 5 * Row::WRITTEN_SIZE, Some(5)));
+
+    let mut file = write_version(&db, 0);
+    file[8 + 2 * 28 + 14..][..2].copy_from_slice(&u16::MAX.to_le_bytes());
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::BadActionCode(u16::MAX), 8 + 2 * 28 + 14, Some(2)));
+}
+
+#[test]
+fn truncated_files_give_where_they_end() {
+    let db = sample_database(Character::Fox, Character::Marth, 20);
+
+    // part way through the header
+    let e = read_database(&written(&db)[..10]).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::Truncated, 0, None));
+    let e = read_database(&written(&db)[..2]).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::Truncated, 0, None));
+
+    // part way through row 5, with the checksum of what is left
+    let mut file = written(&db);
+    file.truncate(Header::WRITTEN_SIZE + 5 * Row::WRITTEN_SIZE + 20);
+    refill_checksum(&mut file);
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind.clone(), e.offset, e.row), (DBErrorKind::Truncated, Header::WRITTEN_SIZE + 5 * Row::WRITTEN_SIZE, Some(5)));
+    assert_eq!(e.to_string(), format!("file is truncated at byte {} in row 5", Header::WRITTEN_SIZE + 5 * Row::WRITTEN_SIZE));
+
+    // part way through the second source
+    let sources_start = Header::WRITTEN_SIZE + db.rows.len() * Row::WRITTEN_SIZE;
+    let second_source = sources_start + 2 + db.sources[0].len();
+    let mut file = written(&db);
+    file.truncate(second_source + 3);
+    refill_checksum(&mut file);
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::Truncated, second_source, None));
+
+    // past versions
+    let mut file = write_version(&db, 3);
+    file.truncate(16 + 5 * Row::WRITTEN_SIZE + 20);
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::Truncated, 16 + 5 * Row::WRITTEN_SIZE, Some(5)));
+
+    let mut file = write_version(&db, 1);
+    file.truncate(8 + 4 * 38 + 7);
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset, e.row), (DBErrorKind::Truncated, 8 + 4 * 38, Some(4)));
+}
+
+#[test]
+fn versions_and_header_errors() {
+    let db = sample_database(Character::Fox, Character::Marth, 5);
+
+    let mut file = written(&db);
+    file[..4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind.clone(), e.offset, e.row), (DBErrorKind::VersionTooNew(VERSION + 1), 0, None));
+    assert_eq!(e.to_string(), format!("file version {} is newer than this library (version {}) at byte 0", VERSION + 1, VERSION));
+    assert_eq!(read_file(&file).unwrap_err().kind, DBErrorKind::VersionTooNew(VERSION + 1));
+
+    let mut file = written(&db);
+    file[5] = 200;
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind.clone(), e.offset, e.row), (DBErrorKind::BadCharacter(200), 5, None));
+    assert_eq!(e.to_string(), "invalid character 200 at byte 5");
+
+    let mut file = written(&db);
+    file[6] = 9;
+    let e = read_database(&file).unwrap_err();
+    assert_eq!(e.to_string(), "invalid layout 9 at byte 6");
+
+    let mut file = written(&db);
+    *file.last_mut().unwrap() ^= 1;
+    assert_eq!(read_database(&file).unwrap_err().to_string(), "checksum does not match the contents at byte 16");
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use slp_action_db::parse_old_game::*;
+use common::Synthetic;
+
+fn with_frame_starts(frame_count: i32) -> Synthetic {
+    Synthetic { frame_starts: true, ..Synthetic::new(frame_count) }
+}
+
+fn parsed(synthetic: &Synthetic) -> FullGame {
+    let (slp, _) = synthetic.finished();
+    let game = parse_old_file_full(&slp).unwrap();
+
+    // streaming sees the same frame starts and rollbacks
+    let streamed = parse_old_stream(&slp[..]).unwrap();
+    assert_eq!(streamed.frame_starts, game.frame_starts);
+    assert_eq!(streamed.rollback_frames, game.rollback_frames);
+    game
+}
+
+fn frames_debug(game: &FullGame) -> Vec<String> {
+    game.game.frames.iter().map(|f| format!("{:?}", f)).collect()
+}
+
+#[test]
+fn frame_start_events() {
+    let mut event = vec![FRAME_START];
+    event.extend_from_slice(&(-100i32).to_be_bytes());
+    event.extend_from_slice(&0xDEADBEEFu32.to_be_bytes());
+    assert_eq!(parse_frame_start(&event), FrameStart { frame: -100, random_seed: 0xDEADBEEF, scene_frame_counter: None });
+
+    // the scene frame counter was added in 3.10.0
+    event.extend_from_slice(&1234u32.to_be_bytes());
+    assert_eq!(parse_frame_start(&event).scene_frame_counter, Some(1234));
+}
+
+#[test]
+fn continuous_replay() {
+    let game = parsed(&with_frame_starts(300));
+    assert_eq!(game.frame_starts.len(), 300);
+    for (i, frame_start) in game.frame_starts.iter().enumerate() {
+        let frame_start = frame_start.unwrap();
+        assert_eq!(frame_start.frame, i as i32 + FIRST_FRAME);
+        assert_eq!(frame_start.random_seed, i as u32 * 31);
+        assert_eq!(frame_start.scene_frame_counter, Some(i as u32 + 7));
+    }
+    assert!(missing_frames(&game.frame_starts).is_empty());
+    assert!(scene_counter_discontinuities(&game.frame_starts).is_empty());
+    assert!(game.rollback_frames.is_empty());
+
+    // replays from before frame start events have none to check
+    let old = parsed(&Synthetic::new(300));
+    assert!(old.frame_starts.iter().all(|f| f.is_none()));
+    assert!(missing_frames(&old.frame_starts).is_empty());
+    assert!(scene_counter_discontinuities(&old.frame_starts).is_empty());
+}
+
+#[test]
+fn dropped_frame() {
+    let game = parsed(&Synthetic { dropped_frame: Some(50), ..with_frame_starts(300) });
+    assert_eq!(missing_frames(&game.frame_starts), [50]);
+    // the gap is a missing frame, not a jump in the counter
+    assert!(scene_counter_discontinuities(&game.frame_starts).is_empty());
+    assert!(game.rollback_frames.is_empty());
+
+    let dropped = (50 - FIRST_FRAME) as usize;
+    for port in 0..2 {
+        let frames = game.game.frames[port].as_ref().unwrap();
+        assert_eq!(format!("{:?}", frames[dropped]), format!("{:?}", slp_parser::Frame::NULL));
+        assert_ne!(format!("{:?}", frames[dropped + 1]), format!("{:?}", slp_parser::Frame::NULL));
+    }
+}
+
+#[test]
+fn desynced_frame() {
+    let game = parsed(&Synthetic { desynced_frame: Some(120), ..with_frame_starts(300) });
+    assert_eq!(scene_counter_discontinuities(&game.frame_starts), [120]);
+    assert!(missing_frames(&game.frame_starts).is_empty());
+}
+
+#[test]
+fn rolled_back_frame() {
+    let game = parsed(&Synthetic { rolled_back_frame: Some(80), ..with_frame_starts(300) });
+    assert_eq!(game.rollback_frames, [80]);
+    assert!(missing_frames(&game.frame_starts).is_empty());
+    assert!(scene_counter_discontinuities(&game.frame_starts).is_empty());
+
+    // the frame written again replaces the rolled back one
+    assert_eq!(frames_debug(&game), frames_debug(&parsed(&with_frame_starts(300))));
+}
+
+#[test]
+fn dropped_rolled_back_and_desynced_frames_together() {
+    let game = parsed(&Synthetic {
+        dropped_frame: Some(10),
+        rolled_back_frame: Some(11),
+        desynced_frame: Some(40),
+        ..with_frame_starts(200)
+    });
+    assert_eq!(missing_frames(&game.frame_starts), [10]);
+    assert_eq!(scene_counter_discontinuities(&game.frame_starts), [40]);
+    assert_eq!(game.rollback_frames, [11]);
+}
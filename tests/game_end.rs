@@ -0,0 +1,107 @@
+mod common;
+
+use slp_action_db::*;
+use slp_action_db::parse_old_game::*;
+use common::{synthetic_replay, Synthetic, CONNECT_CODES};
+
+fn parsed(synthetic: &Synthetic) -> FullGame {
+    parse_old_file_full(&synthetic.finished().0).unwrap()
+}
+
+fn ending(game_end: &[u8]) -> Synthetic {
+    Synthetic { game_end: game_end.to_vec(), ..Synthetic::new(600) }
+}
+
+fn code(port: usize) -> ConnectCode {
+    let mut code = [0; 10];
+    code[..CONNECT_CODES[port].len()].copy_from_slice(CONNECT_CODES[port]);
+    code
+}
+
+#[test]
+fn game_end_events() {
+    assert!(parse_game_end(&[GAME_END]).is_none());
+    assert!(parse_game_end(&[GAME_END + 1, 2]).is_none());
+
+    let old = parse_game_end(&[GAME_END, 3]).unwrap();
+    assert_eq!((old.method, old.lras_initiator, old.placements), (GameEndMethod::Resolved, None, None));
+
+    let quit = parse_game_end(&[GAME_END, 7, 1]).unwrap();
+    assert_eq!((quit.method, quit.lras_initiator, quit.placements), (GameEndMethod::NoContest, Some(1), None));
+    assert!(quit.is_quit_out());
+
+    let placed = parse_game_end(&[GAME_END, 2, 0xFF, 1, 0, 0xFF, 0xFF]).unwrap();
+    assert_eq!((placed.method, placed.lras_initiator, placed.placements), (GameEndMethod::Game, None, Some([1, 0, -1, -1])));
+    assert!(!placed.is_quit_out());
+
+    assert_eq!(parse_game_end(&[GAME_END, 9]).unwrap().method, GameEndMethod::Unknown(9));
+    assert!(parse_game_end(&[GAME_END, 7, 0xFF]).unwrap().is_quit_out());
+}
+
+#[test]
+fn winner_from_placements_then_quit_out_then_stocks() {
+    // placements win over everything else
+    let placements = Synthetic { last_frame: [(4, 0.0), (1, 0.0)], ..ending(&[2, 0xFF, 1, 0, 0xFF, 0xFF]) };
+    assert_eq!(parsed(&placements).winner(), Some(1));
+
+    // the player who quit out loses, whatever the stocks
+    let quit = Synthetic { last_frame: [(4, 0.0), (1, 0.0)], ..ending(&[7, 0]) };
+    let game = parsed(&quit);
+    assert!(game.game_end.unwrap().is_quit_out());
+    assert_eq!(game.winner(), Some(1));
+    assert_eq!(parsed(&ending(&[7, 1])).winner(), Some(0));
+    assert_eq!(parsed(&ending(&[7, 3])).winner(), None);
+
+    // more stocks, then less percent
+    let stocks = Synthetic { last_frame: [(2, 0.0), (3, 120.0)], ..ending(&[2, 0xFF]) };
+    assert_eq!(parsed(&stocks).winner(), Some(1));
+    let percent = Synthetic { last_frame: [(2, 50.0), (2, 80.0)], ..ending(&[2, 0xFF]) };
+    assert_eq!(parsed(&percent).winner(), Some(0));
+    let tie = Synthetic { last_frame: [(2, 50.0), (2, 50.0)], ..ending(&[1, 0xFF]) };
+    assert_eq!(parsed(&tie).winner(), None);
+
+    // no game end, no winner
+    let (slp, frame_ends) = synthetic_replay(600);
+    let cut = parse_old_stream(&slp[..*frame_ends.last().unwrap()]).unwrap();
+    assert!(cut.game_end.is_none());
+    assert_eq!(cut.winner(), None);
+}
+
+#[test]
+fn quit_outs_are_skipped_if_asked() {
+    let skip = build::BuildOptions { skip_quit_outs: true, ..Default::default() };
+    let mut rows = Vec::new();
+
+    for game_end in [&[7, 0][..], &[7, 0xFF], &[2, 1]] {
+        let game = parsed(&ending(game_end));
+        assert_eq!(build::push_game_rows(&mut rows, &game, 0, &skip), Err(build::SkipReason::QuitOut));
+        assert!(rows.is_empty());
+        build::push_game_rows(&mut rows, &game, 0, &build::BuildOptions::default()).unwrap();
+        assert!(!rows.is_empty());
+        rows.clear();
+    }
+
+    build::push_game_rows(&mut rows, &parsed(&ending(&[2, 0xFF])), 0, &skip).unwrap();
+    assert!(!rows.is_empty());
+}
+
+#[test]
+fn rows_are_weighted_by_the_winner() {
+    let options = build::BuildOptions { winner_weight: 2.0, loser_weight: 0.5, ..Default::default() };
+
+    let game = parsed(&ending(&[2, 0xFF, 1, 0, 0xFF, 0xFF]));
+    let mut rows = Vec::new();
+    build::push_game_rows(&mut rows, &game, 0, &options).unwrap();
+    assert!(rows.iter().any(|row| row.player_code == code(0)));
+    assert!(rows.iter().any(|row| row.player_code == code(1)));
+    for row in rows.iter() {
+        let expected = if row.player_code == code(1) { 2.0 } else { 0.5 };
+        assert_eq!(row.weight, expected);
+    }
+
+    // ties weigh everyone the same
+    let tie = parsed(&Synthetic { last_frame: [(2, 50.0), (2, 50.0)], ..ending(&[1, 0xFF]) });
+    let mut rows = Vec::new();
+    build::push_game_rows(&mut rows, &tie, 0, &options).unwrap();
+    assert!(rows.iter().all(|row| row.weight == 1.0));
+}
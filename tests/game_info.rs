@@ -0,0 +1,55 @@
+mod common;
+
+use slp_action_db::parse_old_game::*;
+use slp_action_db::ubjson::Value;
+use common::{finished_replay, push_metadata, CONNECT_CODES, NAMES};
+
+/// Offset of the game start event, after the raw header and the event payloads event.
+const GAME_START_OFFSET: usize = 15 + 2 + 3 * 5;
+
+fn padded<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut padded = [0u8; N];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    padded
+}
+
+#[test]
+fn game_start_has_names_and_connect_codes() {
+    let (slp, _) = finished_replay(300);
+    let game_start = parse_game_start(&slp[GAME_START_OFFSET..]).unwrap();
+
+    for port in 0..2 {
+        assert_eq!(game_start.names[port], padded::<31>(NAMES[port].as_bytes()));
+        assert_eq!(game_start.connect_codes[port], padded::<10>(CONNECT_CODES[port]));
+    }
+    assert_eq!(game_start.names[2..], [[0; 31]; 2]);
+    assert_eq!(game_start.connect_codes[2..], [[0; 10]; 2]);
+
+    // replays older than 3.9.0 have neither
+    let old = parse_game_start(&slp[GAME_START_OFFSET..][..0x221]).unwrap();
+    assert_eq!(old.names, [[0; 31]; 4]);
+    assert_eq!(old.connect_codes, [[0; 10]; 4]);
+}
+
+#[test]
+fn game_info_is_filled_in() {
+    let (slp, _) = finished_replay(300);
+    let game = parse_old_file_full(&slp).unwrap();
+    for port in 0..2 {
+        assert_eq!(game.game.info.names[port], padded::<31>(NAMES[port].as_bytes()));
+        assert_eq!(game.game.info.connect_codes[port], padded::<10>(CONNECT_CODES[port]));
+    }
+
+    // without metadata, the duration is the number of frames and the start time is unknown
+    assert_eq!(game.game.info.duration, 300);
+    assert_eq!(game.game.info.start_time.0, 0);
+
+    let mut with_metadata = slp.clone();
+    push_metadata(&mut with_metadata, &Value::Object(vec![
+        ("startAt".to_string(), Value::String("2020-07-12T18:31:45Z".to_string())),
+        ("lastFrame".to_string(), Value::Int(150)),
+    ]));
+    let game = parse_old_file_full(&with_metadata).unwrap();
+    assert_eq!(game.game.info.duration, (150 - FIRST_FRAME + 1) as u32);
+    assert_eq!(game.game.info.start_time.0, 1594578705);
+}
@@ -0,0 +1,72 @@
+use slp_action_db::gecko::*;
+
+fn words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+#[test]
+fn parse_code_types() {
+    let mut list = Vec::new();
+    list.extend(words(&[0x043AB000, 0x00000001])); // 32 bit write
+    list.extend(words(&[0x053AB004, 0x00000002])); // same with the address high bit set
+    list.extend(words(&[0xC21A0000, 0x00000002, 0x60000000, 0x60000000, 0x60000000, 0x00000000])); // asm insert, 2 lines
+    list.extend(words(&[0x06100000, 0x00000005])); // string write, 5 bytes padded to 8
+    list.extend(b"hello\0\0\0");
+    list.extend(words(&[0x08200000, 0x00000003, 0x00010002, 0x00000004])); // serial write
+    list.extend(words(&[0xF0000000, 0x00000000])); // end of list
+    list.extend(words(&[0x04000000, 0x00000000])); // after the end, ignored
+
+    let codes = parse_gecko_list(&list).codes;
+    assert_eq!(codes.len(), 5);
+    assert_eq!(codes.iter().map(|c| c.code_type).collect::<Vec<_>>(), [0x04, 0x04, 0xC2, 0x06, 0x08]);
+    assert_eq!(codes[0].address, 0x803AB000);
+    assert_eq!(codes[1].address, 0x813AB004);
+    assert_eq!(codes[0].contents, words(&[1]));
+    assert_eq!(codes[2].contents.len(), 4 + 16);
+    assert_eq!(&codes[3].contents[4..9], b"hello");
+    assert_eq!(codes[4].contents.len(), 12);
+}
+
+#[test]
+fn parse_drops_partial_code() {
+    let mut list = words(&[0x043AB000, 0x00000001]);
+    list.extend(words(&[0xC21A0000, 0x00000004, 0x60000000])); // asm insert cut off
+    assert_eq!(parse_gecko_list(&list).codes.len(), 1);
+
+    list.truncate(12);
+    assert_eq!(parse_gecko_list(&list).codes.len(), 1);
+    assert!(parse_gecko_list(&[]).codes.is_empty());
+}
+
+#[test]
+fn classify_by_fingerprint() {
+    let a = words(&[0x043AB000, 0x00000001]);
+    let b = words(&[0x04100000, 0x00000002]);
+    let vanilla = parse_gecko_list(&[a.clone(), b.clone()].concat());
+    let reordered = parse_gecko_list(&[b.clone(), a.clone()].concat());
+    let modded = parse_gecko_list(&[a.clone(), b, words(&[0x04200000, 0x00000003])].concat());
+    let unknown = parse_gecko_list(&a);
+
+    assert_eq!(vanilla.fingerprint(), reordered.fingerprint());
+    assert_ne!(vanilla.fingerprint(), modded.fingerprint());
+
+    let text = format!(
+        "# known builds\n{:016x} vanilla\n\n{:x} unclepunch\n",
+        vanilla.fingerprint(), modded.fingerprint(),
+    );
+    let registry = ModRegistry::parse(&text).unwrap();
+    assert_eq!(registry.classify(&reordered), Some(ModFamily::Vanilla));
+    assert_eq!(registry.classify(&modded), Some(ModFamily::UnclePunch));
+    assert_eq!(registry.classify(&unknown), None);
+
+    assert!(!registry.is_modded(&vanilla));
+    assert!(registry.is_modded(&modded));
+    assert!(!registry.is_modded(&unknown));
+    assert!(!ModRegistry::default().is_modded(&modded));
+
+    let written = registry.write();
+    assert_eq!(ModRegistry::parse(&written).unwrap().fingerprints, registry.fingerprints);
+
+    assert_eq!(ModRegistry::parse("0123 vanilla\nnot hex\n").unwrap_err(), 2);
+    assert_eq!(ModRegistry::parse("0123 retail\n").unwrap_err(), 1);
+}
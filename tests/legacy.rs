@@ -0,0 +1,108 @@
+mod sample;
+
+use slp_action_db::*;
+use slp_parser::Character;
+use sample::{sample_database, sample_queries, write_version};
+
+fn written(db: &Database) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_database(&mut buf, db);
+    buf
+}
+
+#[test]
+fn legacy_files_round_trip_through_the_current_version() {
+    let db = sample_database(Character::Fox, Character::Marth, 40);
+
+    for version in 0..=5 {
+        let file = write_version(&db, version);
+        let read = read_database(&file).unwrap();
+        assert_eq!(read.header.version, VERSION, "version {}", version);
+        assert_eq!(read.rows.len(), db.rows.len());
+
+        for (read_row, row) in read.rows.iter().zip(db.rows.iter()) {
+            let expected = Row {
+                weight: if version >= 2 { row.weight } else { 1.0 },
+                player_code: if version >= 1 { row.player_code } else { [0; 10] },
+                source: if version >= 3 { row.source } else { Row::NO_SOURCE },
+                frame: if version >= 3 { row.frame } else { 0 },
+                ..row.clone()
+            };
+            assert_eq!(format!("{:?}", read_row), format!("{:?}", expected), "version {}", version);
+        }
+        assert_eq!(read.sources, if version >= 3 { db.sources.clone() } else { Vec::new() });
+
+        // writing out what was read gives a current file that reads back the same
+        let rewritten = written(&read);
+        assert_eq!(read_u32_le(&rewritten), VERSION);
+        let reread = read_database(&rewritten).unwrap();
+        assert_eq!(format!("{:?}", reread.rows), format!("{:?}", read.rows));
+        assert_eq!(reread.sources, read.sources);
+        assert_eq!(written(&reread), rewritten);
+        let queries = sample_queries(&db);
+        assert_eq!(format!("{:?}", reread.search(&queries)), format!("{:?}", search(&read.rows, &queries)));
+
+        assert_eq!(legacy::upgrade(&file).unwrap(), rewritten);
+    }
+}
+
+#[test]
+fn columnar_version_4_files_are_read() {
+    let db = sample_database(Character::Fox, Character::Marth, 5000);
+
+    // version 4 wrote the columnar layout as now, without the checksum
+    let mut current = Vec::new();
+    columnar::write_columnar(&mut current, &db);
+    let mut file = current.clone();
+    file.drain(16..24);
+    file[..4].copy_from_slice(&4u32.to_le_bytes());
+
+    let read = read_database(&file).unwrap();
+    assert_eq!(read.header.layout, Layout::Columnar);
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+    assert_eq!(read.sources, db.sources);
+    assert_eq!(written(&read), current);
+}
+
+#[test]
+fn sorted_version_5_files_are_read() {
+    let mut db = sample_database(Character::Fox, Character::Marth, 60);
+    sort_database(&mut db);
+
+    let read = read_database(&write_version(&db, 5)).unwrap();
+    assert_eq!(read.header.state_index, db.header.state_index);
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+    assert_eq!(written(&read), written(&Database { header: Header { version: VERSION, ..db.header.clone() }, ..db }));
+}
+
+#[test]
+fn bytes_a_version_did_not_use_are_rejected() {
+    let db = sample_database(Character::Fox, Character::Marth, 5);
+
+    let mut file = write_version(&db, 3);
+    file[6] = 1;
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset), (DBErrorKind::BadLayout(1), 6));
+
+    let mut file = write_version(&db, 4);
+    file[7] = 1;
+    let e = read_database(&file).unwrap_err();
+    assert_eq!((e.kind, e.offset), (DBErrorKind::BadLayout(1), 7));
+}
+
+#[test]
+fn upgrade_file_reports_the_original_version() {
+    let db = sample_database(Character::Fox, Character::Fox, 10);
+    let path = std::env::temp_dir().join(format!("slp_action_db_legacy_{}.actions", std::process::id()));
+
+    std::fs::write(&path, write_version(&db, 2)).unwrap();
+    assert_eq!(legacy::upgrade_file(&path).unwrap(), 2);
+    assert_eq!(read_u32_le(&std::fs::read(&path).unwrap()), VERSION);
+    assert_eq!(legacy::upgrade_file(&path).unwrap(), VERSION);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn read_u32_le(file: &[u8]) -> u32 {
+    u32::from_le_bytes(file[..4].try_into().unwrap())
+}
@@ -0,0 +1,133 @@
+mod common;
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::merge::*;
+use slp_parser::Character;
+use common::finished_replay;
+use sample::{sample_database, sample_queries};
+
+/// Each row with its source path in place of the source index, to compare rows across manifests.
+fn with_paths(db: &Database) -> Vec<String> {
+    let mut rows = db.rows.iter()
+        .map(|row| format!("{:?} {:?}", Row { source: 0, ..row.clone() }, db.sources.get(row.source as usize)))
+        .collect::<Vec<_>>();
+    rows.sort();
+    rows
+}
+
+#[test]
+fn merge_remaps_sources() {
+    let a = sample_database(Character::Fox, Character::Marth, 30);
+    let mut b = sample_database(Character::Fox, Character::Marth, 20);
+    b.sources = vec![a.sources[1].clone(), "d/other.slp".to_string(), a.sources[0].clone()];
+
+    let merged = merge(vec![a.clone(), b.clone()]).unwrap();
+    assert_eq!(merged.sources, vec![a.sources[0].clone(), a.sources[1].clone(), a.sources[2].clone(), "d/other.slp".to_string()]);
+    assert_eq!(merged.header.row_count as usize, 50);
+    assert_eq!(merged.header.source_count, 4);
+
+    let mut expected = with_paths(&a);
+    expected.extend(with_paths(&b));
+    expected.sort();
+    assert_eq!(with_paths(&merged), expected);
+
+    // rows without a source stay without one
+    let unsourced = |db: &Database| db.rows.iter().filter(|r| r.source == Row::NO_SOURCE).count();
+    assert_eq!(unsourced(&merged), unsourced(&a) + unsourced(&b));
+
+    // any sorted input sorts the result
+    let mut sorted_b = b.clone();
+    sort_database(&mut sorted_b);
+    let merged_sorted = merge(vec![a.clone(), sorted_b]).unwrap();
+    assert!(!merged_sorted.header.state_index.is_empty());
+    assert_eq!(with_paths(&merged_sorted), expected);
+    let queries = sample_queries(&a);
+    let sorted_results = merged_sorted.search(&queries).iter()
+        .map(|rows| rows.len())
+        .collect::<Vec<_>>();
+    let all_results = search(&merged.rows, &queries).iter().map(|rows| rows.len()).collect::<Vec<_>>();
+    assert_eq!(sorted_results, all_results);
+
+    let other = sample_database(Character::Marth, Character::Fox, 5);
+    assert_eq!(merge(vec![a, b, other]).unwrap_err(), 2);
+}
+
+#[test]
+fn split_by_stage_reads_sources() {
+    let (slp, _) = finished_replay(200);
+    let mut battlefield = slp.clone();
+    // stage in the game start block, after the raw header, the event sizes and the game start's command and version
+    let stage_offset = 15 + 2 + 3 * 5 + 5 + 0xE;
+    assert_eq!(&slp[stage_offset..stage_offset + 2], &32u16.to_be_bytes());
+    battlefield[stage_offset..stage_offset + 2].copy_from_slice(&31u16.to_be_bytes());
+
+    let dir = std::env::temp_dir().join(format!("slp_action_db_split_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fd_path = dir.join("fd.slp");
+    let bf_path = dir.join("bf.slp");
+    std::fs::write(&fd_path, &slp).unwrap();
+    std::fs::write(&bf_path, &battlefield).unwrap();
+
+    let mut db = sample_database(Character::Fox, Character::Fox, 30);
+    db.sources = vec![fd_path.to_string_lossy().into_owned(), bf_path.to_string_lossy().into_owned()];
+
+    let split = split_by_stage(&db).unwrap();
+    let keys = split.keys().cloned().collect::<Vec<_>>();
+    assert_eq!(keys.len(), 2);
+    for part in split.values() {
+        assert_eq!(part.sources.len(), 1);
+        assert!(part.rows.iter().all(|r| r.source == 0));
+    }
+    let kept: usize = split.values().map(|p| p.rows.len()).sum();
+    assert_eq!(kept, db.rows.iter().filter(|r| r.source != Row::NO_SOURCE).count());
+    let mut expected = with_paths(&Database { rows: db.rows.iter().filter(|r| r.source != Row::NO_SOURCE).cloned().collect(), ..db.clone() });
+    let mut got = split.values().flat_map(with_paths).collect::<Vec<_>>();
+    expected.sort();
+    got.sort();
+    assert_eq!(got, expected);
+
+    // offline ditto, so every sourced row is kept under Fox_Fox
+    let by_character = split_by_character(&db).unwrap();
+    assert_eq!(by_character.len(), 1);
+    let (key, part) = by_character.iter().next().unwrap();
+    assert_eq!(key, &format!("{:?}_{:?}", Character::Fox, Character::Fox));
+    assert_eq!(part.rows.len(), kept);
+
+    // sources that can't be read are errors, not dropped rows
+    let archive = dir.join("replays.zip");
+    std::fs::write(&archive, b"PK").unwrap();
+    let mut archived = db.clone();
+    archived.sources[1] = archive.join("Game.slp").to_string_lossy().into_owned();
+    assert!(matches!(split_by_stage(&archived), Err(SourceError::InArchive(p)) if p == archived.sources[1]));
+
+    let mut missing = db.clone();
+    missing.sources[0] = dir.join("missing.slp").to_string_lossy().into_owned();
+    assert!(matches!(split_by_character(&missing), Err(SourceError::Io(_, _))));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedup_drops_repeated_replays() {
+    let db = sample_database(Character::Fox, Character::Marth, 30);
+
+    // the same replay under a second path, the first path again, and a repeated row
+    let mut doubled = db.clone();
+    doubled.sources.push("elsewhere/game 1.slp".to_string());
+    doubled.sources.push(db.sources[1].clone());
+    let copies = db.rows.iter().filter(|r| r.source == 0).map(|r| Row { source: 3, ..r.clone() });
+    let same_path = db.rows.iter().filter(|r| r.source == 1).map(|r| Row { source: 4, ..r.clone() });
+    doubled.rows.extend(copies.chain(same_path).collect::<Vec<_>>());
+    let repeated = db.rows[0].clone();
+    doubled.rows.push(repeated);
+
+    let added = doubled.rows.len() - db.rows.len();
+    assert_eq!(dedup(&mut doubled), added);
+    assert_eq!(with_paths(&doubled), with_paths(&Database { sources: db.sources[..2].to_vec(), ..db.clone() }));
+    assert_eq!(doubled.header.row_count as usize, doubled.rows.len());
+    assert_eq!(doubled.header.source_count, 2);
+
+    // nothing left to remove
+    assert_eq!(dedup(&mut doubled), 0);
+}
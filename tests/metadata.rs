@@ -0,0 +1,134 @@
+mod common;
+
+use slp_action_db::parse_old_game::*;
+use slp_action_db::ubjson::{self, Value};
+use common::{finished_replay, push_metadata};
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+#[test]
+fn malformed_ubjson_is_none() {
+    // string, array and key lengths running past the end
+    assert_eq!(ubjson::parse_value(b"SU\x0Aabc"), None);
+    assert_eq!(ubjson::parse_value(b"[$U#l\x7F\xFF\xFF\xFFabc"), None);
+    assert_eq!(ubjson::parse_value(b"{U\x09lastFrame"), None);
+    assert_eq!(ubjson::parse_value(b"l\x00\x01"), None);
+    // negative lengths
+    assert_eq!(ubjson::parse_value(b"Si\xFFabc"), None);
+    assert_eq!(ubjson::parse_value(b"[#i\xFE"), None);
+    // unclosed containers and unknown markers
+    assert_eq!(ubjson::parse_value(b"[i\x01i\x02"), None);
+    assert_eq!(ubjson::parse_value(b"{U\x01ai\x01"), None);
+    assert_eq!(ubjson::parse_value(b"X"), None);
+    assert_eq!(ubjson::parse_value(b""), None);
+    // a type without a count
+    assert_eq!(ubjson::parse_value(b"[$Ui\x01]"), None);
+    assert_eq!(ubjson::parse_value(b"{$iU\x01ai\x01}"), None);
+
+    // nesting is cut off past 32 levels
+    let nested = |depth: usize| [vec![b'['; depth], vec![b']'; depth]].concat();
+    assert!(ubjson::parse_value(&nested(32)).is_some());
+    assert_eq!(ubjson::parse_value(&nested(33)), None);
+    assert_eq!(ubjson::parse_value(&nested(100_000)), None);
+}
+
+#[test]
+fn ubjson_round_trips() {
+    let value = Value::Object(vec![
+        ("startAt".to_string(), string("2020-07-12T18:31:45Z")),
+        ("lastFrame".to_string(), Value::Int(-70000)),
+        ("bytes".to_string(), Value::Bytes(vec![1, 2, 3])),
+        ("list".to_string(), Value::Array(vec![Value::Null, Value::Bool(true), Value::Float(0.5), Value::Int(300)])),
+    ]);
+    let mut bytes = Vec::new();
+    ubjson::write_value(&mut bytes, &value);
+    bytes.extend_from_slice(b"trailing");
+    assert_eq!(ubjson::parse_value(&bytes), Some((value, bytes.len() - 8)));
+}
+
+#[test]
+fn iso_8601_timestamps() {
+    let known = [
+        ("1970-01-01T00:00:00Z", 0),
+        ("2020-07-12T18:31:45Z", 1594578705),
+        ("2020-07-12T18:31:45.123Z", 1594578705),
+        ("2020-07-12T18:31:45", 1594578705),
+        ("2020-07-12T20:31:45+02:00", 1594578705),
+        ("2020-07-12T13:31:45-0500", 1594578705),
+        ("2000-02-29T12:00:00Z", 951825600),
+    ];
+    for (s, secs) in known {
+        assert_eq!(parse_iso_8601(s).map(|t| t.0), Some(secs), "{}", s);
+    }
+
+    for s in ["2020-13-01T00:00:00Z", "2020-07-12 18:31:45Z", "2020-07-12T18:31", "2020-07-12T18:31:45X", "1969-12-31T23:59:59Z", "２０２０-07-12T18:31:45Z"] {
+        assert!(parse_iso_8601(s).is_none(), "{}", s);
+    }
+}
+
+#[test]
+fn metadata_fills_in_game_info() {
+    let (slp, _) = finished_replay(300);
+    let mut with_metadata = slp.clone();
+    push_metadata(&mut with_metadata, &Value::Object(vec![
+        ("startAt".to_string(), string("2020-07-12T20:31:45.5+02:00")),
+        ("lastFrame".to_string(), Value::Int(100)),
+        ("playedOn".to_string(), string("network")),
+        ("consoleNick".to_string(), string("Wii")),
+        ("players".to_string(), Value::Object(vec![
+            ("1".to_string(), Value::Object(vec![
+                ("names".to_string(), Value::Object(vec![("netplay".to_string(), string("Beta")), ("code".to_string(), string("CD#456"))])),
+                ("characters".to_string(), Value::Object(vec![("1".to_string(), Value::Int(224))])),
+            ])),
+            ("9".to_string(), Value::Object(Vec::new())),
+        ])),
+    ]));
+
+    let game = parse_old_file_full(&with_metadata).unwrap();
+    assert_eq!(game.game.info.start_time.0, 1594578705);
+    assert_eq!(game.game.info.duration, (100 - FIRST_FRAME + 1) as u32);
+
+    let metadata = game.metadata.unwrap();
+    assert_eq!(metadata.start_at.as_deref(), Some("2020-07-12T20:31:45.5+02:00"));
+    assert_eq!(metadata.last_frame, Some(100));
+    assert_eq!(metadata.played_on.as_deref(), Some("network"));
+    assert_eq!(metadata.console_nick.as_deref(), Some("Wii"));
+    let player = metadata.players[1].as_ref().unwrap();
+    assert_eq!(player.netplay_name.as_deref(), Some("Beta"));
+    assert_eq!(player.netplay_code.as_deref(), Some("CD#456"));
+    assert_eq!(player.characters, [(1, 224)]);
+    assert!(metadata.players[0].is_none());
+}
+
+#[test]
+fn bad_metadata_is_ignored() {
+    let (slp, _) = finished_replay(300);
+
+    let mut truncated = slp.clone();
+    push_metadata(&mut truncated, &Value::Object(vec![("startAt".to_string(), string("2020-07-12T18:31:45Z"))]));
+    truncated.truncate(truncated.len() - 10);
+
+    let mut wrong_types = slp.clone();
+    push_metadata(&mut wrong_types, &Value::Object(vec![
+        ("startAt".to_string(), Value::Int(5)),
+        ("lastFrame".to_string(), string("100")),
+        ("players".to_string(), Value::Array(Vec::new())),
+    ]));
+
+    let mut not_an_object = slp.clone();
+    push_metadata(&mut not_an_object, &Value::Array(Vec::new()));
+
+    for (name, file) in [("none", &slp), ("truncated", &truncated), ("wrong types", &wrong_types), ("not an object", &not_an_object)] {
+        let game = parse_old_file_full(file).unwrap();
+        assert_eq!(game.game.info.start_time.0, 0, "{}", name);
+        assert_eq!(game.game.info.duration, 300, "{}", name);
+        assert_eq!(game.game.frame_count, 300, "{}", name);
+    }
+
+    assert!(parse_old_file_full(&wrong_types).unwrap().metadata.is_some());
+    assert!(parse_old_file_full(&truncated).unwrap().metadata.is_none());
+    assert!(parse_metadata(b"U\x08metadata").is_none());
+    assert!(parse_metadata(b"").is_none());
+}
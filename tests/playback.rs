@@ -0,0 +1,78 @@
+mod common;
+
+use slp_action_db::*;
+use slp_action_db::playback::*;
+use common::finished_replay;
+
+#[test]
+fn queue_resolves_sources_and_interaction_ends() {
+    let (slp, _) = finished_replay(1200);
+
+    let dir = std::env::temp_dir().join(format!("slp_action_db_playback_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let extract_dir = dir.join("extracted");
+    std::fs::create_dir_all(&extract_dir).unwrap();
+
+    let slp_path = dir.join("game.slp");
+    std::fs::write(&slp_path, &slp).unwrap();
+    let slpz_path = dir.join("game.slpz");
+    let mut compressor = slpz::Compressor::new(3).unwrap();
+    std::fs::write(&slpz_path, slpz::compress(&mut compressor, &slp).unwrap()).unwrap();
+
+    let archive_path = dir.join("replays.zip");
+    std::fs::write(&archive_path, b"PK").unwrap();
+
+    let sources = vec![
+        slp_path.to_string_lossy().into_owned(),
+        slpz_path.to_string_lossy().into_owned(),
+        archive_path.join("Game_20240101T000000.slp").to_string_lossy().into_owned(),
+    ];
+
+    let game = parse_old_game::parse_old_file_full(&slp).unwrap();
+    let mut rows = Vec::new();
+    build::push_game_rows(&mut rows, &game, 0, &build::BuildOptions::default()).unwrap();
+    assert!(!rows.is_empty());
+
+    let options = QueueOptions::default();
+    let from_slp = queue_entries(&rows, &sources, &options, &extract_dir).unwrap();
+    assert_eq!(from_slp.len(), rows.len());
+    for (entry, row) in from_slp.iter().zip(rows.iter()) {
+        assert_eq!(entry.path, sources[0]);
+        assert_eq!(entry.start_frame, (row.frame - options.lead_in).max(parse_old_game::FIRST_FRAME));
+        let end = interaction_end_frame(&game.game, row).unwrap();
+        assert!(end > row.frame);
+        assert_eq!(entry.end_frame, end + options.lead_out);
+    }
+
+    // the same rows pointing at the compressed copy play the decompressed file over the same frames
+    let slpz_rows = rows.iter().map(|row| Row { source: 1, ..row.clone() }).collect::<Vec<_>>();
+    let from_slpz = queue_entries(&slpz_rows, &sources, &options, &extract_dir).unwrap();
+    let extracted = &from_slpz[0].path;
+    assert!(extracted.ends_with(".slp"));
+    assert!(std::path::Path::new(extracted).starts_with(&extract_dir));
+    assert_eq!(std::fs::read(extracted).unwrap(), slp);
+    for (a, b) in from_slpz.iter().zip(from_slp.iter()) {
+        assert_eq!(a.path, *extracted);
+        assert_eq!((a.start_frame, a.end_frame), (b.start_frame, b.end_frame));
+    }
+
+    // archive entries can't be opened by Dolphin
+    let archived = Row { source: 2, ..rows[0].clone() };
+    assert!(matches!(
+        queue_entries(&[archived], &sources, &options, &extract_dir),
+        Err(PlaybackError::UnsupportedSource(s)) if s == sources[2]
+    ));
+
+    // a row that isn't from the source replay
+    let wrong = Row { frame: rows[0].frame + 1, ..rows[0].clone() };
+    assert!(matches!(
+        queue_entries(&[wrong], &sources, &options, &extract_dir),
+        Err(PlaybackError::MissingInteraction(_, _))
+    ));
+
+    // rows without a source are skipped
+    let unsourced = Row { source: Row::NO_SOURCE, ..rows[0].clone() };
+    assert!(queue_entries(&[unsourced], &sources, &options, &extract_dir).unwrap().is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use slp_action_db::*;
+use slp_action_db::player::*;
+use slp_parser::{BroadState, Character, HighLevelAction};
+use common::{finished_replay, CONNECT_CODES};
+
+#[test]
+fn connect_codes_convert_both_ways() {
+    let code = connect_code_from_str("ab#123").unwrap();
+    assert_eq!(&code, b"AB\x81\x94123\0\0\0");
+    assert_eq!(connect_code_to_string(&code), "AB#123");
+
+    assert_eq!(connect_code_from_str("ABCDEFGH#1"), None);
+    assert_eq!(connect_code_from_str("AB-123"), None);
+    assert_eq!(connect_code_from_str("ABCD#123").map(|c| connect_code_to_string(&c)).as_deref(), Some("ABCD#123"));
+}
+
+#[test]
+fn player_option_keeps_only_their_rows() {
+    let game = parse_old_game::parse_old_file_full(&finished_replay(1200).0).unwrap();
+    let mut all = Vec::new();
+    build::push_game_rows(&mut all, &game, 0, &build::BuildOptions::default()).unwrap();
+
+    let code = connect_code_from_str("CD#456").unwrap();
+    assert_eq!(&code[..CONNECT_CODES[1].len()], CONNECT_CODES[1]);
+    assert_eq!(game.game.info.connect_codes[1], code);
+
+    let options = build::BuildOptions { player: Some(code), ..Default::default() };
+    let mut filtered = Vec::new();
+    build::push_game_rows(&mut filtered, &game, 0, &options).unwrap();
+
+    assert!(!filtered.is_empty());
+    assert!(filtered.len() < all.len());
+    assert!(filtered.iter().all(|row| row.player_code == code));
+    assert_eq!(format!("{:?}", filtered), format!("{:?}", player_rows(&all, &code)));
+
+    let options = build::BuildOptions { player: Some(connect_code_from_str("EF#789").unwrap()), ..Default::default() };
+    let mut none = Vec::new();
+    build::push_game_rows(&mut none, &game, 0, &options).unwrap();
+    assert!(none.is_empty());
+}
+
+#[test]
+fn compare_player_against_population() {
+    let state = (0..=u16::MAX).find_map(|n| BroadState::from_u16(Character::Fox, n)).unwrap();
+    let mut actions = (0..=u16::MAX).filter_map(|n| HighLevelAction::from_u16(Character::Fox, n));
+    let (a, b) = (actions.next().unwrap(), actions.next().unwrap());
+    let scout = connect_code_from_str("AB#123").unwrap();
+    let other = connect_code_from_str("CD#456").unwrap();
+
+    let row = |code: ConnectCode, action: HighLevelAction, score: f32| Row {
+        player_response: Situation { start_state: state, action_taken: action, pos_x: 0.0, pos_y: 0.0 },
+        opponent_initiation: Situation { start_state: state, action_taken: a, pos_x: 0.0, pos_y: 0.0 },
+        score,
+        weight: 1.0,
+        player_code: code,
+        source: Row::NO_SOURCE,
+        frame: 0,
+    };
+    let rows = [
+        row(scout, a, 1.0), row(scout, a, 3.0), row(scout, b, 5.0),
+        row(other, b, 2.0), row(other, b, 4.0), row(other, b, 6.0), row(other, a, 8.0),
+    ];
+    let query = SearchQuery {
+        player_response: SearchSituation { start_state: state, pos_x: 0.0, pos_y: 0.0 },
+        opponent_initiation: SearchSituation { start_state: state, pos_x: 0.0, pos_y: 0.0 },
+    };
+
+    let comparisons = compare_player(&rows, &scout, &[query]);
+    assert_eq!(comparisons.len(), 1);
+    let c = &comparisons[0];
+
+    // the scout favours a, so it comes first
+    assert_eq!(c.len(), 2);
+    assert_eq!(c[0].action, a);
+    assert_eq!((c[0].player_count, c[0].player_frequency, c[0].player_mean_score), (2, 2.0 / 3.0, 2.0));
+    assert_eq!((c[0].population_count, c[0].population_frequency, c[0].population_mean_score), (1, 0.25, 8.0));
+    assert_eq!(c[1].action, b);
+    assert_eq!((c[1].player_count, c[1].player_frequency, c[1].player_mean_score), (1, 1.0 / 3.0, 5.0));
+    assert_eq!((c[1].population_count, c[1].population_frequency, c[1].population_mean_score), (3, 0.75, 4.0));
+}
@@ -0,0 +1,63 @@
+mod common;
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::replay_query::*;
+use slp_parser::Character;
+use common::finished_replay;
+
+fn database(player: Character, opponent: Character, rows: Vec<Row>) -> Database {
+    let mut db = sample::database(player, opponent, rows, vec!["game.slp".to_string()]);
+    sort_database(&mut db);
+    db
+}
+
+#[test]
+fn replay_interactions_find_their_own_rows() {
+    let (slp, _) = finished_replay(1200);
+
+    let game = parse_old_game::parse_old_file_full(&slp).unwrap();
+    let mut rows = Vec::new();
+    build::push_game_rows(&mut rows, &game, 0, &build::BuildOptions::default()).unwrap();
+    assert!(!rows.is_empty());
+    let db = database(Character::Fox, Character::Fox, rows);
+
+    let all = query_game(&db, &game.game, FrameSelection::All).unwrap();
+    assert!(all.len() >= db.rows.len());
+    assert!(all.windows(2).all(|w| (w[0].frame, w[0].port) <= (w[1].frame, w[1].port)));
+
+    // every row the game made is found by the interaction it came from, at its own position
+    for row in &db.rows {
+        let row_text = format!("{:?}", row);
+        let found = all.iter().any(|m| {
+            m.frame == row.frame
+                && m.query.player_response.pos_x == row.player_response.pos_x
+                && m.query.opponent_initiation.pos_x == row.opponent_initiation.pos_x
+                && m.rows.iter().any(|r| format!("{:?}", r) == row_text)
+        });
+        assert!(found, "no interaction found {:?}", row);
+    }
+
+    // each port's last interaction at or before the frame
+    let first_frame = all[0].frame;
+    let last_frame = all[all.len() - 1].frame;
+    for frame in [first_frame - 1, first_frame, (first_frame + last_frame) / 2, last_frame, last_frame + 100] {
+        let selected = query_game(&db, &game.game, FrameSelection::Frame(frame)).unwrap();
+
+        let mut expected = Vec::new();
+        for port in [0, 1] {
+            let last = all.iter().filter(|m| m.port == port && m.frame <= frame).map(|m| m.frame).max();
+            expected.extend(last.map(|f| (f, port)));
+        }
+        expected.sort();
+
+        let selected = selected.iter().map(|m| (m.frame, m.port)).collect::<Vec<_>>();
+        assert_eq!(selected, expected, "frame {}", frame);
+    }
+
+    let marth = database(Character::Marth, Character::Fox, Vec::new());
+    assert!(matches!(
+        query_game(&marth, &game.game, FrameSelection::All),
+        Err(ReplayQueryError::WrongMatchup(Character::Fox, Character::Fox)),
+    ));
+}
@@ -0,0 +1,120 @@
+// each test crate uses only some of these
+#![allow(dead_code)]
+
+use slp_action_db::*;
+use slp_parser::{BroadState, Character, HighLevelAction};
+
+/// `count` unsorted rows spread over a few start state pairs and responses, with varied positions,
+/// scores, weights, connect codes and frames. Every third row has no source.
+pub fn sample_database(player: Character, opponent: Character, count: usize) -> Database {
+    let states = |c: Character| (0..=u16::MAX).filter_map(|n| BroadState::from_u16(c, n)).take(3).collect::<Vec<_>>();
+    let actions = |c: Character| (0..=u16::MAX).filter_map(|n| HighLevelAction::from_u16(c, n)).take(3).collect::<Vec<_>>();
+    let (pl_states, op_states) = (states(player), states(opponent));
+    let (pl_actions, op_actions) = (actions(player), actions(opponent));
+
+    let sources = vec!["a/game 1.slp".to_string(), "b/game\t2.slpz".to_string(), "c/\u{e9}.slp".to_string()];
+    let rows = (0..count)
+        .map(|i| Row {
+            player_response: Situation {
+                start_state: pl_states[i % pl_states.len()],
+                action_taken: pl_actions[i / 2 % pl_actions.len()],
+                pos_x: (i % 7) as f32 * 3.5 - 10.0,
+                pos_y: (i % 5) as f32 * 0.25,
+            },
+            opponent_initiation: Situation {
+                start_state: op_states[i / 3 % op_states.len()],
+                action_taken: op_actions[i % op_actions.len()],
+                pos_x: (i % 11) as f32 - 5.0,
+                pos_y: (i % 3) as f32 * 1.5,
+            },
+            score: i as f32 * 0.75 - 20.0,
+            weight: if i % 4 == 0 { 0.5 } else { 1.0 },
+            player_code: if i % 2 == 0 { *b"AB#123\0\0\0\0" } else { [0; 10] },
+            source: if i % 3 == 2 { Row::NO_SOURCE } else { (i % 2) as u32 },
+            frame: i as i32 * 13 - 123,
+        })
+        .collect::<Vec<_>>();
+
+    database(player, opponent, rows, sources)
+}
+
+/// An unsorted database of the current version with these rows and sources.
+pub fn database(player: Character, opponent: Character, rows: Vec<Row>, sources: Vec<String>) -> Database {
+    Database {
+        header: Header {
+            version: VERSION,
+            player_character: player,
+            opponent_character: opponent,
+            row_count: rows.len() as u32,
+            source_count: sources.len() as u32,
+            layout: Layout::Rows,
+            state_index: Vec::new(),
+            checksum: 0,
+        },
+        rows,
+        sources,
+    }
+}
+
+/// Queries around every third row, some exactly on it and some off by up to `SEARCH_DISTANCE`,
+/// and one for a start state pair no row has.
+pub fn sample_queries(db: &Database) -> Vec<SearchQuery> {
+    let situation = |s: &Situation, offset: f32| SearchSituation { start_state: s.start_state, pos_x: s.pos_x + offset, pos_y: s.pos_y };
+    let mut queries = db.rows.iter()
+        .step_by(3)
+        .enumerate()
+        .map(|(i, row)| {
+            let offset = (i % 4) as f32 * 0.75;
+            SearchQuery {
+                player_response: situation(&row.player_response, offset),
+                opponent_initiation: situation(&row.opponent_initiation, -offset),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let missing = (0..=u16::MAX).rev().find_map(|n| slp_parser::BroadState::from_u16(db.header.player_character, n)).unwrap();
+    queries.push(SearchQuery {
+        player_response: SearchSituation { start_state: missing, pos_x: 0.0, pos_y: 0.0 },
+        opponent_initiation: SearchSituation { start_state: missing, pos_x: 0.0, pos_y: 0.0 },
+    });
+    queries
+}
+
+/// The database written in a past format version, built from the current row layout,
+/// which only ever appended fields.
+pub fn write_version(db: &Database, version: u32) -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(&version.to_le_bytes());
+    file.push(db.header.player_character.to_u8_internal());
+    file.push(db.header.opponent_character.to_u8_internal());
+    file.push((version >= 4 && db.header.layout == Layout::Columnar) as u8);
+    let has_index = version >= 5 && !db.header.state_index.is_empty();
+    file.push(has_index as u8);
+    if version >= 3 {
+        file.extend_from_slice(&(db.rows.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(db.sources.len() as u32).to_le_bytes());
+    }
+    if has_index {
+        file.extend_from_slice(&(db.header.state_index.len() as u32).to_le_bytes());
+        for (pair, row) in db.header.state_index.iter() {
+            file.extend_from_slice(&pair.to_le_bytes());
+            file.extend_from_slice(&row.to_le_bytes());
+        }
+    }
+
+    for row in db.rows.iter() {
+        let mut current = Vec::new();
+        write_row(&mut current, row);
+        match version {
+            0 => file.extend_from_slice(&current[..28]),
+            1 => { file.extend_from_slice(&current[..28]); file.extend_from_slice(&current[32..42]); }
+            2 => file.extend_from_slice(&current[..42]),
+            _ => file.extend_from_slice(&current),
+        }
+    }
+
+    if version >= 3 {
+        for source in db.sources.iter() { write_source(&mut file, source); }
+    }
+    file
+}
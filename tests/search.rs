@@ -0,0 +1,58 @@
+mod sample;
+
+use slp_action_db::*;
+use slp_parser::Character;
+use sample::{sample_database, sample_queries};
+
+/// Results with each query's rows in a fixed order, for comparing searches that visit rows in different orders.
+fn normalized(results: Vec<Vec<Row>>) -> Vec<Vec<String>> {
+    results.into_iter()
+        .map(|rows| {
+            let mut rows = rows.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>();
+            rows.sort();
+            rows
+        })
+        .collect()
+}
+
+#[test]
+fn sorted_search_matches_search() {
+    let unsorted = sample_database(Character::Fox, Character::Marth, 2000);
+    let queries = sample_queries(&unsorted);
+    let expected = search(&unsorted.rows, &queries);
+    assert!(expected.iter().any(|rows| rows.len() > 1));
+    assert!(expected.last().unwrap().is_empty());
+
+    let mut db = unsorted.clone();
+    sort_database(&mut db);
+    assert!(db.header.state_index.len() > 1);
+
+    // same rows as searching every row, in the sorted order
+    let sorted_expected = search(&db.rows, &queries);
+    assert_eq!(format!("{:?}", db.search(&queries)), format!("{:?}", sorted_expected));
+    assert_eq!(normalized(sorted_expected), normalized(expected));
+
+    let mut file = Vec::new();
+    write_database(&mut file, &db);
+    assert_eq!(file[7], 1);
+    assert_eq!(format!("{:?}", search_file(&file, &queries).unwrap()), format!("{:?}", db.search(&queries)));
+
+    let read = read_database(&file).unwrap();
+    assert_eq!(read.header.state_index, db.header.state_index);
+    assert_eq!(format!("{:?}", read.search(&queries)), format!("{:?}", db.search(&queries)));
+}
+
+#[test]
+fn state_range_covers_each_pair() {
+    let mut db = sample_database(Character::Fox, Character::Marth, 300);
+    sort_database(&mut db);
+
+    for (i, row) in db.rows.iter().enumerate() {
+        let range = db.header.state_range(row.opponent_initiation.start_state, row.player_response.start_state).unwrap();
+        assert!(range.contains(&i));
+        for other in db.rows[range].iter() {
+            assert_eq!(other.opponent_initiation.start_state, row.opponent_initiation.start_state);
+            assert_eq!(other.player_response.start_state, row.player_response.start_state);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+#![cfg(feature = "serde")]
+
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::names::*;
+use slp_parser::{BroadState, Character, HighLevelAction};
+use sample::{sample_database, sample_queries};
+
+fn characters() -> Vec<Character> {
+    (0..=u8::MAX).filter_map(Character::from_u8_internal).collect()
+}
+
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+#[test]
+fn rows_headers_and_queries_round_trip_across_characters() {
+    let characters = characters();
+    for (i, &player) in characters.iter().enumerate() {
+        for opponent in [player, characters[(i + 1) % characters.len()]] {
+            let mut db = sample_database(player, opponent, 24);
+            sort_database(&mut db);
+
+            assert_eq!(format!("{:?}", round_trip(&db.rows)), format!("{:?}", db.rows), "{:?} {:?}", player, opponent);
+            assert_eq!(format!("{:?}", round_trip(&db.header)), format!("{:?}", db.header));
+            let queries = sample_queries(&db);
+            assert_eq!(format!("{:?}", round_trip(&queries)), format!("{:?}", queries));
+        }
+    }
+}
+
+#[test]
+fn names_are_written_not_codes() {
+    let db = sample_database(Character::Fox, Character::Marth, 1);
+    let json = serde_json::to_value(&db.rows[0]).unwrap();
+    assert_eq!(json["player_response"]["start_state"], state_name(db.rows[0].player_response.start_state));
+    assert_eq!(json["opponent_initiation"]["action_taken"], action_name(db.rows[0].opponent_initiation.action_taken));
+
+    let mut header = serde_json::to_value(&db.header).unwrap();
+    assert_eq!(header["player_character"], "Fox");
+    header["player_character"] = "Nobody".into();
+    assert!(serde_json::from_value::<Header>(header).is_err());
+}
+
+/// Names read without a character never give a value some character means something else by.
+#[test]
+fn names_without_a_character_are_never_misread() {
+    let characters = characters();
+    let tables = characters.iter().map(|&c| NameTable::new(c)).collect::<Vec<_>>();
+
+    for &character in characters.iter() {
+        for n in 0..=u16::MAX {
+            if let Some(state) = BroadState::from_u16(character, n) {
+                let name = state_name(state);
+                match parse_any_state(&name) {
+                    Ok(read) => assert_eq!(read, state),
+                    Err(e) => {
+                        assert_eq!(e, NameError::Ambiguous);
+                        assert!(tables.iter().any(|t| t.state(&name).is_some_and(|s| s != state)));
+                    }
+                }
+            }
+            if let Some(action) = HighLevelAction::from_u16(character, n) {
+                let name = action_name(action);
+                match parse_any_action(&name) {
+                    Ok(read) => assert_eq!(read, action),
+                    Err(e) => {
+                        assert_eq!(e, NameError::Ambiguous);
+                        assert!(tables.iter().any(|t| t.action(&name).is_some_and(|a| a != action)));
+                    }
+                }
+            }
+        }
+    }
+
+    assert_eq!(parse_any_state("NotAState"), Err(NameError::Unknown));
+    assert_eq!(parse_any_action("NotAnAction"), Err(NameError::Unknown));
+}
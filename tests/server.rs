@@ -0,0 +1,121 @@
+#![cfg(feature = "server")]
+
+mod sample;
+
+use std::io::{Read, Write};
+use slp_action_db::*;
+use slp_parser::{BroadState, Character, HighLevelAction};
+
+/// Twenty rows along the x axis in one start state pair, alternating between two responses.
+fn database(player: Character, opponent: Character) -> Database {
+    let state = (0..=u16::MAX).find_map(|n| BroadState::from_u16(player, n)).unwrap();
+    let mut actions = (0..=u16::MAX).filter_map(|n| HighLevelAction::from_u16(player, n));
+    let actions = [actions.next().unwrap(), actions.next().unwrap()];
+
+    let rows = (0..20)
+        .map(|i| Row {
+            player_response: Situation { start_state: state, action_taken: actions[i % 2], pos_x: i as f32, pos_y: 0.0 },
+            opponent_initiation: Situation { start_state: state, action_taken: actions[0], pos_x: 0.0, pos_y: 0.0 },
+            score: i as f32,
+            weight: 1.0,
+            player_code: [0; 10],
+            source: 0,
+            frame: i as i32,
+        })
+        .collect::<Vec<_>>();
+
+    let mut db = sample::database(player, opponent, rows, vec!["game.slp".to_string()]);
+    sort_database(&mut db);
+    db
+}
+
+/// One request per connection, as the simplest client would do it. Returns the whole response.
+fn raw_request(addr: std::net::SocketAddr, method: &str, path: &str, body: &str) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method, path, body.len(), body,
+    ).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn request(addr: std::net::SocketAddr, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+    let response = raw_request(addr, method, path, body);
+    let status = response[9..12].parse().unwrap();
+    let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn query(x: f32) -> String {
+    let state = names::state_name(database(Character::Fox, Character::Marth).rows[0].player_response.start_state);
+    format!(
+        r#"{{"player_response":{{"start_state":"{s}","pos_x":{x},"pos_y":0}},"opponent_initiation":{{"start_state":"{s}","pos_x":0,"pos_y":0}}}}"#,
+        s = state, x = x,
+    )
+}
+
+#[test]
+fn server_endpoints() {
+    let api = server::Api::new(vec![
+        database(Character::Fox, Character::Marth),
+        database(Character::Marth, Character::Fox),
+    ]).unwrap();
+    let http = server::bind("127.0.0.1:0").unwrap();
+    let addr = http.server_addr().to_ip().unwrap();
+    std::thread::spawn(move || server::serve(&http, &api));
+
+    let (status, health) = request(addr, "GET", "/health", "");
+    assert_eq!(status, 200);
+    assert_eq!(health["matchups"], 2);
+
+    let (status, metadata) = request(addr, "GET", "/metadata", "");
+    assert_eq!(status, 200);
+    let names = metadata["matchups"].as_array().unwrap().iter().map(|m| m["name"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(names, ["Fox_Marth", "Marth_Fox"]);
+    assert_eq!(metadata["matchups"][0]["row_count"], 20);
+
+    // within SEARCH_DISTANCE of x = 5
+    let (status, search) = request(addr, "POST", "/search", &format!(r#"{{"matchup":"Fox_Marth","queries":[{}]}}"#, query(5.0)));
+    assert_eq!(status, 200);
+    let hits = search["results"][0].as_array().unwrap();
+    assert_eq!(hits.len(), 5);
+    assert!(hits.iter().all(|h| h["source_path"] == "game.slp"));
+
+    let (status, nearest) = request(addr, "POST", "/nearest", &format!(r#"{{"matchup":"Fox_Marth","query":{},"k":3}}"#, query(5.2)));
+    assert_eq!(status, 200);
+    let xs = nearest["neighbours"].as_array().unwrap().iter()
+        .map(|n| n["row"]["player_response"]["pos_x"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(xs, [5.0, 6.0, 4.0]);
+
+    let (status, actions) = request(addr, "POST", "/actions", &format!(r#"{{"matchup":"Marth_Fox","query":{}}}"#, query(5.0)));
+    assert_eq!(status, 200);
+    assert_eq!(actions["row_count"], 5);
+    let counts = actions["actions"].as_array().unwrap().iter().map(|a| a["count"].as_u64().unwrap()).collect::<Vec<_>>();
+    assert_eq!(counts, [3, 2]);
+
+    // x = 3, 5, 7 respond with the first action, 4 and 6 with the second
+    let mean_scores = actions["actions"].as_array().unwrap().iter().map(|a| a["mean_score"].as_f64().unwrap()).collect::<Vec<_>>();
+    assert_eq!(mean_scores, [5.0, 5.0]);
+
+    assert_eq!(request(addr, "POST", "/search", &format!(r#"{{"queries":[{}]}}"#, query(5.0))).0, 400);
+    assert_eq!(request(addr, "POST", "/search", &format!(r#"{{"matchup":"Fox_Fox","queries":[{}]}}"#, query(5.0))).0, 404);
+    assert_eq!(request(addr, "POST", "/nearest", "{").0, 400);
+    assert_eq!(request(addr, "GET", "/search", "").0, 405);
+    assert_eq!(request(addr, "GET", "/missing", "").0, 404);
+
+    // a browser's CORS preflight before POSTing JSON
+    let preflight = raw_request(addr, "OPTIONS", "/search", "");
+    assert_eq!(&preflight[9..12], "204");
+    let (head, body) = preflight.split_once("\r\n\r\n").unwrap();
+    let head = head.to_ascii_lowercase();
+    assert!(head.contains("access-control-allow-origin: *"));
+    assert!(head.contains("access-control-allow-methods: get, post, options"));
+    assert!(head.contains("access-control-allow-headers: content-type"));
+    assert_eq!(body, "");
+    assert_eq!(request(addr, "OPTIONS", "/missing", "").0, 404);
+}
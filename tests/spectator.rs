@@ -0,0 +1,75 @@
+mod common;
+
+use slp_action_db::spectator::{self, Message, SpectatorClient};
+use common::finished_replay;
+use slp_action_db::parse_old_game::{parse_old_file_full, FullGame};
+
+/// The same game parsed from the whole file.
+fn parsed_whole(slp: &[u8]) -> FullGame {
+    parse_old_file_full(slp).unwrap()
+}
+
+fn assert_same_frames(a: &FullGame, b: &FullGame) {
+    assert_eq!(a.game.frame_count, b.game.frame_count);
+    for port in 0..4 {
+        assert_eq!(format!("{:?}", a.game.frames[port]), format!("{:?}", b.game.frames[port]));
+    }
+    assert_eq!(format!("{:?}", a.game_end), format!("{:?}", b.game_end));
+}
+
+/// Accepts one client and replays `games` over the console protocol in small pieces.
+fn mock_server(games: Vec<Vec<u8>>) -> (std::net::SocketAddr, std::thread::JoinHandle<()>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let handshake = spectator::read_message(&mut stream).unwrap().unwrap();
+        assert_eq!(handshake.get("type").and_then(|t| t.as_int()), Some(spectator::MESSAGE_HANDSHAKE));
+
+        let reply = Message::Handshake { nick: Some("mock".to_string()), version: Some("1.9.0".to_string()), pos: 0 };
+        spectator::write_message(&mut stream, &reply.to_value()).unwrap();
+
+        let mut pos = 0u64;
+        for game in games {
+            // the console sends events without the replay header
+            for data in game[15..].chunks(500) {
+                let next_pos = pos + data.len() as u64;
+                let message = Message::Replay { pos, next_pos, data: data.to_vec() };
+                spectator::write_message(&mut stream, &message.to_value()).unwrap();
+
+                // resent messages are ignored by the client
+                if pos == 0 { spectator::write_message(&mut stream, &message.to_value()).unwrap(); }
+                pos = next_pos;
+            }
+            spectator::write_message(&mut stream, &Message::KeepAlive.to_value()).unwrap();
+        }
+    });
+
+    (addr, handle)
+}
+
+#[test]
+fn spectator_mock_server() {
+    let (first, _) = finished_replay(300);
+    let (second, _) = finished_replay(200);
+    let (addr, server) = mock_server(vec![first.clone(), second.clone()]);
+
+    let mut client = SpectatorClient::connect(addr).unwrap();
+    assert_eq!(client.nick(), Some("mock"));
+
+    let game = client.next_game().unwrap().expect("first game");
+    assert!(game.game_end.is_some());
+    assert!(game.metadata.is_none());
+    assert_eq!(game.game.frames[0].as_ref().unwrap().len(), 300);
+    assert_same_frames(&game, &parsed_whole(&first));
+
+    let game = client.next_game().unwrap().expect("second game");
+    assert!(game.game_end.is_some());
+    assert_eq!(game.game.frames[1].as_ref().unwrap().len(), 200);
+    assert_same_frames(&game, &parsed_whole(&second));
+
+    assert!(client.next_game().unwrap().is_none());
+    server.join().unwrap();
+}
@@ -0,0 +1,151 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::sqlite::*;
+use slp_parser::Character;
+use common::finished_replay;
+use sample::{sample_database, sample_queries};
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("slp_action_db_{}_{}.sqlite", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Sample rows plus rows on and just past the search distance, and rows with positions that aren't finite.
+fn edge_database() -> Database {
+    let mut db = sample_database(Character::Fox, Character::Marth, 60);
+    let base = db.rows[0].clone();
+    let beyond = f32::from_bits(SEARCH_DISTANCE.to_bits() + 1);
+    let shifted = |dx: f32, dy: f32| {
+        let mut row = base.clone();
+        row.opponent_initiation.pos_x += dx;
+        row.player_response.pos_y += dy;
+        row
+    };
+
+    db.rows.extend([
+        shifted(SEARCH_DISTANCE, 0.0),
+        shifted(beyond, 0.0),
+        shifted(0.0, -SEARCH_DISTANCE),
+        shifted(0.0, -beyond),
+        shifted(f32::NAN, 0.0),
+        shifted(0.0, f32::INFINITY),
+        shifted(f32::NEG_INFINITY, 0.0),
+        Row { score: f32::NAN, weight: f32::INFINITY, ..base.clone() },
+    ]);
+    db.header.row_count = db.rows.len() as u32;
+    db
+}
+
+fn edge_queries(db: &Database) -> Vec<SearchQuery> {
+    let mut queries = sample_queries(db);
+    let base = SearchQuery {
+        player_response: SearchSituation {
+            start_state: db.rows[0].player_response.start_state,
+            pos_x: db.rows[0].player_response.pos_x,
+            pos_y: db.rows[0].player_response.pos_y,
+        },
+        opponent_initiation: SearchSituation {
+            start_state: db.rows[0].opponent_initiation.start_state,
+            pos_x: db.rows[0].opponent_initiation.pos_x,
+            pos_y: db.rows[0].opponent_initiation.pos_y,
+        },
+    };
+    for x in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, f32::MAX, 1.0e30] {
+        let mut query = base.clone();
+        query.opponent_initiation.pos_x = x;
+        queries.push(query);
+    }
+    queries.push(base);
+    queries
+}
+
+#[test]
+fn sqlite_search_matches_search() {
+    let db = edge_database();
+    let queries = edge_queries(&db);
+    let expected = search(&db.rows, &queries);
+    assert!(expected.iter().any(|rows| rows.iter().any(|r| r.opponent_initiation.pos_x.is_nan())));
+
+    let path = temp_path("search");
+    let sqlite = SqliteDatabase::create(&path, &db).unwrap();
+    assert_eq!(format!("{:?}", sqlite.search(&queries).unwrap()), format!("{:?}", expected));
+
+    // the same after opening the file again
+    drop(sqlite);
+    let sqlite = SqliteDatabase::open(&path).unwrap();
+    assert_eq!(format!("{:?}", sqlite.search(&queries).unwrap()), format!("{:?}", expected));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sqlite_round_trip() {
+    let mut db = edge_database();
+    db.header.version = 3;
+    sort_database(&mut db);
+
+    let path = temp_path("round_trip");
+    SqliteDatabase::create(&path, &db).unwrap();
+    let read = SqliteDatabase::open(&path).unwrap().read_database().unwrap();
+    assert_eq!(read.header.version, VERSION);
+    assert_eq!(read.header.state_index, db.header.state_index);
+    assert_eq!(read.sources, db.sources);
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn game_context_from_sources() {
+    let (slp, _) = finished_replay(200);
+    let replay = std::env::temp_dir().join(format!("slp_action_db_sqlite_context_{}.slp", std::process::id()));
+    std::fs::write(&replay, &slp).unwrap();
+
+    let mut db = sample_database(Character::Fox, Character::Fox, 30);
+    db.sources = vec![replay.to_string_lossy().into_owned(), "missing/game.slp".to_string()];
+
+    // read from the replays
+    let path = temp_path("context");
+    let sqlite = SqliteDatabase::create(&path, &db).unwrap();
+    let stages = sqlite.connection()
+        .prepare("SELECT stage FROM sources ORDER BY id").unwrap()
+        .query_map([], |r| r.get::<_, Option<String>>(0)).unwrap()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(stages, vec![Some(format!("{:?}", slp_parser::Stage::FinalDestination)), None]);
+    let players: Vec<(u32, u32, String)> = sqlite.connection()
+        .prepare("SELECT source, port, character_name FROM players ORDER BY source, port").unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(players, vec![(0, 0, "Fox".to_string()), (0, 1, "Fox".to_string())]);
+
+    // offline games have no connect codes to find the responding player by
+    let matched: u32 = sqlite.connection()
+        .query_row("SELECT COUNT(*) FROM interactions WHERE player_port IS NOT NULL", [], |r| r.get(0)).unwrap();
+    assert_eq!(matched, 0);
+    drop(sqlite);
+    std::fs::remove_file(&path).unwrap();
+
+    // the same game played online, where the responding player is found by connect code
+    let mut game_start = merge::read_game_start(&db.sources[0]).unwrap();
+    game_start.connect_codes[1] = *b"AB#123\0\0\0\0";
+    let sqlite = SqliteDatabase::create_with_game_starts(&path, &db, &[Some(game_start), None]).unwrap();
+    let rows = sqlite.connection()
+        .prepare("SELECT id, player_port, player_game_character, stage FROM interactions ORDER BY id").unwrap()
+        .query_map([], |r| Ok((r.get::<_, usize>(0)?, r.get::<_, Option<u32>>(1)?, r.get::<_, Option<String>>(2)?, r.get::<_, Option<String>>(3)?))).unwrap()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows.len(), db.rows.len());
+    for (id, port, character, stage) in rows {
+        let row = &db.rows[id];
+        let online = row.source == 0 && row.player_code != [0; 10];
+        assert_eq!(port, online.then_some(1));
+        assert_eq!(character, online.then(|| "Fox".to_string()));
+        assert_eq!(stage.is_some(), row.source == 0);
+    }
+
+    drop(sqlite);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&replay).unwrap();
+}
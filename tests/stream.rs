@@ -0,0 +1,88 @@
+mod common;
+
+use slp_action_db::parse_old_game::*;
+use common::{finished_replay, synthetic_replay};
+
+/// Hands out a few bytes at a time, so events are split across reads.
+struct Trickle<'a>(&'a [u8]);
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.0.len()).min(7);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+fn assert_same_game(a: &FullGame, b: &FullGame) {
+    assert_eq!(a.game.frame_count, b.game.frame_count);
+    for port in 0..4 {
+        assert_eq!(format!("{:?}", a.game.frames[port]), format!("{:?}", b.game.frames[port]));
+        assert_eq!(format!("{:?}", a.game.follower_frames[port]), format!("{:?}", b.game.follower_frames[port]));
+    }
+    assert_eq!(format!("{:?}", a.game_end), format!("{:?}", b.game_end));
+    assert_eq!(format!("{:?}", a.frame_starts), format!("{:?}", b.frame_starts));
+    assert_eq!(a.rollback_frames, b.rollback_frames);
+    assert_eq!(a.gecko_codes, b.gecko_codes);
+}
+
+#[test]
+fn stream_matches_full_parse() {
+    let (slp, _) = finished_replay(300);
+    let full = parse_old_file_full(&slp).unwrap();
+    assert_eq!(full.game.frame_count, 300);
+    assert!(full.game_end.is_some());
+
+    assert_same_game(&parse_old_stream(&slp[..]).unwrap(), &full);
+    assert_same_game(&parse_old_stream(Trickle(&slp)).unwrap(), &full);
+
+    // frame by frame sees every frame once, in order
+    let mut parser = StreamParser::new(Trickle(&slp)).unwrap();
+    let mut frames = 0;
+    while let Some(frame) = parser.next_frame().unwrap() {
+        assert_eq!(frame.frame, FIRST_FRAME + frames);
+        assert_eq!(
+            format!("{:?}", frame.frames[0].unwrap()),
+            format!("{:?}", full.game.frames[0].as_ref().unwrap()[frames as usize]),
+        );
+        frames += 1;
+    }
+    assert_eq!(frames, 300);
+    assert!(parser.game_end().is_some());
+}
+
+#[test]
+fn stream_without_raw_length() {
+    // a game still being written ends at the end of the file
+    let (slp, frame_ends) = synthetic_replay(300);
+    let streamed = parse_old_stream(&slp[..]).unwrap();
+    assert_eq!(streamed.game.frame_count, 300);
+
+    let cut = parse_old_stream(&slp[..frame_ends[99]]).unwrap();
+    assert_eq!(cut.game.frame_count, 100);
+    assert!(cut.game_end.is_none());
+}
+
+#[test]
+fn frames_before_first_frame_are_errors() {
+    let (slp, frame_ends) = finished_replay(10);
+
+    // bookend of the first frame, before the first frame and too far past it to allocate
+    let bookend = frame_ends[0] - 9;
+    assert_eq!(slp[bookend], FRAME_BOOKEND);
+    for frame in [-200, MAX_FRAME + 1, i32::MAX] {
+        let mut bad_bookend = slp.clone();
+        bad_bookend[bookend + 1..bookend + 5].copy_from_slice(&frame.to_be_bytes());
+        assert!(parse_old_file_full(&bad_bookend).is_err(), "frame {}", frame);
+        assert!(parse_old_stream(&bad_bookend[..]).is_err(), "frame {}", frame);
+    }
+
+    // port of the first pre frame update
+    let mut bad_port = slp.clone();
+    let pre = frame_ends[0] - 9 - 2 * (0x3F + 1 + 0x34 + 1);
+    assert_eq!(bad_port[pre], PRE_FRAME_UPDATE);
+    bad_port[pre + 5] = 9;
+    assert!(parse_old_file_full(&bad_port).is_err());
+    assert!(parse_old_stream(&bad_port[..]).is_err());
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::io::Write;
+use slp_action_db::*;
+use slp_action_db::tail::TailParser;
+use slp_action_db::parse_old_game::parse_old_file_full;
+use common::{finished_replay, synthetic_replay};
+
+#[test]
+fn tail_growing_file() {
+    let (slp, frame_ends) = synthetic_replay(300);
+    let path = std::env::temp_dir().join(format!("slp_action_db_tail_{}.slp", std::process::id()));
+
+    let mut writer = std::fs::File::create(&path).unwrap();
+    let mut parser = TailParser::new(std::fs::File::open(&path).unwrap());
+
+    // nothing written yet
+    assert!(parser.poll().unwrap().is_empty());
+    assert!(parser.builder().is_none());
+
+    // header cut off part way through the game start
+    writer.write_all(&slp[..40]).unwrap();
+    parser.poll().unwrap();
+    assert!(parser.builder().is_none());
+
+    // write in chunks that split events, checking the frame count never runs ahead of the writer
+    let mut written = 40;
+    let mut last_frame_count = 0;
+    for chunk_end in (40..slp.len()).step_by(777).skip(1).chain(std::iter::once(slp.len())) {
+        writer.write_all(&slp[written..chunk_end]).unwrap();
+        writer.flush().unwrap();
+        written = chunk_end;
+
+        parser.poll().unwrap();
+        let builder = parser.builder().expect("game start was written");
+
+        let finished_frames = frame_ends.iter().filter(|&&end| end <= written).count();
+        assert_eq!(builder.frame_count(), finished_frames);
+        assert!(builder.frame_count() >= last_frame_count);
+        last_frame_count = builder.frame_count();
+    }
+
+    assert!(parser.is_finished());
+    assert_eq!(parser.builder().unwrap().frame_count(), 300);
+
+    // metadata written after the game end is ignored
+    writer.write_all(b"U\x08metadata{}}").unwrap();
+    assert!(parser.poll().unwrap().is_empty());
+
+    drop(writer);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn tail_rows_match_batch_build() {
+    // long enough for the parse window to move several times
+    let (slp, frame_ends) = synthetic_replay(3000);
+    let path = std::env::temp_dir().join(format!("slp_action_db_tail_rows_{}.slp", std::process::id()));
+
+    let mut writer = std::fs::File::create(&path).unwrap();
+    let mut parser = TailParser::new(std::fs::File::open(&path).unwrap());
+
+    let mut tailed = Vec::new();
+    let mut written = 0;
+    for chunk_end in frame_ends.iter().copied().step_by(37).chain(std::iter::once(slp.len())) {
+        writer.write_all(&slp[written..chunk_end]).unwrap();
+        writer.flush().unwrap();
+        written = chunk_end;
+        tailed.extend(parser.poll().unwrap());
+    }
+    assert!(parser.is_finished());
+
+    let (finished, _) = finished_replay(3000);
+    let game = parse_old_file_full(&finished).unwrap();
+    let mut batch = Vec::new();
+    build::push_game_rows(&mut batch, &game, Row::NO_SOURCE, &build::BuildOptions::default()).unwrap();
+
+    let sorted = |rows: &[Row]| {
+        let mut rows = rows.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>();
+        rows.sort();
+        rows
+    };
+    assert_eq!(sorted(&tailed), sorted(&batch));
+
+    drop(writer);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,125 @@
+mod sample;
+
+use slp_action_db::*;
+use slp_action_db::text::*;
+use slp_parser::Character;
+use sample::{sample_database, sample_queries};
+
+/// The sample rows with values that need escaping, quoting or special spelling in text.
+fn awkward_database() -> Database {
+    let mut db = sample_database(Character::Fox, Character::Marth, 40);
+    db.sources = vec!["a,b/\"quoted\" game.slp".to_string(), "tab\there/\u{e9}.slp".to_string(), " leading space.slp".to_string()];
+    db.rows[0].score = f32::NAN;
+    db.rows[1].player_response.pos_x = f32::INFINITY;
+    db.rows[2].opponent_initiation.pos_y = f32::NEG_INFINITY;
+    db.rows[3].weight = -0.0;
+    db.rows[4].score = 1.0e-40;
+    db.rows[5].player_code = *b"A,B\"C#1\\\0\0";
+    db.rows[6].player_code = [0x81, 0x40, b'#', 0xFF, 0x00, 0x7F, b'x', 0, 0, 0];
+    db.rows[7].player_code = [0xAB; 10];
+    db.rows[8].frame = i32::MIN;
+    db
+}
+
+fn assert_same(read: &Database, db: &Database) {
+    assert_eq!(read.header.version, VERSION);
+    assert_eq!(read.header.player_character, db.header.player_character);
+    assert_eq!(read.header.opponent_character, db.header.opponent_character);
+    assert_eq!(read.header.layout, db.header.layout);
+    assert_eq!(read.header.state_index, db.header.state_index);
+    assert_eq!(read.sources, db.sources);
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", db.rows));
+}
+
+#[test]
+fn csv_round_trip() {
+    let db = awkward_database();
+    let csv = write_csv(&db);
+    assert!(csv.contains("\\x81@#\\xFF"));
+    assert!(csv.contains("NaN") && csv.contains("-inf"));
+    assert_same(&read_csv(&csv).unwrap(), &db);
+
+    let mut sorted = db.clone();
+    sort_database(&mut sorted);
+    let read = read_csv(&write_csv(&sorted)).unwrap();
+    assert_same(&read, &sorted);
+    let queries = sample_queries(&sorted);
+    assert_eq!(format!("{:?}", read.search(&queries)), format!("{:?}", sorted.search(&queries)));
+}
+
+#[test]
+fn jsonl_round_trip() {
+    let db = awkward_database();
+    let jsonl = write_jsonl(&db);
+    assert!(jsonl.contains("\"NaN\"") && jsonl.contains("\"inf\""));
+    assert!(jsonl.contains("\"source\": null"));
+    assert_same(&read_jsonl(&jsonl).unwrap(), &db);
+
+    let mut sorted = db.clone();
+    sort_database(&mut sorted);
+    assert_same(&read_jsonl(&write_jsonl(&sorted)).unwrap(), &sorted);
+}
+
+#[test]
+fn numeric_codes_read_as_names() {
+    let db = sample_database(Character::Fox, Character::Marth, 10);
+    let by_name = write_csv(&db);
+
+    // the same file with every state and action given by its code
+    let mut by_code = String::new();
+    for line in by_name.lines().take_while(|l| l.starts_with('#')) {
+        by_code.push_str(line);
+        by_code.push('\n');
+    }
+    by_code.push_str(&CSV_COLUMNS.join(","));
+    by_code.push('\n');
+    for row in db.rows.iter() {
+        let source = if row.source == Row::NO_SOURCE { String::new() } else { row.source.to_string() };
+        by_code.push_str(&format!(
+            "{},{},{:?},{:?},{},{},{:?},{:?},{:?},{:?},,{},{}\n",
+            row.opponent_initiation.start_state.as_u16(), row.opponent_initiation.action_taken.as_u16(),
+            row.opponent_initiation.pos_x, row.opponent_initiation.pos_y,
+            row.player_response.start_state.as_u16(), row.player_response.action_taken.as_u16(),
+            row.player_response.pos_x, row.player_response.pos_y,
+            row.score, row.weight, source, row.frame,
+        ));
+    }
+
+    let read = read_csv(&by_code).unwrap();
+    let expected = db.rows.iter().map(|r| Row { player_code: [0; 10], ..r.clone() }).collect::<Vec<_>>();
+    assert_eq!(format!("{:?}", read.rows), format!("{:?}", expected));
+
+    // and the same in json, with the codes as numbers
+    let mut jsonl = write_jsonl(&db);
+    for row in db.rows.iter() {
+        for situation in [&row.opponent_initiation, &row.player_response] {
+            jsonl = jsonl.replacen(
+                &format!("\"state\": \"{}\"", names::state_name(situation.start_state)),
+                &format!("\"state\": {}", situation.start_state.as_u16()),
+                1,
+            );
+        }
+    }
+    assert_same(&read_jsonl(&jsonl).unwrap(), &db);
+}
+
+#[test]
+fn bad_input_is_reported_by_line() {
+    let db = sample_database(Character::Fox, Character::Marth, 3);
+
+    let csv = write_csv(&db).replacen("\n", "\n# unknown key\n", 1);
+    assert!(read_csv(&csv).is_ok());
+
+    let mut lines = write_csv(&db).lines().map(|l| l.to_string()).collect::<Vec<_>>();
+    let last = lines.len() - 1;
+    lines[last] = lines[last].replacen(',', ",\"unclosed", 1);
+    let e = read_csv(&lines.join("\n")).unwrap_err();
+    assert_eq!(e, TextError { kind: TextErrorKind::BadField("quoting"), line: last + 1 });
+
+    let jsonl = write_jsonl(&db);
+    let broken = jsonl.replacen("\"frame\": ", "\"frame\": [", 1);
+    assert_eq!(read_jsonl(&broken).unwrap_err(), TextError { kind: TextErrorKind::BadJson, line: 2 });
+
+    let bad_source = jsonl.replacen("\"source\": 0", "\"source\": 7", 1);
+    assert_eq!(read_jsonl(&bad_source).unwrap_err().kind, TextErrorKind::BadSource(7));
+}
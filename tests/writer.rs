@@ -0,0 +1,49 @@
+mod common;
+
+use slp_action_db::parse_old_game::*;
+use slp_action_db::ubjson::Value;
+use slp_action_db::writer::{write_slp, RawReplay};
+use common::{finished_replay, push_metadata};
+
+/// The finished synthetic replay with a metadata block.
+fn replay_with_metadata(frame_count: i32) -> Vec<u8> {
+    let (mut slp, _) = finished_replay(frame_count);
+    push_metadata(&mut slp, &Value::Object(vec![
+        ("playedOn".to_string(), Value::String("dolphin".to_string())),
+        ("lastFrame".to_string(), Value::Int(frame_count as i64 - 124)),
+    ]));
+    slp
+}
+
+#[test]
+fn trimmed_replay_parses() {
+    let slp = replay_with_metadata(600);
+    let original = parse_old_file_full(&slp).unwrap();
+    assert_eq!(original.metadata.as_ref().unwrap().last_frame, Some(476));
+
+    let trimmed = write_slp(&RawReplay::parse(&slp).unwrap(), 100..=300).unwrap();
+    let trimmed = parse_old_file_full(&trimmed).unwrap();
+
+    // frames keep their numbers, the ones cut off are empty
+    let first = (100 - FIRST_FRAME) as usize;
+    let last = (300 - FIRST_FRAME) as usize;
+    assert_eq!(trimmed.game.frame_count, last + 1);
+    for port in 0..2 {
+        let original_frames = original.game.frames[port].as_ref().unwrap();
+        let trimmed_frames = trimmed.game.frames[port].as_ref().unwrap();
+        assert_eq!(format!("{:?}", &trimmed_frames[first..]), format!("{:?}", &original_frames[first..=last]));
+        assert!(trimmed_frames[..first].iter().all(|f| format!("{:?}", f) == format!("{:?}", slp_parser::Frame::NULL)));
+    }
+
+    assert!(trimmed.game_end.is_some());
+    let metadata = trimmed.metadata.unwrap();
+    assert_eq!(metadata.last_frame, Some(300));
+    assert_eq!(metadata.played_on.as_deref(), Some("dolphin"));
+}
+
+#[test]
+fn trim_of_whole_game_is_unchanged() {
+    let slp = replay_with_metadata(300);
+    let trimmed = write_slp(&RawReplay::parse(&slp).unwrap(), FIRST_FRAME..=1000).unwrap();
+    assert_eq!(trimmed, slp);
+}